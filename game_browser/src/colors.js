@@ -45,6 +45,8 @@ export default {
     controlPointMaster: [0.3, 0.3, 1.0],
     controlPointCurrentProposal: [0.0, 0.061, 1.0],//[0, 72, 255]
     controlPointHover: [1.0, 1.0, 1.0],
+    controlPointError: [1.0, 0.1, 0.0],
+    planWarning: [1.0, 0.6, 0.0],
 
     Residential: mix(toLinFloat([234, 203, 82]), grass, 0.9),
     Commercial: mix(toLinFloat([213, 94, 0]), grass, 0.9),
@@ -9,6 +9,7 @@ pub fn plan_grid(proposal_id: Serde<::planning::ProposalID>, n: usize, spacing:
     let world = &mut system.world();
 
     let plan_manager = ::planning::PlanManager::global_first(world);
+    let token = ::planning_browser::local_player_token();
 
     use ::transport::transport_planning::RoadIntent;
     use ::planning::{GestureID, GestureIntent};
@@ -24,9 +25,10 @@ pub fn plan_grid(proposal_id: Serde<::planning::ProposalID>, n: usize, spacing:
             id,
             GestureIntent::Road(RoadIntent::new(3, 3)),
             p1,
+            token,
             world,
         );
-        plan_manager.add_control_point(proposal_id.0, id, p2, true, true, world);
+        plan_manager.add_control_point(proposal_id.0, id, p2, true, true, token, world);
     }
 
     for y in 0..n {
@@ -39,9 +41,10 @@ pub fn plan_grid(proposal_id: Serde<::planning::ProposalID>, n: usize, spacing:
             id,
             GestureIntent::Road(RoadIntent::new(3, 3)),
             p1,
+            token,
             world,
         );
-        plan_manager.add_control_point(proposal_id.0, id, p2, true, true, world);
+        plan_manager.add_control_point(proposal_id.0, id, p2, true, true, token, world);
     }
 }
 
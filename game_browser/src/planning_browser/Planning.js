@@ -3,7 +3,7 @@ import renderOrder from '../renderOrder';
 import React from 'react';
 import { vec3, mat4 } from 'gl-matrix';
 import uuid from '../uuid';
-import { Button, Select } from 'antd';
+import { Button, Select, Input } from 'antd';
 const Option = Select.Option;
 
 import { solidColorShader } from 'monet';
@@ -29,6 +29,7 @@ export const initialState = {
             zoneGroups: new Map(LAND_USES.map(landUse => [landUse, new Map()])),
             zoneOutlineGroups: new Map(LAND_USES.map(landUse => [landUse, new Map()])),
             buildingOutlinesGroup: new Map(),
+            prototypesToDestructGroups: new Map(),
         }
     },
     master: {
@@ -38,6 +39,21 @@ export const initialState = {
     },
     currentProposal: null,
     hoveredControlPoint: {},
+    snappedPoint: null,
+    previewError: null,
+    previewWarnings: [],
+    confirmingImplement: false,
+    impactReport: null,
+    displacedHouseholds: [],
+    revertRefusal: null,
+    governance: { enabled: false, quorum: 1 },
+    // the ID of this browser's player, handed out by the server together with a secret token
+    player: null,
+    changeRefused: null,
+    reviewRequired: null,
+    merge: null,
+    pendingImport: null,
+    importError: null,
     canvasMode: {
         intent: null,
         currentGesture: null,
@@ -52,6 +68,34 @@ export const settingsSpec = {
             key: /Mac|iPod|iPhone|iPad/.test(navigator.platform) ? 'command+enter' : 'ctrl+enter'
         }, description: "Implement Plan"
     },
+    deleteGestureKey: {
+        default: {
+            key: 'backspace'
+        }, description: "Delete Hovered Gesture"
+    },
+    insertControlPointKey: {
+        default: {
+            key: 'i'
+        }, description: "Insert Control Point After Hovered One"
+    },
+    removeControlPointKey: {
+        default: {
+            key: 'del'
+        }, description: "Remove Hovered Control Point"
+    },
+    splitGestureKey: {
+        default: {
+            key: 'x'
+        }, description: "Split Road at Hovered Control Point"
+    },
+    joinGesturesKey: {
+        default: {
+            key: 'j'
+        }, description: "Join Road with Road Ending at Hovered Control Point"
+    },
+    snapRadius: { default: 3.0, description: "Snap to Existing Geometry Within", min: 0.0, max: 10.0, step: 0.5 },
+    snapGridSize: { default: 0.0, description: "Snap to Grid (0 = off)", min: 0.0, max: 50.0, step: 1.0 },
+    snapAngleDegrees: { default: 0.0, description: "Snap to Angles (0 = off)", min: 0.0, max: 90.0, step: 5.0 },
     finishGestureDistance: { default: 3.0, description: "Finish Gesture Double-Click Distance", min: 0.5, max: 10.0, step: 0.1 }
 }
 
@@ -141,6 +185,35 @@ function addControlPoint(proposalId, gestureId, point, addToEnd, doneAdding) {
     }
 }
 
+// the answer arrives asynchronously as planning.snappedPoint
+function requestSnap(state, candidate) {
+    const { currentGesture, previousClick } = state.planning.canvasMode;
+    const settings = state.settings.planning;
+
+    cbRustBrowser.snap_point(
+        state.planning.currentProposal,
+        [candidate[0], candidate[1]],
+        currentGesture && previousClick ? [previousClick[0], previousClick[1]] : null,
+        {
+            radius: settings.snapRadius,
+            grid_size: settings.snapGridSize > 0 ? settings.snapGridSize : null,
+            angle_increment: settings.snapAngleDegrees > 0 ? settings.snapAngleDegrees * Math.PI / 180 : null,
+            ignored_gesture: currentGesture || null
+        }
+    );
+}
+
+const SNAP_CANDIDATE_TOLERANCE = 0.5;
+
+function snappedOr(state, point) {
+    const snapped = state.planning.snappedPoint;
+    if (snapped && Math.hypot(snapped.candidate[0] - point[0], snapped.candidate[1] - point[1]) < SNAP_CANDIDATE_TOLERANCE) {
+        return [snapped.point[0], snapped.point[1], point[2]];
+    } else {
+        return point;
+    }
+}
+
 function finishGesture(proposalId, gestureId) {
     cbRustBrowser.finish_gesture();
 
@@ -153,8 +226,200 @@ function finishGesture(proposalId, gestureId) {
     });
 }
 
+function deleteHoveredGesture(oldState) {
+    const { gestureId } = oldState.planning.hoveredControlPoint;
+
+    if (oldState.planning.currentProposal && gestureId) {
+        cbRustBrowser.delete_gesture(oldState.planning.currentProposal, gestureId);
+
+        return update(oldState, {
+            planning: {
+                hoveredControlPoint: { $set: {} }
+            }
+        });
+    } else {
+        return oldState;
+    }
+}
+
+// the gestures of the master plan as changed by the current proposal
+function currentGestures(state) {
+    return Object.keys(state.planning.master.gestures).map(gestureId =>
+        ({ [gestureId]: Object.assign(state.planning.master.gestures[gestureId][0], { fromMaster: true }) })
+    ).concat(state.planning.currentProposal
+        ? state.planning.proposals[state.planning.currentProposal].undoable_history
+            .concat([state.planning.proposals[state.planning.currentProposal].ongoing || { gestures: [] }]).map(step => step.gestures)
+        : []
+    ).reduce((coll, gestures) => Object.assign(coll, gestures), {});
+}
+
+function editHoveredControlPoint(edit) {
+    return oldState => {
+        const { gestureId, pointIdx } = oldState.planning.hoveredControlPoint;
+        const proposalId = oldState.planning.currentProposal;
+
+        if (proposalId && gestureId) {
+            edit(oldState, proposalId, gestureId, pointIdx);
+
+            return update(oldState, {
+                planning: {
+                    hoveredControlPoint: { $set: {} }
+                }
+            });
+        } else {
+            return oldState;
+        }
+    }
+}
+
+const insertControlPointAfterHovered = editHoveredControlPoint((state, proposalId, gestureId, pointIdx) => {
+    const points = getGestureAsOf(state, proposalId, gestureId).points;
+    if (pointIdx + 1 < points.length) {
+        const [a, b] = [points[pointIdx], points[pointIdx + 1]];
+        cbRustBrowser.insert_control_point(proposalId, gestureId, pointIdx + 1, [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2]);
+    }
+});
+
+const removeHoveredControlPoint = editHoveredControlPoint((state, proposalId, gestureId, pointIdx) =>
+    cbRustBrowser.remove_control_point(proposalId, gestureId, pointIdx)
+);
+
+const splitGestureAtHovered = editHoveredControlPoint((state, proposalId, gestureId, pointIdx) =>
+    cbRustBrowser.split_gesture(proposalId, gestureId, pointIdx, uuid())
+);
+
+const JOIN_DISTANCE = 1.0;
+
+const joinGesturesAtHovered = editHoveredControlPoint((state, proposalId, gestureId, pointIdx) => {
+    const point = getGestureAsOf(state, proposalId, gestureId).points[pointIdx];
+    const gestures = currentGestures(state);
+    const otherGestureId = Object.keys(gestures).find(otherGestureId => {
+        const other = gestures[otherGestureId];
+        return otherGestureId != gestureId && !other.deleted && other.intent.Road
+            && [other.points[0], other.points[other.points.length - 1]].some(end =>
+                Math.hypot(end[0] - point[0], end[1] - point[1]) < JOIN_DISTANCE);
+    });
+
+    if (otherGestureId) {
+        cbRustBrowser.join_gestures(proposalId, gestureId, otherGestureId);
+    }
+});
+
+function requestImpactReport(oldState) {
+    if (!oldState.planning.currentProposal) {
+        return oldState;
+    }
+    cbRustBrowser.get_impact_report(oldState.planning.currentProposal);
+    return update(oldState, {
+        planning: {
+            confirmingImplement: { $set: true },
+            impactReport: { $set: null },
+            displacedHouseholds: { $set: [] },
+        }
+    });
+}
+
+function cancelImplement(oldState) {
+    return update(oldState, {
+        planning: {
+            confirmingImplement: { $set: false },
+        }
+    });
+}
+
 function implementProposal(oldState) {
     cbRustBrowser.implement_proposal(oldState.planning.currentProposal);
+    // with governance, the server only implements approved proposals,
+    // so we stay on the proposal until we know it was implemented
+    return update(oldState, {
+        planning: oldState.planning.governance.enabled ? {
+            confirmingImplement: { $set: false },
+        } : {
+            confirmingImplement: { $set: false },
+            $unset: ['currentProposal'],
+        }
+    });
+}
+
+function countVotes(metadata, approve) {
+    return metadata.votes.filter(vote => vote.approve == approve).length;
+}
+
+// with governance, only the author can change a proposal, players are told apart by
+// the player ID the server handed out, since all browsers share the same machine
+function isAuthor(state, metadata) {
+    return !!metadata.author && metadata.author == state.planning.player;
+}
+
+function mayChange(state, proposalId) {
+    const proposal = state.planning.proposals[proposalId];
+    return !state.planning.governance.enabled || !proposal || isAuthor(state, proposal.metadata);
+}
+
+function submitForReview(oldState) {
+    cbRustBrowser.submit_for_review(oldState.planning.currentProposal);
+    return update(oldState, {
+        planning: {
+            reviewRequired: { $set: null },
+            changeRefused: { $set: null },
+        }
+    });
+}
+
+function voteOnProposal(approve) {
+    return oldState => {
+        cbRustBrowser.vote_on_proposal(oldState.planning.currentProposal, approve);
+        return oldState;
+    };
+}
+
+function reviewControls(state, setState) {
+    const metadata = state.planning.proposals[state.planning.currentProposal].metadata;
+    const ownProposal = isAuthor(state, metadata);
+    const quorum = state.planning.governance.quorum;
+
+    return [
+        (metadata.status == "Draft" || metadata.status == "Rejected") && ownProposal &&
+        <Button type="primary"
+            disabled={!!state.planning.previewError}
+            onClick={() => setState(submitForReview)}
+        >{metadata.status == "Rejected" ? "Resubmit for Review" : "Submit for Review"}</Button>,
+        metadata.status == "UnderReview" && !ownProposal &&
+        <Button type="primary" size="small"
+            onClick={() => setState(voteOnProposal(true))}
+        >Approve</Button>,
+        metadata.status == "UnderReview" && !ownProposal &&
+        <Button size="small"
+            onClick={() => setState(voteOnProposal(false))}
+        >Reject</Button>,
+        <span className="review-status">
+            {metadata.status == "UnderReview"
+                ? `Under review: ${countVotes(metadata, true)}/${quorum} approvals, ${countVotes(metadata, false)}/${quorum} rejections`
+                : metadata.status}
+        </span>,
+        state.planning.reviewRequired == state.planning.currentProposal &&
+        <span className="plan-error" style={{ color: toCSS(fromLinFloat(colors.controlPointError)) }}>
+            Needs to be approved by {quorum} other player(s) first
+        </span>
+    ];
+}
+
+function proposalName(proposalId, proposal) {
+    return (proposal.metadata && proposal.metadata.name) || `Proposal '${proposalId.split("-")[0]}'`;
+}
+
+function renameProposal(proposalId, name) {
+    return oldState => {
+        const proposal = oldState.planning.proposals[proposalId];
+        if (proposal && proposal.metadata.name !== name) {
+            cbRustBrowser.rename_proposal(proposalId, name);
+        }
+        return oldState;
+    };
+}
+
+function deleteProposal(oldState) {
+    cbRustBrowser.delete_proposal(oldState.planning.currentProposal);
     return update(oldState, {
         planning: {
             $unset: ['currentProposal'],
@@ -162,6 +427,88 @@ function implementProposal(oldState) {
     });
 }
 
+function forkProposal(oldState) {
+    cbRustBrowser.fork_proposal(oldState.planning.currentProposal, uuid());
+    // the fork shows up in the proposal list with the next plans update
+    return oldState;
+}
+
+function requestMergeConflicts(fromProposalId) {
+    return oldState => {
+        cbRustBrowser.get_merge_conflicts(oldState.planning.currentProposal, fromProposalId);
+        return update(oldState, {
+            planning: {
+                merge: {
+                    $set: {
+                        into: oldState.planning.currentProposal,
+                        from: fromProposalId,
+                        conflicts: null,
+                        resolutions: {}
+                    }
+                }
+            }
+        });
+    };
+}
+
+function resolveMergeConflict(gestureId, resolution) {
+    return oldState => update(oldState, {
+        planning: { merge: { resolutions: { [gestureId]: { $set: resolution } } } }
+    });
+}
+
+function mergeProposals(oldState) {
+    const { into, from, resolutions } = oldState.planning.merge;
+    cbRustBrowser.merge_proposals(into, from, Object.entries(resolutions));
+    return update(oldState, {
+        planning: { $unset: ['merge'] }
+    });
+}
+
+function cancelMerge(oldState) {
+    return update(oldState, {
+        planning: { $unset: ['merge'] }
+    });
+}
+
+function revertLastProposal(oldState) {
+    cbRustBrowser.revert_proposal(null);
+    return update(oldState, {
+        planning: {
+            revertRefusal: { $set: null },
+            changeRefused: { $set: null },
+        }
+    });
+}
+
+function exportPlan(source) {
+    cbRustBrowser.export_plan(source);
+    return s => s;
+}
+
+function importProposal(file) {
+    let reader = new FileReader();
+    const proposalId = uuid();
+
+    reader.onload = () => {
+        try {
+            cbRustBrowser.import_proposal(proposalId, JSON.parse(reader.result));
+        } catch (e) {
+            console.error("Couldn't import plan from", file.name, e);
+        }
+    };
+    reader.readAsText(file);
+
+    // the new proposal shows up in the proposal list with the next plans update,
+    // unless the import is rejected
+    return oldState => update(oldState, {
+        planning: {
+            pendingImport: { $set: proposalId },
+            importError: { $set: null },
+        }
+    });
+}
+
 // INTERACTABLES AND RENDER LAYERS
 
 const destructedAsphaltInstance = new Float32Array([0.0, 0.0, 0.0, 1.0, 0.0, ...colors.destructedAsphalt]);
@@ -170,6 +517,14 @@ const plannedRoadMarkerInstance = new Float32Array([0.0, 0.0, 0.0, 1.0, 0.0, ...
 const landUseInstances = new Map(LAND_USES.map(landUse => [landUse, new Float32Array([0.0, 0.0, 0.0, 1.0, 0.0, ...colors[landUse]])]));
 const buildingOutlinesInstance = new Float32Array([0.0, 0.0, 0.0, 1.0, 0.0, ...colors.buildingOutlines]);
 
+const WARNING_DESCRIPTIONS = {
+    ShortLane: "Lane is too short",
+    DeadEnd: "Road ends without connecting to another road",
+    LotWithoutRoad: "Lot has no road access",
+    BuildingOnPavedArea: "Building stands on a road",
+    UnconnectedIncomingLane: "Lane leads into an intersection without a way out",
+};
+
 const stripedShaders = [
     "mod(p.x + p.y, 6.0) < 2.0 && mod(p.x - p.y, 6.0) > 2.0",
     "mod(p.x + p.y, 6.0) > 2.0 && mod(p.x + p.y, 6.0) < 4.0 && mod(p.x - p.y, 6.0) > 2.0",
@@ -202,19 +557,17 @@ export function render(state, setState) {
     const controlPointsInteractables = [];
 
     if (state.planning) {
-        let gestures = Object.keys(state.planning.master.gestures).map(gestureId =>
-            ({ [gestureId]: Object.assign(state.planning.master.gestures[gestureId][0], { fromMaster: true }) })
-        ).concat(state.planning.currentProposal
-            ? state.planning.proposals[state.planning.currentProposal].undoable_history
-                .concat([state.planning.proposals[state.planning.currentProposal].ongoing || { gestures: [] }]).map(step => step.gestures)
-            : []
-        ).reduce((coll, gestures) => Object.assign(coll, gestures), {});
+        let gestures = currentGestures(state);
 
         let { gestureId: hoveredGestureId, pointIdx: hoveredPointIdx } = state.planning.hoveredControlPoint;
 
         for (let gestureId of Object.keys(gestures)) {
             const gesture = gestures[gestureId];
 
+            if (gesture.deleted) {
+                continue;
+            }
+
             for (let [pointIdx, point] of gesture.points.entries()) {
 
                 let isRelevant = (gesture.intent.Road && state.planning.planningMode === "roads")
@@ -222,13 +575,17 @@ export function render(state, setState) {
 
                 if (isRelevant) {
                     let isHovered = gestureId == hoveredGestureId && pointIdx == hoveredPointIdx;
+                    let isBroken = state.planning.previewError
+                        && state.planning.previewError.gesture_ids.includes(gestureId);
 
                     controlPointsInstances.push.apply(controlPointsInstances, [
                         point[0], point[1], 0,
                         1.0, 0.0,
                         ...(isHovered
                             ? colors.controlPointHover
-                            : (gesture.fromMaster ? colors.controlPointMaster : colors.controlPointCurrentProposal))
+                            : isBroken
+                                ? colors.controlPointError
+                                : (gesture.fromMaster ? colors.controlPointMaster : colors.controlPointCurrentProposal))
                     ]);
 
                     controlPointsInteractables.push({
@@ -279,9 +636,28 @@ export function render(state, setState) {
         lanesToConstructMarkerGroups,
         lanesToConstructMarkerGapsGroups,
         zoneGroups, zoneOutlineGroups,
-        buildingOutlinesGroup } = state.planning.rendering.currentPreview;
+        buildingOutlinesGroup,
+        prototypesToDestructGroups } = state.planning.rendering.currentPreview;
+
+    const warningInstances = [];
+
+    for (let warning of state.planning.previewWarnings) {
+        warningInstances.push.apply(warningInstances, [
+            warning.location[0], warning.location[1], 0,
+            1.0, 0.0,
+            ...colors.planWarning
+        ]);
+    }
 
     const layers = [
+        {
+            renderOrder: renderOrder.deletedGestures,
+            decal: true,
+            batches: [...prototypesToDestructGroups.values()].map(groupMesh => ({
+                mesh: groupMesh,
+                instances: destructedAsphaltInstance
+            }))
+        },
         {
             renderOrder: renderOrder.addedGesturesAsphalt,
             decal: true,
@@ -345,6 +721,9 @@ export function render(state, setState) {
             batches: [{
                 mesh: state.planning.rendering.staticMeshes.GestureDot,
                 instances: new Float32Array(controlPointsInstances)
+            }, {
+                mesh: state.planning.rendering.staticMeshes.GestureDot,
+                instances: new Float32Array(warningInstances)
             }]
         }
     ];
@@ -363,12 +742,87 @@ export function render(state, setState) {
                 onChange={(value) => setState(switchToProposal(value))}
                 value={state.planning.currentProposal || undefined}
             >{Object.keys(state.planning.proposals).map(proposalId =>
-                <Option value={proposalId}>Proposal '{proposalId.split("-")[0]}'</Option>
+                <Option value={proposalId}>{proposalName(proposalId, state.planning.proposals[proposalId])}</Option>
             )}</Select>,
+            state.planning.currentProposal && state.planning.proposals[state.planning.currentProposal] &&
+            <Input size="small" style={{ width: 160 }}
+                key={state.planning.currentProposal}
+                disabled={!mayChange(state, state.planning.currentProposal)}
+                placeholder="Name this proposal"
+                defaultValue={state.planning.proposals[state.planning.currentProposal].metadata.name}
+                onPressEnter={e => setState(renameProposal(state.planning.currentProposal, e.target.value))}
+                onBlur={e => setState(renameProposal(state.planning.currentProposal, e.target.value))} />,
+            state.planning.currentProposal && mayChange(state, state.planning.currentProposal) &&
+            <Button size="small"
+                onClick={() => setState(deleteProposal)}
+            >Delete Proposal</Button>,
             state.planning.currentProposal &&
+            <Button size="small"
+                onClick={() => setState(forkProposal)}
+            >Fork Proposal</Button>,
+            state.planning.currentProposal && Object.keys(state.planning.proposals).length > 1
+            && mayChange(state, state.planning.currentProposal)
+            && state.planning.proposals[state.planning.currentProposal].metadata.status != "UnderReview" &&
+            <Select
+                style={{ width: 180 }}
+                size="small"
+                placeholder="Merge from proposal"
+                value={undefined}
+                onChange={(value) => setState(requestMergeConflicts(value))}
+            >{Object.keys(state.planning.proposals).filter(proposalId => proposalId != state.planning.currentProposal).map(proposalId =>
+                <Option value={proposalId}>{proposalName(proposalId, state.planning.proposals[proposalId])}</Option>
+            )}</Select>,
+            state.planning.currentProposal && !state.planning.governance.enabled &&
             <Button type="primary"
-                onClick={() => setState(implementProposal)}
+                disabled={!!state.planning.previewError}
+                onClick={() => setState(requestImpactReport)}
             >Implement</Button>,
+            ...(state.planning.currentProposal && state.planning.governance.enabled
+                && state.planning.proposals[state.planning.currentProposal]
+                ? reviewControls(state, setState) : []),
+            state.planning.currentProposal && state.planning.previewError &&
+            <span className="plan-error" style={{ color: toCSS(fromLinFloat(colors.controlPointError)) }}>
+                {state.planning.previewError.reason}
+            </span>,
+            state.planning.currentProposal && !state.planning.previewError
+            && state.planning.previewWarnings.length > 0 &&
+            <span className="plan-warnings" style={{ color: toCSS(fromLinFloat(colors.planWarning)) }}
+                title={[...new Set(state.planning.previewWarnings.map(warning =>
+                    WARNING_DESCRIPTIONS[warning.kind]))].join("\n")}
+            >{state.planning.previewWarnings.length} warning(s)</span>,
+            <Button size="small"
+                onClick={() => setState(exportPlan(state.planning.currentProposal
+                    ? { Proposal: state.planning.currentProposal }
+                    : "MasterPlan"))}
+            >{state.planning.currentProposal ? "Export Proposal" : "Export Master Plan"}</Button>,
+            !state.planning.currentProposal &&
+            <Button size="small"
+                onClick={() => cbRustBrowser.export_geojson()}
+            >Export City as GeoJSON</Button>,
+            !state.planning.currentProposal &&
+            <Button size="small"
+                onClick={() => setState(revertLastProposal)}
+            >Revert Last Proposal</Button>,
+            state.planning.changeRefused &&
+            (!state.planning.currentProposal || state.planning.changeRefused == state.planning.currentProposal) &&
+            <span className="plan-error" style={{ color: toCSS(fromLinFloat(colors.controlPointError)) }}>
+                Only the author of the proposal can do that
+            </span>,
+            !state.planning.currentProposal && state.planning.revertRefusal &&
+            <span className="plan-error" style={{ color: toCSS(fromLinFloat(colors.controlPointError)) }}>
+                {state.planning.revertRefusal.error
+                    ? state.planning.revertRefusal.error.reason
+                    : `Can't revert, ${state.planning.revertRefusal.dependentProposals.length} later proposal(s) or new buildings build on it`}
+            </span>,
+            <label className="ant-btn ant-btn-sm">
+                Import Proposal
+                <input type="file" accept=".json,application/json" style={{ display: "none" }}
+                    onChange={e => e.target.files[0] && setState(importProposal(e.target.files[0]))} />
+            </label>,
+            state.planning.importError &&
+            <span className="plan-error" style={{ color: toCSS(fromLinFloat(colors.controlPointError)) }}>
+                {state.planning.importError}
+            </span>,
             state.planning.currentProposal &&
             <Toolbar id="planning-toolbar"
                 options={{ roads: { description: "Roads" }, zoning: { description: "Zoning" } }}
@@ -400,6 +854,22 @@ export function render(state, setState) {
         ]
     ];
 
+    const windows = [
+        state.uiMode == 'planning' && state.planning.currentProposal
+        && state.planning.confirmingImplement
+        && <ImpactReport {...state.planning}
+            onConfirm={() => setState(implementProposal)}
+            onCancel={() => setState(cancelImplement)} />,
+        state.uiMode == 'planning' && state.planning.merge
+        && state.planning.merge.into == state.planning.currentProposal
+        && state.planning.proposals[state.planning.merge.from]
+        && <MergeDialog {...state.planning.merge}
+            fromName={proposalName(state.planning.merge.from, state.planning.proposals[state.planning.merge.from])}
+            onResolve={(gestureId, resolution) => setState(resolveMergeConflict(gestureId, resolution))}
+            onConfirm={() => setState(mergeProposals)}
+            onCancel={() => setState(cancelMerge)} />
+    ];
+
     // TODO: invent a better way to preserve identity
 
     const interactables = [
@@ -415,10 +885,11 @@ export function render(state, setState) {
             onEvent: e => {
                 const canvasMode = state.planning.canvasMode;
                 if (e.hover && e.hover.now) {
+                    requestSnap(state, e.hover.now);
                     if (canvasMode.currentGesture) {
                         setState(addControlPoint(
                             state.planning.currentProposal, canvasMode.currentGesture,
-                            e.hover.now, canvasMode.addToEnd, false
+                            snappedOr(state, e.hover.now), canvasMode.addToEnd, false
                         ))
                     }
                 }
@@ -430,12 +901,12 @@ export function render(state, setState) {
                         } else {
                             setState(addControlPoint(
                                 state.planning.currentProposal, canvasMode.currentGesture,
-                                e.drag.end, canvasMode.addToEnd, true
+                                snappedOr(state, e.drag.end), canvasMode.addToEnd, true
                             ))
                         }
                     } else if (canvasMode.intent) {
                         setState(startNewGesture(
-                            state.planning.currentProposal, canvasMode.intent, e.drag.end
+                            state.planning.currentProposal, canvasMode.intent, snappedOr(state, e.drag.end)
                         ));
                     }
                 }
@@ -444,16 +915,114 @@ export function render(state, setState) {
     ]
 
     if (state.uiMode == "planning" && state.planning.currentProposal) {
-        return { layers, interactables, tools };
+        // the server refuses edits of other players' proposals anyway
+        if (!mayChange(state, state.planning.currentProposal)) {
+            return { layers, interactables: [], tools, windows };
+        }
+        return { layers, interactables, tools, windows };
     } else {
         return { tools };
     }
 }
 
+function ImpactReport(props) {
+    const { displacedHouseholds, previewError, currentProposal } = props;
+    // ignore late reports for proposals that were open earlier
+    const impactReport = props.impactReport && props.impactReport.proposalId == currentProposal
+        ? props.impactReport : null;
+
+    if (!impactReport || impactReport.unavailable) {
+        return <div className="window impact-report">
+            <h1>Implementing Proposal</h1>
+            <p>{impactReport
+                ? impactReport.unavailable
+                : previewError ? previewError.reason : "Calculating impact..."}</p>
+            <Button size="small" onClick={props.onCancel}>Cancel</Button>
+        </div>;
+    }
+
+    const { lanes, intersections, lots } = impactReport.counts;
+    const nResidents = displacedHouseholds.reduce((sum, building) => sum + building.residents.length, 0);
+    const nWorkers = displacedHouseholds.reduce((sum, building) => sum + building.workers.length, 0);
+
+    return <div className="window impact-report">
+        <h1>Implementing Proposal</h1>
+        <table>
+            <tr><th></th><th>Constructed</th><th>Changed</th><th>Destroyed</th></tr>
+            {[["Lanes", lanes], ["Intersections", intersections], ["Lots", lots]].map(([name, counts]) =>
+                <tr><td>{name}</td><td>{counts.constructed}</td><td>{counts.morphed}</td><td>{counts.destructed}</td></tr>
+            )}
+        </table>
+        {impactReport.nBuildingsDestroyed > 0 && [
+            <p>{impactReport.nBuildingsDestroyed} building(s) will be destroyed</p>,
+            <p>{nResidents} household(s) will lose their home, {nWorkers} their workplace</p>
+        ]}
+        <Button type="primary" onClick={props.onConfirm}>Implement</Button>
+        <Button size="small" onClick={props.onCancel}>Cancel</Button>
+    </div>;
+}
+
+function describeGesture(gesture) {
+    const [x, y] = gesture.points[0];
+    const kind = Object.keys(gesture.intent)[0];
+    return `${kind} near (${Math.round(x)}, ${Math.round(y)})${gesture.deleted ? " (deleted)" : ""}`;
+}
+
+function MergeDialog(props) {
+    const { conflicts, resolutions, fromName } = props;
+
+    if (!conflicts) {
+        return <div className="window merge-dialog">
+            <h1>Merging {fromName}</h1>
+            <p>Looking for conflicts...</p>
+            <Button size="small" onClick={props.onCancel}>Cancel</Button>
+        </div>;
+    }
+
+    const nUnresolved = conflicts.filter(conflict => !resolutions[conflict.gesture_id]).length;
+
+    return <div className="window merge-dialog">
+        <h1>Merging {fromName}</h1>
+        {conflicts.length == 0
+            ? <p>No conflicts, all changes can be merged</p>
+            : <table>
+                <tr><th>Changed in both</th><th>Keep</th></tr>
+                {conflicts.map(conflict =>
+                    <tr>
+                        <td>{describeGesture(conflict.ours)}</td>
+                        <td>
+                            <Button size="small"
+                                type={resolutions[conflict.gesture_id] == "Ours" ? "primary" : "default"}
+                                onClick={() => props.onResolve(conflict.gesture_id, "Ours")}
+                            >This proposal's</Button>
+                            <Button size="small"
+                                type={resolutions[conflict.gesture_id] == "Theirs" ? "primary" : "default"}
+                                onClick={() => props.onResolve(conflict.gesture_id, "Theirs")}
+                            >{fromName}'s</Button>
+                        </td>
+                    </tr>
+                )}
+            </table>}
+        {nUnresolved > 0 && <p>{nUnresolved} conflict(s) left to resolve</p>}
+        <Button type="primary" disabled={nUnresolved > 0} onClick={props.onConfirm}>Merge</Button>
+        <Button size="small" onClick={props.onCancel}>Cancel</Button>
+    </div>;
+}
+
 export function bindInputs(state, setState) {
     const inputActions = {
-        "implementProposal": () => setState(implementProposal)
+        "implementProposal": () => setState(requestImpactReport),
+        "deleteHoveredGesture": () => setState(deleteHoveredGesture),
+        "insertControlPoint": () => setState(insertControlPointAfterHovered),
+        "removeControlPoint": () => setState(removeHoveredControlPoint),
+        "splitGesture": () => setState(splitGestureAtHovered),
+        "joinGestures": () => setState(joinGesturesAtHovered)
     }
 
     Mousetrap.bind(state.settings.planning.implementProposalKey.key, inputActions["implementProposal"]);
+    Mousetrap.bind(state.settings.planning.deleteGestureKey.key, inputActions["deleteHoveredGesture"]);
+    Mousetrap.bind(state.settings.planning.insertControlPointKey.key, inputActions["insertControlPoint"]);
+    Mousetrap.bind(state.settings.planning.removeControlPointKey.key, inputActions["removeControlPoint"]);
+    Mousetrap.bind(state.settings.planning.splitGestureKey.key, inputActions["splitGesture"]);
+    Mousetrap.bind(state.settings.planning.joinGesturesKey.key, inputActions["joinGestures"]);
 }
\ No newline at end of file
@@ -3,6 +3,17 @@ use stdweb::serde::Serde;
 use stdweb::js_export;
 use SYSTEM;
 
+/// The secret token of the player using this browser, handed out by the server
+/// when the browser joined (see `BrowserUI::on_player_joined`)
+pub fn local_player_token() -> ::planning::PlayerToken {
+    let token = js!{ return window.cbPlayerToken; }
+        .into_string()
+        .expect("player token should be known before planning");
+    ::planning::PlayerToken(
+        ::util::random::Uuid::parse_str(&token).expect("player token should be a UUID"),
+    )
+}
+
 #[js_export]
 pub fn move_gesture_point(
     proposal_id: Serde<::planning::ProposalID>,
@@ -19,6 +30,7 @@ pub fn move_gesture_point(
         point_idx,
         new_position.0,
         done_moving,
+        local_player_token(),
         world,
     );
 }
@@ -38,6 +50,7 @@ pub fn start_new_gesture(
         gesture_id.0,
         intent.0,
         start.0,
+        local_player_token(),
         world,
     )
 }
@@ -58,10 +71,118 @@ pub fn add_control_point(
         new_point.0,
         add_to_end,
         done_adding,
+        local_player_token(),
         world,
     )
 }
 
+#[js_export]
+pub fn delete_gesture(
+    proposal_id: Serde<::planning::ProposalID>,
+    gesture_id: Serde<::planning::GestureID>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).delete_gesture(
+        proposal_id.0,
+        gesture_id.0,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn insert_control_point(
+    proposal_id: Serde<::planning::ProposalID>,
+    gesture_id: Serde<::planning::GestureID>,
+    point_idx: u32,
+    new_point: Serde<::descartes::P2>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).insert_control_point(
+        proposal_id.0,
+        gesture_id.0,
+        point_idx,
+        new_point.0,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn remove_control_point(
+    proposal_id: Serde<::planning::ProposalID>,
+    gesture_id: Serde<::planning::GestureID>,
+    point_idx: u32,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).remove_control_point(
+        proposal_id.0,
+        gesture_id.0,
+        point_idx,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn split_gesture(
+    proposal_id: Serde<::planning::ProposalID>,
+    gesture_id: Serde<::planning::GestureID>,
+    point_idx: u32,
+    new_gesture_id: Serde<::planning::GestureID>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).split_gesture(
+        proposal_id.0,
+        gesture_id.0,
+        point_idx,
+        new_gesture_id.0,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn join_gestures(
+    proposal_id: Serde<::planning::ProposalID>,
+    gesture_id: Serde<::planning::GestureID>,
+    other_gesture_id: Serde<::planning::GestureID>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).join_gestures(
+        proposal_id.0,
+        gesture_id.0,
+        other_gesture_id.0,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn snap_point(
+    proposal_id: Serde<::planning::ProposalID>,
+    candidate: Serde<::descartes::P2>,
+    previous: Serde<Option<::descartes::P2>>,
+    options: Serde<::planning::interaction::snapping::SnappingOptions>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let ui = ::browser_ui::BrowserUI::local_first(world);
+    ::planning::PlanManager::global_first(world).snap_point(
+        proposal_id.0,
+        candidate.0,
+        previous.0,
+        options.0,
+        ui,
+        world,
+    );
+}
+
 #[js_export]
 pub fn finish_gesture() {
     let system = unsafe { &mut *SYSTEM };
@@ -75,3 +196,162 @@ pub fn implement_proposal(proposal_id: Serde<::planning::ProposalID>) {
     let world = &mut system.world();
     ::planning::PlanManager::global_first(world).implement(proposal_id.0, world);
 }
+
+#[js_export]
+pub fn list_proposals() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let ui = ::browser_ui::BrowserUI::local_first(world);
+    ::planning::PlanManager::global_first(world).list_proposals(ui, world);
+}
+
+#[js_export]
+pub fn rename_proposal(proposal_id: Serde<::planning::ProposalID>, name: String) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let mut compact_name = ::compact::CString::new();
+    compact_name.push_str(&name);
+    ::planning::PlanManager::global_first(world).rename_proposal(
+        proposal_id.0,
+        compact_name,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn describe_proposal(proposal_id: Serde<::planning::ProposalID>, description: String) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let mut compact_description = ::compact::CString::new();
+    compact_description.push_str(&description);
+    ::planning::PlanManager::global_first(world).describe_proposal(
+        proposal_id.0,
+        compact_description,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn delete_proposal(proposal_id: Serde<::planning::ProposalID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).delete_proposal(
+        proposal_id.0,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn submit_for_review(proposal_id: Serde<::planning::ProposalID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).submit_for_review(
+        proposal_id.0,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn vote_on_proposal(proposal_id: Serde<::planning::ProposalID>, approve: bool) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).vote(
+        proposal_id.0,
+        local_player_token(),
+        approve,
+        world,
+    );
+}
+
+#[js_export]
+pub fn revert_proposal(proposal_id: Serde<Option<::planning::ProposalID>>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).revert(proposal_id.0, local_player_token(), world);
+}
+
+#[js_export]
+pub fn get_impact_report(proposal_id: Serde<::planning::ProposalID>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let ui = ::browser_ui::BrowserUI::local_first(world);
+    ::planning::PlanManager::global_first(world).get_impact_report(proposal_id.0, ui, world);
+}
+
+#[js_export]
+pub fn export_plan(source: Serde<::planning::PlanExportSource>) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let ui = ::browser_ui::BrowserUI::local_first(world);
+    ::planning::PlanManager::global_first(world).export_to_ui(source.0, ui, world);
+}
+
+#[js_export]
+pub fn import_proposal(
+    proposal_id: Serde<::planning::ProposalID>,
+    export: Serde<::planning::PlanExport>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).import_proposal(
+        proposal_id.0,
+        export.0,
+        Some(local_player_token()),
+        world,
+    );
+}
+
+#[js_export]
+pub fn fork_proposal(
+    source_id: Serde<::planning::ProposalID>,
+    proposal_id: Serde<::planning::ProposalID>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).fork_proposal(
+        source_id.0,
+        proposal_id.0,
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn get_merge_conflicts(
+    into: Serde<::planning::ProposalID>,
+    from: Serde<::planning::ProposalID>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let ui = ::browser_ui::BrowserUI::local_first(world);
+    ::planning::PlanManager::global_first(world).get_merge_conflicts(into.0, from.0, ui, world);
+}
+
+#[js_export]
+pub fn merge_proposals(
+    into: Serde<::planning::ProposalID>,
+    from: Serde<::planning::ProposalID>,
+    resolutions: Serde<Vec<(::planning::GestureID, ::planning::branching::MergeResolution)>>,
+) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::planning::PlanManager::global_first(world).merge_proposals(
+        into.0,
+        from.0,
+        resolutions.0.into_iter().collect(),
+        local_player_token(),
+        world,
+    );
+}
+
+#[js_export]
+pub fn export_geojson() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    let ui = ::browser_ui::BrowserUI::local_first(world);
+    ::planning::PlanManager::global_first(world).export_geojson_to_ui(ui, world);
+}
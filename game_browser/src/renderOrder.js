@@ -1,7 +1,6 @@
 let i = 0;
 
 export default {
-    deletedGestures: i++,
     buildingGround: i++,
     addedGesturesZones: i++,
     addedGesturesZonesStipple: i++,
@@ -9,6 +8,7 @@ export default {
     asphalt: i++,
     asphaltMarker: i++,
     asphaltMarkerGap: i++,
+    deletedGestures: i++,
     addedGesturesAsphalt: i++,
     addedGesturesMarker: i++,
     addedGesturesMarkerGap: i++,
@@ -1,18 +1,30 @@
 import React from 'react';
-import { Slider } from 'antd';
+import { Slider, Button } from 'antd';
 import update from 'immutability-helper';
 
 export const initialState = {
     ticks: 0,
     time: [0, 0],
-    speed: 1
+    date: { weekday: "Monday", day: 1, month: "March", year: 1, season: "Spring" },
+    speed: 1,
+    paused: false,
+    runUntil: null
 }
 
 export function render(state, setState) {
+    const date = state.simulation.date;
     const windows = <div className="sim-time">
+        <span className="sim-date" title={date.season}>
+            {`${date.weekday.slice(0, 3)}, ${date.day} ${date.month.slice(0, 3)} Y${date.year} `}
+        </span>
         {(state.simulation.time[0] + "").padStart(2, "0")}
         <span className="sim-time-colon">:</span>
         {(state.simulation.time[1] + "").padStart(2, "0")}
+        {state.simulation.paused && <span className="sim-paused"> (paused)</span>}
+        {state.simulation.runUntil && <span className="sim-run-until">
+            {" → " + (state.simulation.runUntil[0] + "").padStart(2, "0")
+                + ":" + (state.simulation.runUntil[1] + "").padStart(2, "0")}
+        </span>}
         <Slider className="sim-speed"
             value={state.simulation.speed == 0 ? 0 : Math.log2(state.simulation.speed) + 1}
             min={0} max={6}
@@ -24,6 +36,16 @@ export function render(state, setState) {
             }}
             tipFormatter={speed => speed ? `Speed: ${Math.pow(2, speed - 1)}x` : "Pause"}
         />
+        <div className="sim-controls">
+            {state.simulation.paused
+                ? <Button size="small" onClick={() => cbRustBrowser.resume_sim()}>Resume</Button>
+                : <Button size="small" onClick={() => cbRustBrowser.pause_sim()}>Pause</Button>}
+            <Button size="small" onClick={() => cbRustBrowser.step_sim(1)}>Step</Button>
+            <Button size="small" onClick={() => cbRustBrowser.run_sim_until_time_of_day(
+                (state.simulation.time[0] + 1) % 24, 0
+            )}>Next hour</Button>
+            <Button size="small" onClick={() => cbRustBrowser.save_game()}>Save</Button>
+        </div>
     </div>;
 
     return { windows }
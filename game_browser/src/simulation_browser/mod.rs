@@ -8,3 +8,41 @@ pub fn set_sim_speed(new_speed: u16) {
     let world = &mut system.world();
     ::simulation::Simulation::global_first(world).set_speed(new_speed, world);
 }
+
+#[js_export]
+pub fn pause_sim() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::simulation::Simulation::global_first(world).pause(world);
+}
+
+#[js_export]
+pub fn resume_sim() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::simulation::Simulation::global_first(world).resume(world);
+}
+
+#[js_export]
+pub fn step_sim(n_ticks: u32) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::simulation::Simulation::global_first(world).step(n_ticks, world);
+}
+
+#[js_export]
+pub fn run_sim_until_time_of_day(hours: u32, minutes: u32) {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::simulation::Simulation::global_first(world).run_until_time_of_day(
+        ::simulation::TimeOfDay::new(hours as usize, minutes as usize),
+        world,
+    );
+}
+
+#[js_export]
+pub fn save_game() {
+    let system = unsafe { &mut *SYSTEM };
+    let world = &mut system.world();
+    ::savegame::SavegameManager::global_first(world).save(world);
+}
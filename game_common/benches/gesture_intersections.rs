@@ -0,0 +1,92 @@
+//! Compares the grid-accelerated intersection search between road gestures
+//! with splitting every pair of gestures against each other, on grid cities
+//! where every block edge is its own gesture.
+//!
+//! Run with `cargo bench -p citybound_common`
+
+#![feature(test)]
+
+extern crate citybound_common;
+extern crate test;
+
+use citybound_common::descartes::{P2, LinePath, Area};
+use citybound_common::planning::{GestureID, StepID};
+use citybound_common::transport::transport_planning::{RoadIntent, TransportPlanningCache,
+gesture_area_for_intersection, gesture_intersection_areas};
+use citybound_common::util::random::uuid;
+use test::Bencher;
+
+const BLOCK_SIZE: f32 = 100.0;
+
+/// `2 * n_blocks * (n_blocks + 1)` gestures
+fn grid_city_gesture_areas(n_blocks: usize) -> Vec<(Area, GestureID, StepID)> {
+    let step_id = StepID(uuid());
+    let road_intent = RoadIntent::new(2, 2);
+
+    (0..=n_blocks)
+        .flat_map(|line_i| {
+            (0..n_blocks).flat_map(move |block_i| {
+                let along = block_i as f32 * BLOCK_SIZE;
+                let across = line_i as f32 * BLOCK_SIZE;
+                vec![
+                    (P2::new(along, across), P2::new(along + BLOCK_SIZE, across)),
+                    (P2::new(across, along), P2::new(across, along + BLOCK_SIZE)),
+                ]
+            })
+        }).map(|(start, end)| {
+            let path = LinePath::new(vec![start, end].into()).expect("Block edge should be valid");
+            (
+                gesture_area_for_intersection(road_intent, &path),
+                GestureID::new(),
+                step_id,
+            )
+        }).collect()
+}
+
+/// How intersections were found before using the grid
+fn all_pairs_intersection_areas(gesture_areas: &[(Area, GestureID, StepID)]) -> Vec<Area> {
+    gesture_areas
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &(ref shape_a, _, _))| {
+            gesture_areas[(i + 1)..]
+                .iter()
+                .flat_map(move |&(ref shape_b, _, _)| {
+                    shape_a
+                        .split(shape_b)
+                        .intersection()
+                        .map(|intersections| intersections.disjoint())
+                        .unwrap_or_default()
+                })
+        }).collect()
+}
+
+#[bench]
+fn grid_220_gestures(b: &mut Bencher) {
+    let gesture_areas = grid_city_gesture_areas(10);
+    b.iter(|| gesture_intersection_areas(&gesture_areas, &mut TransportPlanningCache::default()));
+}
+
+#[bench]
+fn all_pairs_220_gestures(b: &mut Bencher) {
+    let gesture_areas = grid_city_gesture_areas(10);
+    b.iter(|| all_pairs_intersection_areas(&gesture_areas));
+}
+
+#[bench]
+fn grid_1012_gestures(b: &mut Bencher) {
+    let gesture_areas = grid_city_gesture_areas(22);
+    b.iter(|| gesture_intersection_areas(&gesture_areas, &mut TransportPlanningCache::default()));
+}
+
+#[bench]
+fn all_pairs_1012_gestures(b: &mut Bencher) {
+    let gesture_areas = grid_city_gesture_areas(22);
+    b.iter(|| all_pairs_intersection_areas(&gesture_areas));
+}
+
+#[bench]
+fn grid_3960_gestures(b: &mut Bencher) {
+    let gesture_areas = grid_city_gesture_areas(44);
+    b.iter(|| gesture_intersection_areas(&gesture_areas, &mut TransportPlanningCache::default()));
+}
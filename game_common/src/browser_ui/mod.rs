@@ -5,12 +5,18 @@
 )]
 
 use kay::{World, ActorSystem, Actor, RawID, External};
-use compact::{CVec, CHashMap};
-use std::collections::HashMap;
-use descartes::LinePath;
+use compact::{CVec, CHashMap, COption};
+use std::collections::{HashMap, HashSet};
+use descartes::{P2, LinePath};
 use michelangelo::{MeshGrouper, Instance};
 use planning::{ProposalID, Proposal, PrototypeID, PlanHistory, PlanResult,
-PlanHistoryUpdate, ProposalUpdate, PlanResultUpdate, ActionGroups};
+PlanHistoryUpdate, ProposalUpdate, PlanResultUpdate, ActionGroups, PlanExport, PlanExportSource,
+Prototype, PlanError, ProposalMetadata, PlayerID, PlayerToken};
+use planning::interaction::snapping::SnappedPoint;
+use planning::validation::PlanWarning;
+use planning::impact::ImpactReport;
+use planning::review::GovernanceSettings;
+use planning::branching::MergeConflict;
 use ::land_use::zone_planning::{LandUse, LAND_USES};
 #[cfg(feature = "browser")]
 use stdweb::serde::Serde;
@@ -50,6 +56,8 @@ pub struct BrowserUINonPersistedState {
     zone_groupers: HashMap<LandUse, MeshGrouper<PrototypeID>>,
     zone_outline_groupers: HashMap<LandUse, MeshGrouper<PrototypeID>>,
     building_outlines_grouper: MeshGrouper<PrototypeID>,
+    prototypes_to_destruct: HashSet<PrototypeID>,
+    prototypes_to_destruct_grouper: MeshGrouper<PrototypeID>,
     // transport geometry
     asphalt_grouper: MeshGrouper<RawID>,
     lane_marker_grouper: MeshGrouper<RawID>,
@@ -119,6 +127,17 @@ impl BrowserUI {
             ::transport::lane::Lane::global_broadcast(world).get_render_info(id, world);
             ::transport::lane::SwitchLane::global_broadcast(world).get_render_info(id, world);
             ::land_use::buildings::Building::global_broadcast(world).get_render_info(id, world);
+            ::planning::PlanManager::global_first(world).get_governance(id, world);
+
+            let known_token = js! { return localStorage["cbplayertoken"] || null; }
+                .into_string()
+                .and_then(|token| ::util::random::Uuid::parse_str(&token).ok())
+                .map(PlayerToken);
+            ::planning::PlanManager::global_first(world).join_as_player(
+                COption(known_token),
+                id,
+                world,
+            );
         }
 
         BrowserUI {
@@ -142,6 +161,8 @@ impl BrowserUI {
                     .map(|land_use| (*land_use, MeshGrouper::new(2000)))
                     .collect(),
                 building_outlines_grouper: MeshGrouper::new(2000),
+                prototypes_to_destruct: HashSet::new(),
+                prototypes_to_destruct_grouper: MeshGrouper::new(2000),
                 asphalt_grouper: MeshGrouper::new(2000),
                 lane_marker_grouper: MeshGrouper::new(2000),
                 lane_marker_gaps_grouper: MeshGrouper::new(2000),
@@ -209,18 +230,33 @@ impl BrowserUI {
         &mut self,
         current_instant: ::simulation::Instant,
         speed: u16,
+        paused: bool,
+        run_until: &::compact::COption<::simulation::Instant>,
         _world: &mut World,
     ) {
         #[cfg(feature = "browser")]
         {
+            let run_until_time = run_until
+                .map(|instant| ::simulation::TimeOfDay::from(instant).hours_minutes());
+            let date = ::simulation::Date::from(current_instant);
+            let (month, day_of_month) = date.month_and_day();
             js! {
                 window.cbReactApp.setState(oldState => update(oldState, {
                     simulation: {
-                        ticks: {"$set": @{current_instant.ticks() as u32}},
+                        ticks: {"$set": @{current_instant.ticks() as f64}},
                         time: {"$set": @{
                             Serde(::simulation::TimeOfDay::from(current_instant).hours_minutes())
                         }},
-                        speed: {"$set": @{speed}}
+                        date: {"$set": {
+                            weekday: @{Serde(date.weekday())},
+                            day: @{day_of_month as u32},
+                            month: @{Serde(month)},
+                            year: @{date.year() as u32},
+                            season: @{Serde(date.season())}
+                        }},
+                        speed: {"$set": @{speed}},
+                        paused: {"$set": @{paused}},
+                        runUntil: {"$set": @{Serde(run_until_time)}}
                     }
                 }))
             }
@@ -279,12 +315,14 @@ impl BrowserUI {
                     }
                     ProposalUpdate::Removed => {
                         js! {
+                           // e.g. implemented after being approved by other players
                            window.cbReactApp.setState(oldState => update(oldState, {
-                               planning: {
+                               planning: Object.assign({
                                    proposals: {
                                        "$unset": [@{Serde(*proposal_id)}]
                                    }
-                               }
+                               }, oldState.planning.currentProposal == @{Serde(*proposal_id)}
+                                   ? {"$unset": ["currentProposal"]} : {})
                            }));
                         }
                         self.proposals.remove(proposal_id);
@@ -294,15 +332,80 @@ impl BrowserUI {
         }
     }
 
+    pub fn on_plan_export(
+        &mut self,
+        source: PlanExportSource,
+        export: &PlanExport,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            let file_name = match source {
+                PlanExportSource::MasterPlan => "master_plan.json".to_owned(),
+                PlanExportSource::Proposal(proposal_id) => format!(
+                    "proposal_{}.json",
+                    proposal_id.0.to_string().split('-').next().unwrap_or("")
+                ),
+            };
+            let export_json =
+                ::serde_json::to_string_pretty(export).expect("Plan export should serialize");
+            offer_download(&file_name, &export_json, "application/json");
+        }
+    }
+
+    /// Only shown by the UI that started the import
+    pub fn on_import_rejected(
+        &mut self,
+        proposal_id: ProposalID,
+        reason: &::compact::CString,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState =>
+                    oldState.planning.pendingImport == @{Serde(proposal_id)}
+                        ? update(oldState, {
+                            planning: {
+                                pendingImport: {"$set": null},
+                                importError: {"$set": @{&**reason}}
+                            }
+                        })
+                        : oldState
+                );
+            }
+        }
+    }
+
+    pub fn on_geojson_export(&mut self, geojson: &::compact::CString, _world: &mut World) {
+        #[cfg(feature = "browser")]
+        {
+            offer_download("city.geojson", geojson, "application/geo+json");
+        }
+    }
+
     pub fn on_proposal_preview_update(
         &mut self,
         _proposal_id: ProposalID,
         result_update: &PlanResultUpdate,
         new_actions: &ActionGroups,
+        prototypes_to_destruct: &CVec<Prototype>,
+        error: &COption<PlanError>,
+        warnings: &CVec<PlanWarning>,
         _world: &mut World,
     ) {
         #[cfg(feature = "browser")]
         {
+            if let Some(ref error) = **error {
+                js! {
+                    window.cbReactApp.setState(oldState => update(oldState, {
+                        planning: {previewError: {"$set": @{Serde(error)}}}
+                    }));
+                }
+                self.awaiting_preview_update = false;
+                return;
+            }
+
             use ::planning::PrototypeKind;
             use ::transport::transport_planning::{RoadPrototype, LanePrototype,
 SwitchLanePrototype, IntersectionPrototype};
@@ -521,6 +624,60 @@ SwitchLanePrototype, IntersectionPrototype};
                 .building_outlines_grouper
                 .update(building_outlines_rem, building_outlines_add);
 
+            let still_to_destruct = prototypes_to_destruct
+                .iter()
+                .map(|prototype| prototype.id)
+                .collect::<HashSet<_>>();
+
+            let to_destruct_rem = self
+                .prototypes_to_destruct
+                .iter()
+                .filter(|prototype_id| !still_to_destruct.contains(prototype_id))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            // only roads and buildings, destructed vacant lots would just be noise
+            let to_destruct_add = prototypes_to_destruct
+                .iter()
+                .filter(|prototype| !self.prototypes_to_destruct.contains(&prototype.id))
+                .filter_map(|prototype| {
+                    let mesh = match prototype.kind {
+                        PrototypeKind::Road(RoadPrototype::Lane(LanePrototype(
+                            ref lane_path,
+                            _,
+                        ))) => lane_mesh(lane_path),
+                        PrototypeKind::Road(RoadPrototype::Intersection(IntersectionPrototype {
+                            ref connecting_lanes,
+                            ..
+                        })) => {
+                            let mut intersection_mesh = Mesh::empty();
+                            for &LanePrototype(ref lane_path, _) in
+                                connecting_lanes.values().flat_map(|lanes| lanes)
+                            {
+                                intersection_mesh += lane_mesh(lane_path);
+                            }
+                            intersection_mesh
+                        }
+                        PrototypeKind::Lot(LotPrototype {
+                            ref lot,
+                            occupancy: LotOccupancy::Occupied(_),
+                        }) => Mesh::from_area(&lot.area),
+                        _ => return None,
+                    };
+                    Some((prototype.id, mesh))
+                }).collect::<Vec<_>>();
+
+            for &(prototype_id, _) in &to_destruct_add {
+                self.prototypes_to_destruct.insert(prototype_id);
+            }
+            for prototype_id in &to_destruct_rem {
+                self.prototypes_to_destruct.remove(prototype_id);
+            }
+
+            let updated_prototypes_to_destruct_groups = self
+                .prototypes_to_destruct_grouper
+                .update(to_destruct_rem, to_destruct_add);
+
             js! {
                 window.cbReactApp.setState(oldState => update(oldState, {
                     planning: {rendering: {
@@ -547,8 +704,15 @@ SwitchLanePrototype, IntersectionPrototype};
                                     updated_building_outlines_groups
                                 )}
                             },
+                            prototypesToDestructGroups: {
+                                "$add": @{updated_groups_to_js(
+                                    updated_prototypes_to_destruct_groups
+                                )}
+                            },
                         }
-                    }}
+                    },
+                    previewError: {"$set": null},
+                    previewWarnings: {"$set": @{Serde(warnings)}}}
                 }));
             }
 
@@ -558,6 +722,249 @@ SwitchLanePrototype, IntersectionPrototype};
         }
     }
 
+    pub fn on_implement_blocked(
+        &mut self,
+        proposal_id: ProposalID,
+        error: &PlanError,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        currentProposal: {"$set": @{Serde(proposal_id)}},
+                        previewError: {"$set": @{Serde(error)}}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_governance_update(&mut self, settings: GovernanceSettings, _world: &mut World) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        governance: {"$set": @{Serde(settings)}}
+                    }
+                }));
+            }
+        }
+    }
+
+    /// The token is remembered across reloads, so the browser stays the same player
+    pub fn on_player_joined(&mut self, token: PlayerToken, player: PlayerID, _world: &mut World) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                localStorage["cbplayertoken"] = @{Serde(token)};
+                window.cbPlayerToken = @{Serde(token)};
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        player: {"$set": @{Serde(player)}}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_change_refused(
+        &mut self,
+        proposal_id: ProposalID,
+        player: PlayerID,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState =>
+                    oldState.planning.player == @{Serde(player)}
+                        ? update(oldState, {
+                            planning: {
+                                changeRefused: {"$set": @{Serde(proposal_id)}}
+                            }
+                        })
+                        : oldState
+                );
+            }
+        }
+    }
+
+    pub fn on_review_required(&mut self, proposal_id: ProposalID, _world: &mut World) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        reviewRequired: {"$set": @{Serde(proposal_id)}}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_merge_conflicts(
+        &mut self,
+        into: ProposalID,
+        from: ProposalID,
+        conflicts: &CVec<MergeConflict>,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        merge: {"$set": {
+                            into: @{Serde(into)},
+                            from: @{Serde(from)},
+                            conflicts: @{Serde(conflicts)},
+                            resolutions: {}
+                        }}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_proposal_list(
+        &mut self,
+        all_metadata: &CHashMap<ProposalID, ProposalMetadata>,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            let as_map = all_metadata
+                .pairs()
+                .map(|(proposal_id, metadata)| (*proposal_id, metadata.clone()))
+                .collect::<HashMap<_, _>>();
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        proposalList: {"$set": @{Serde(as_map)}}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_revert_refused(
+        &mut self,
+        proposal_id: ProposalID,
+        dependent_proposals: &CVec<ProposalID>,
+        error: &COption<PlanError>,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        revertRefusal: {"$set": {
+                            proposalId: @{Serde(proposal_id)},
+                            dependentProposals: @{Serde(dependent_proposals)},
+                            error: @{Serde(&**error)}
+                        }}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_impact_report(
+        &mut self,
+        proposal_id: ProposalID,
+        report: &ImpactReport,
+        n_buildings_destroyed: u32,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        impactReport: {"$set": {
+                            proposalId: @{Serde(proposal_id)},
+                            counts: @{Serde(report)},
+                            nBuildingsDestroyed: @{n_buildings_destroyed}
+                        }}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_impact_report_unavailable(
+        &mut self,
+        proposal_id: ProposalID,
+        reason: &::compact::CString,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        impactReport: {"$set": {
+                            proposalId: @{Serde(proposal_id)},
+                            unavailable: @{&**reason}
+                        }}
+                    }
+                }));
+            }
+        }
+    }
+
+    pub fn on_displaced_households(
+        &mut self,
+        proposal_id: ProposalID,
+        _building_id: ::land_use::buildings::BuildingID,
+        style: ::land_use::buildings::BuildingStyle,
+        residents: &CVec<::economy::households::HouseholdID>,
+        workers: &CVec<::economy::households::HouseholdID>,
+        _world: &mut World,
+    ) {
+        #[cfg(feature = "browser")]
+        {
+            // replies might still arrive for a report that was requested earlier
+            js! {
+                window.cbReactApp.setState(oldState =>
+                    oldState.planning.confirmingImplement
+                    && oldState.planning.currentProposal == @{Serde(proposal_id)}
+                        ? update(oldState, {
+                            planning: {
+                                displacedHouseholds: {"$push": [{
+                                    style: @{Serde(style)},
+                                    residents: @{Serde(residents)},
+                                    workers: @{Serde(workers)}
+                                }]}
+                            }
+                        })
+                        : oldState
+                );
+            }
+        }
+    }
+
+    pub fn on_point_snapped(&mut self, candidate: P2, snapped: SnappedPoint, _world: &mut World) {
+        #[cfg(feature = "browser")]
+        {
+            js! {
+                window.cbReactApp.setState(oldState => update(oldState, {
+                    planning: {
+                        snappedPoint: {"$set": {
+                            candidate: @{Serde(candidate)},
+                            point: @{Serde(snapped.point)},
+                            target: @{Serde(snapped.target)}
+                        }}
+                    }
+                }));
+            }
+        }
+    }
+
     pub fn on_lane_constructed(
         &mut self,
         id: RawID,
@@ -705,6 +1112,7 @@ SwitchLanePrototype, IntersectionPrototype};
         id: ::land_use::buildings::BuildingID,
         lot: &::land_use::zone_planning::Lot,
         style: ::land_use::buildings::BuildingStyle,
+        architecture_seed: u64,
         _world: &mut World,
     ) {
         #[cfg(feature = "browser")]
@@ -712,7 +1120,7 @@ SwitchLanePrototype, IntersectionPrototype};
             let meshes = ::land_use::buildings::architecture::build_building(
                 lot,
                 style,
-                &mut ::util::random::seed(id),
+                &mut ::util::random::seed(architecture_seed),
             );
 
             js!{
@@ -805,6 +1213,18 @@ SwitchLanePrototype, IntersectionPrototype};
     }
 }
 
+#[cfg(feature = "browser")]
+fn offer_download(file_name: &str, contents: &str, mime_type: &str) {
+    js! {
+        const link = document.createElement("a");
+        link.href = URL.createObjectURL(new Blob([@{contents}], {type: @{mime_type}}));
+        link.download = @{file_name};
+        document.body.appendChild(link);
+        link.click();
+        document.body.removeChild(link);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<BrowserUI>();
     auto_setup(system);
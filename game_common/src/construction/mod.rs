@@ -1,7 +1,11 @@
-use kay::{World, Fate, ActorSystem};
-use compact::{CVec, CHashMap};
-use planning::{PrototypeID, Prototype, PrototypeKind, Action, ActionGroups};
+use kay::{World, Fate, ActorSystem, TypedID};
+use compact::{CVec, CHashMap, CString};
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
+use planning::{PrototypeID, Prototype, PrototypeKind, Action, ActionGroups, ProposalID};
 use simulation::{Simulatable, SimulatableID, Instant};
+use util::profiling;
+use land_use::buildings::BuildingID;
+use browser_ui::BrowserUIID;
 
 pub trait Constructable {
     fn morph(&mut self, new_prototype: &Prototype, report_to: ConstructionID, world: &mut World);
@@ -31,7 +35,7 @@ impl Prototype {
     }
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Construction {
     id: ConstructionID,
     constructed: CHashMap<PrototypeID, CVec<ConstructableID>>,
@@ -115,10 +119,29 @@ impl Construction {
                 .insert(new_prototype.id, new_prototype.clone());
         }
     }
+
+    pub fn report_displaced_households(
+        &mut self,
+        lot_prototype_ids: &CVec<PrototypeID>,
+        proposal_id: ProposalID,
+        requester: BrowserUIID,
+        world: &mut World,
+    ) {
+        for prototype_id in lot_prototype_ids {
+            if let Some(ids) = self.constructed.get(*prototype_id) {
+                for id in ids {
+                    // occupied lots are always constructed as buildings
+                    BuildingID::from_raw(id.as_raw())
+                        .report_displaced_households(proposal_id, requester, world);
+                }
+            }
+        }
+    }
 }
 
 impl Simulatable for Construction {
     fn tick(&mut self, _dt: f32, _current_instant: Instant, world: &mut World) {
+        let _measurement = profiling::start("Construction ticks");
         if self.pending_constructables.is_empty() {
             if !self.queued_action_groups.0.is_empty() {
                 println!("Starting construction group:");
@@ -138,6 +161,16 @@ impl Simulatable for Construction {
     }
 }
 
+impl Persistent for Construction {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<Construction>();
     auto_setup(system);
@@ -1,13 +1,16 @@
 use kay::{ActorSystem, World, TypedID, Actor};
-use simulation::{TimeOfDay, TimeOfDayRange, Duration, SimulationID, Ticks};
+use compact::CString;
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
+use simulation::{TimeOfDay, TimeOfDayRange, WeeklyTimeOfDayRange, Duration, SimulationID, Ticks};
 use economy::resources::Resource;
 use economy::resources::Resource::*;
 use economy::market::{Deal, EvaluationRequester, EvaluationRequesterID, EvaluatedSearchResult};
 use land_use::buildings::BuildingID;
+use util::profiling;
 
 use super::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Bakery {
     id: BakeryID,
     site: BuildingID,
@@ -34,7 +37,10 @@ impl Bakery {
                 vec![
                     Offer::new(
                         MemberIdx(0),
-                        TimeOfDayRange::new(7, 0, 20, 0),
+                        WeeklyTimeOfDayRange::weekdays_and_weekends(
+                            TimeOfDayRange::new(7, 0, 20, 0),
+                            Some(TimeOfDayRange::new(8, 0, 12, 0)),
+                        ),
                         Deal::new(
                             vec![
                                 (Resource::BakedGoods, 100.0),
@@ -136,8 +142,9 @@ const UPDATE_EVERY_N_SECS: u32 = 4;
 
 impl Simulatable for Bakery {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
-        if (current_instant.ticks() + self.id.as_raw().instance_id as usize)
-            % (UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND) as usize
+        let _measurement = profiling::start("Bakery ticks");
+        if (current_instant.ticks() + self.id.as_raw().instance_id as u64)
+            % u64::from(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND)
             == 0
         {
             self.decay(Duration(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND), world);
@@ -183,6 +190,25 @@ impl TripListener for Bakery {
     }
 }
 
+impl Bakery {
+    pub fn restore(id: BakeryID, saved: &Bakery, _: &mut World) -> Bakery {
+        Bakery {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for Bakery {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<Bakery>();
     auto_setup(system);
@@ -1,5 +1,8 @@
-use kay::{ActorSystem, World, Actor};
+use kay::{ActorSystem, World, Actor, TypedID};
+use compact::CString;
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use util::random::{seed, Rng};
+use util::profiling;
 
 use simulation::{TimeOfDay, TimeOfDayRange, Instant, Duration, Ticks, SimulationID, Simulatable,
 SimulatableID};
@@ -15,7 +18,7 @@ use self::names::{family_name, member_name};
 
 use super::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer, OfferID, OfferIdx};
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Family {
     id: FamilyID,
     home: BuildingID,
@@ -218,6 +221,7 @@ impl Household for Family {
 
 impl Simulatable for Family {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measurement = profiling::start("Family ticks");
         self.on_tick(current_instant, world);
     }
 }
@@ -230,6 +234,25 @@ impl RoughLocation for Family {
     }
 }
 
+impl Family {
+    pub fn restore(id: FamilyID, saved: &Family, _: &mut World) -> Family {
+        Family {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for Family {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<Family>();
     auto_setup(system);
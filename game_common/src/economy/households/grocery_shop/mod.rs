@@ -1,13 +1,16 @@
 use kay::{ActorSystem, World, TypedID, Actor};
+use compact::CString;
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use simulation::{TimeOfDay, TimeOfDayRange, Duration, SimulationID, Ticks};
 use economy::resources::Resource;
 use economy::resources::Resource::*;
 use economy::market::{Deal, EvaluationRequester, EvaluationRequesterID, EvaluatedSearchResult};
 use land_use::buildings::BuildingID;
+use util::profiling;
 
 use super::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct GroceryShop {
     id: GroceryShopID,
     site: BuildingID,
@@ -173,8 +176,9 @@ const UPDATE_EVERY_N_SECS: u32 = 4;
 
 impl Simulatable for GroceryShop {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
-        if (current_instant.ticks() + self.id.as_raw().instance_id as usize)
-            % (UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND) as usize
+        let _measurement = profiling::start("GroceryShop ticks");
+        if (current_instant.ticks() + self.id.as_raw().instance_id as u64)
+            % u64::from(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND)
             == 0
         {
             self.decay(Duration(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND), world);
@@ -215,6 +219,25 @@ impl TripListener for GroceryShop {
     }
 }
 
+impl GroceryShop {
+    pub fn restore(id: GroceryShopID, saved: &GroceryShop, _: &mut World) -> GroceryShop {
+        GroceryShop {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for GroceryShop {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<GroceryShop>();
     auto_setup(system);
@@ -1,9 +1,10 @@
 use kay::{ActorSystem, World, Actor, TypedID, Fate};
 use compact::{CVec, CDict, COption, CString};
-use simulation::{Duration, TimeOfDay, Instant, Ticks, Simulation, TICKS_PER_SIM_SECOND, Sleeper,
-Simulatable};
+use simulation::{Duration, TimeOfDay, Date, Instant, Ticks, Simulation, TICKS_PER_SIM_SECOND,
+Sleeper, Simulatable};
 use util::async_counter::AsyncCounter;
 use util::random::{seed, Rng};
+use util::profiling;
 use ordered_float::OrderedFloat;
 
 pub mod tasks;
@@ -18,7 +19,7 @@ pub mod mill;
 pub mod bakery;
 pub mod neighboring_town_trade;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct MemberIdx(u32);
 
 impl MemberIdx {
@@ -212,6 +213,7 @@ pub trait Household:
     fn on_destroy(&mut self, world: &mut World);
 
     fn update_core(&mut self, current_instant: Instant, world: &mut World) {
+        let _measurement = profiling::start("Household decisions");
         if let DecisionState::None = self.core().decision_state {
             let idle_members_idx_loc = self
                 .core()
@@ -327,6 +329,7 @@ pub trait Household:
     }
 
     fn update_results(&mut self, resource: Resource, update: &ResultAspect, world: &mut World) {
+        let _measurement = profiling::start("Household decisions");
         let done = {
             let core = self.core_mut();
 
@@ -341,12 +344,17 @@ pub trait Household:
                     match *update {
                         ResultAspect::AddDeals(ref evaluated_deals) => {
                             for evaluated_deal in evaluated_deals {
+                                let todays_opening_hours = evaluated_deal
+                                    .opening_hours
+                                    .on(Date::from(instant).weekday())
+                                    .map(|hours| {
+                                        (hours.start.hours_minutes(), hours.end.hours_minutes())
+                                    });
                                 core.log.log(
                                     format!(
-                                        "Got eval'd deal for {}, {:?} -> {:?}\n",
+                                        "Got eval'd deal for {}, today {:?}\n",
                                         evaluated_deal.deal.main_given(),
-                                        evaluated_deal.opening_hours.start.hours_minutes(),
-                                        evaluated_deal.opening_hours.end.hours_minutes(),
+                                        todays_opening_hours,
                                     ).as_str(),
                                 );
                                 if evaluated_deal.opening_hours.contains(instant) {
@@ -687,8 +695,8 @@ pub trait Household:
     }
 
     fn on_tick(&mut self, current_instant: Instant, world: &mut World) {
-        if (current_instant.ticks() + self.id().as_raw().instance_id as usize)
-            % (UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND) as usize
+        if (current_instant.ticks() + self.id().as_raw().instance_id as u64)
+            % u64::from(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND)
             == 0
         {
             self.decay(Duration(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND), world);
@@ -713,10 +721,7 @@ pub trait Household:
     ) {
         let offer = self.get_offer(offer_idx);
 
-        if offer
-            .opening_hours
-            .end_after_on_same_day(TimeOfDay::from(instant))
-        {
+        if offer.opening_hours.end_after_on_same_day(instant) {
             let search_result = EvaluatedSearchResult {
                 resource: offer.deal.main_given(),
                 evaluated_deals: vec![EvaluatedDeal {
@@ -926,14 +931,14 @@ pub enum ResultAspect {
     SetTarget(u32),
 }
 
-#[derive(Compact, Clone, Serialize)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct DecisionResourceEntry {
     results_counter: AsyncCounter,
     best_deal: COption<EvaluatedDeal>,
     best_deal_usefulness: f32,
 }
 
-#[derive(Compact, Clone, Serialize)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub enum DecisionState {
     None,
     Choosing(
@@ -945,7 +950,7 @@ pub enum DecisionState {
     WaitingForTrip(MemberIdx),
 }
 
-#[derive(Compact, Clone, Serialize)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct HouseholdCore {
     pub resources: Inventory,
     pub member_resources: CVec<Inventory>,
@@ -995,7 +1000,7 @@ impl HouseholdCore {
 
 const DO_HOUSEHOLD_LOGGING: bool = false;
 
-#[derive(Compact, Clone, Default, Serialize)]
+#[derive(Compact, Clone, Default, Serialize, Deserialize)]
 pub struct HouseholdLog(CString);
 
 impl HouseholdLog {
@@ -1,4 +1,6 @@
-use kay::{ActorSystem, World, Actor};
+use kay::{ActorSystem, World, Actor, TypedID};
+use compact::CString;
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use simulation::{TimeOfDay, TimeOfDayRange, Duration, Instant, Simulatable, SimulatableID,
 SimulationID, Ticks};
 use economy::resources::Resource;
@@ -7,10 +9,11 @@ use economy::market::{Deal, EvaluationRequester, EvaluationRequesterID, Evaluate
 use land_use::buildings::BuildingID;
 use transport::pathfinding::RoughLocationID;
 use transport::pathfinding::trip::{TripListener, TripListenerID, TripID, TripResult};
+use util::profiling;
 
 use super::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct NeighboringTownTrade {
     id: NeighboringTownTradeID,
     town: BuildingID,
@@ -292,6 +295,7 @@ impl TripListener for NeighboringTownTrade {
 
 impl Simulatable for NeighboringTownTrade {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
+        let _measurement = profiling::start("NeighboringTownTrade ticks");
         self.on_tick(current_instant, world);
     }
 }
@@ -304,6 +308,29 @@ impl RoughLocation for NeighboringTownTrade {
     }
 }
 
+impl NeighboringTownTrade {
+    pub fn restore(
+        id: NeighboringTownTradeID,
+        saved: &NeighboringTownTrade,
+        _: &mut World,
+    ) -> NeighboringTownTrade {
+        NeighboringTownTrade {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for NeighboringTownTrade {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<NeighboringTownTrade>();
     auto_setup(system);
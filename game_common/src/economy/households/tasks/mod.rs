@@ -1,10 +1,12 @@
-use kay::{ActorSystem, World};
-use compact::CVec;
-use simulation::{Instant, Duration, Simulatable, SimulatableID};
+use kay::{ActorSystem, World, TypedID};
+use compact::CString;
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
+use simulation::{Instant, Duration, Simulatable, SimulatableID, WakeUpQueue};
 use transport::pathfinding::RoughLocationID;
 use transport::pathfinding::trip::TripID;
 use super::super::resources::Resource;
 use super::OfferID;
+use util::profiling;
 
 use super::{HouseholdID, MemberIdx};
 
@@ -33,17 +35,17 @@ impl Task {
     }
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct TaskEndScheduler {
     id: TaskEndSchedulerID,
-    task_ends: CVec<(Instant, HouseholdID, MemberIdx)>,
+    task_ends: WakeUpQueue<(HouseholdID, MemberIdx), ()>,
 }
 
 impl TaskEndScheduler {
     pub fn spawn(id: TaskEndSchedulerID, _: &mut World) -> TaskEndScheduler {
         TaskEndScheduler {
             id,
-            task_ends: CVec::new(),
+            task_ends: WakeUpQueue::new(),
         }
     }
 
@@ -54,39 +56,33 @@ impl TaskEndScheduler {
         member: MemberIdx,
         _: &mut World,
     ) {
-        let maybe_idx = self
-            .task_ends
-            .binary_search_by_key(&(-end.iticks()), |&(e, ..)| -(e.iticks()));
-        let insert_idx = match maybe_idx {
-            Ok(idx) | Err(idx) => idx,
-        };
-        self.task_ends.insert(insert_idx, (end, household, member));
+        self.task_ends.schedule((household, member), end, ());
     }
 
     pub fn deschedule(&mut self, household: HouseholdID, member: MemberIdx, _: &mut World) {
-        self.task_ends.retain(|&(_, task_household, task_member)| {
-            task_household != household || task_member != member
-        });
+        self.task_ends.cancel((household, member));
     }
 }
 
 impl Simulatable for TaskEndScheduler {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
-        while self
-            .task_ends
-            .last()
-            .map(|&(end, ..)| end < current_instant)
-            .unwrap_or(false)
-        {
-            let (_, household, member) = self
-                .task_ends
-                .pop()
-                .expect("just checked that there are WIP tasks");
+        let _measurement = profiling::start("TaskEndScheduler ticks");
+        while let Some(((household, member), ())) = self.task_ends.pop_due_before(current_instant) {
             household.task_succeeded(member, world);
         }
     }
 }
 
+impl Persistent for TaskEndScheduler {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<TaskEndScheduler>();
     auto_setup(system);
@@ -1,13 +1,16 @@
 use kay::{ActorSystem, World, TypedID, Actor};
+use compact::CString;
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use simulation::{TimeOfDay, TimeOfDayRange, Duration, SimulationID, Ticks};
 use economy::resources::Resource;
 use economy::resources::Resource::*;
 use economy::market::{Deal, EvaluationRequester, EvaluationRequesterID, EvaluatedSearchResult};
 use land_use::buildings::BuildingID;
+use util::profiling;
 
 use super::{Household, HouseholdID, HouseholdCore, MemberIdx, Offer};
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct VegetableFarm {
     id: VegetableFarmID,
     site: BuildingID,
@@ -109,8 +112,9 @@ const UPDATE_EVERY_N_SECS: u32 = 4;
 
 impl Simulatable for VegetableFarm {
     fn tick(&mut self, _dt: f32, current_instant: Instant, world: &mut World) {
-        if (current_instant.ticks() + self.id.as_raw().instance_id as usize)
-            % (UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND) as usize
+        let _measurement = profiling::start("VegetableFarm ticks");
+        if (current_instant.ticks() + self.id.as_raw().instance_id as u64)
+            % u64::from(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND)
             == 0
         {
             self.decay(Duration(UPDATE_EVERY_N_SECS * TICKS_PER_SIM_SECOND), world);
@@ -156,6 +160,25 @@ impl TripListener for VegetableFarm {
     }
 }
 
+impl VegetableFarm {
+    pub fn restore(id: VegetableFarmID, saved: &VegetableFarm, _: &mut World) -> VegetableFarm {
+        VegetableFarm {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for VegetableFarm {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<VegetableFarm>();
     auto_setup(system);
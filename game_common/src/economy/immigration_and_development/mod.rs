@@ -1,7 +1,8 @@
-use kay::{World, Actor, ActorSystem};
-use compact::COption;
+use kay::{World, Actor, ActorSystem, TypedID};
+use compact::{COption, CString};
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use land_use::buildings::{UnitType, Building, BuildingID, UnitIdx};
-use simulation::{Sleeper, SleeperID, Instant, SimulationID, Duration};
+use simulation::{Sleeper, SleeperID, Instant, SimulationID, Duration, WakeUpHandle};
 use util::random::{seed, Rng};
 
 use economy::households::family::FamilyID;
@@ -20,7 +21,7 @@ use planning::{PlanManagerID, Proposal, PrototypeID, Plan, GestureID, Gesture, G
 // TODO: somehow get rid of this horrible duplication by having something like
 // a pointer to an abstract Household trait...
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum HouseholdTypeToSpawn {
     Family,
     GroceryShop,
@@ -58,7 +59,7 @@ pub fn building_style_for(household_type: HouseholdTypeToSpawn) -> BuildingStyle
     }
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct ImmigrationManager {
     id: ImmigrationManagerID,
     simulation: SimulationID,
@@ -84,7 +85,7 @@ impl ImmigrationManager {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum ImmigrationManagerState {
     Idle,
     FindingBuilding(HouseholdTypeToSpawn),
@@ -211,12 +212,13 @@ impl ImmigrationManager {
     }
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct DevelopmentManager {
     id: DevelopmentManagerID,
     simulation: SimulationID,
     plan_manager: PlanManagerID,
     building_to_develop: COption<BuildingStyle>,
+    give_up_wake_up: COption<WakeUpHandle>,
 }
 
 impl DevelopmentManager {
@@ -231,6 +233,7 @@ impl DevelopmentManager {
             simulation,
             plan_manager,
             building_to_develop: COption(None),
+            give_up_wake_up: COption(None),
         }
     }
 
@@ -239,8 +242,11 @@ impl DevelopmentManager {
             println!("Trying to develop {:?}", building_style);
             self.building_to_develop = COption(Some(building_style));
             VacantLot::global_broadcast(world).suggest_lot(building_style, self.id, world);
-            self.simulation
-                .wake_up_in(Duration::from_minutes(10).into(), self.id.into(), world);
+            self.give_up_wake_up = COption(Some(self.simulation.wake_up_in(
+                Duration::from_minutes(10).into(),
+                self.id.into(),
+                world,
+            )));
         }
     }
 
@@ -265,6 +271,9 @@ impl DevelopmentManager {
                     world,
                 );
                 self.building_to_develop = COption(None);
+                if let Some(give_up_wake_up) = self.give_up_wake_up.take() {
+                    self.simulation.cancel_wake_up(give_up_wake_up, world);
+                }
             }
         }
     }
@@ -272,7 +281,29 @@ impl DevelopmentManager {
 
 impl Sleeper for DevelopmentManager {
     fn wake(&mut self, _: Instant, _world: &mut World) {
+        // didn't get a suggested lot in time
         self.building_to_develop = COption(None);
+        self.give_up_wake_up = COption(None);
+    }
+}
+
+impl Persistent for ImmigrationManager {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
+impl Persistent for DevelopmentManager {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
     }
 }
 
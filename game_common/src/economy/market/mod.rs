@@ -1,8 +1,9 @@
-use kay::{ActorSystem, Fate, World, Actor};
-use compact::{CVec, CDict};
+use kay::{ActorSystem, Fate, World, Actor, TypedID};
+use compact::{CVec, CDict, CString};
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use super::resources::{Inventory, Entry, Resource, ResourceAmount};
 use super::households::OfferID;
-use simulation::{TimeOfDayRange, Duration, Instant};
+use simulation::{WeeklyTimeOfDayRange, Duration, Instant};
 use transport::pathfinding::{RoughLocationID, LocationRequesterID};
 
 #[derive(Compact, Clone, Serialize, Deserialize)]
@@ -36,7 +37,7 @@ pub trait EvaluationRequester {
     fn on_result(&mut self, result: &EvaluatedSearchResult, world: &mut World);
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Market {
     id: MarketID,
     offers_by_resource: CDict<Resource, CVec<OfferID>>,
@@ -89,10 +90,10 @@ impl Market {
 pub struct EvaluatedDeal {
     pub offer: OfferID,
     pub deal: Deal,
-    pub opening_hours: TimeOfDayRange,
+    pub opening_hours: WeeklyTimeOfDayRange,
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct EvaluatedSearchResult {
     pub resource: Resource,
     pub evaluated_deals: CVec<EvaluatedDeal>,
@@ -101,7 +102,7 @@ pub struct EvaluatedSearchResult {
 use transport::pathfinding::{PreciseLocation, LocationRequester, DistanceRequester,
 DistanceRequesterID};
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct TripCostEstimator {
     id: TripCostEstimatorID,
     requester: EvaluationRequesterID,
@@ -220,6 +221,39 @@ impl DistanceRequester for TripCostEstimator {
     }
 }
 
+impl Persistent for Market {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
+impl TripCostEstimator {
+    pub fn restore(
+        id: TripCostEstimatorID,
+        saved: &TripCostEstimator,
+        _: &mut World,
+    ) -> TripCostEstimator {
+        TripCostEstimator {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for TripCostEstimator {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<Market>();
     system.register::<TripCostEstimator>();
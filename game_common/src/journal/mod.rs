@@ -0,0 +1,574 @@
+//! The input journal records every external input (planning gestures, implementing
+//! proposals, changing the simulation speed) together with the instant it was applied at.
+//! Replaying a journal into a fresh world feeds the same inputs at the same instants,
+//! which reproduces the same city - and the same crashes.
+//!
+//! Inputs are applied between ticks, so a replay holds the simulation before the tick
+//! of each entry until it applied the entry, just like live inputs are applied.
+//!
+//! Journals are written as one JSON entry per line and flushed after every entry
+//! (except intermediate steps of dragging), so they are complete up to the moment
+//! the simulation broke.
+
+use kay::{World, ActorSystem, MachineID, External};
+use compact::{CVec, CHashMap, CString};
+use descartes::P2;
+use simulation::{Instant, Ticks, SimulationID, Simulatable, SimulatableID};
+use planning::{PlanManagerID, ProposalID, GestureID, GestureIntent, PlanExport, PlayerID, PlayerToken};
+use planning::review::GovernanceSettings;
+use planning::branching::MergeResolution;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use serde_json;
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub enum JournaledInput {
+    StartNewGesture {
+        proposal_id: ProposalID,
+        machine: u8,
+        gesture_id: GestureID,
+        intent: GestureIntent,
+        start: P2,
+        token: PlayerToken,
+    },
+    FinishGesture {
+        machine: u8,
+    },
+    AddControlPoint {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        new_point: P2,
+        add_to_end: bool,
+        commit: bool,
+        token: PlayerToken,
+    },
+    MoveControlPoint {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        point_index: u32,
+        new_position: P2,
+        is_move_finished: bool,
+        token: PlayerToken,
+    },
+    SetIntent {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        new_intent: GestureIntent,
+        is_move_finished: bool,
+        token: PlayerToken,
+    },
+    DeleteGesture {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        token: PlayerToken,
+    },
+    InsertControlPoint {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        point_index: u32,
+        new_point: P2,
+        token: PlayerToken,
+    },
+    RemoveControlPoint {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        point_index: u32,
+        token: PlayerToken,
+    },
+    SplitGesture {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        point_index: u32,
+        new_gesture_id: GestureID,
+        token: PlayerToken,
+    },
+    JoinGestures {
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        other_gesture_id: GestureID,
+        token: PlayerToken,
+    },
+    Undo {
+        proposal_id: ProposalID,
+        token: PlayerToken,
+    },
+    Redo {
+        proposal_id: ProposalID,
+        token: PlayerToken,
+    },
+    Implement {
+        proposal_id: ProposalID,
+    },
+    Revert {
+        proposal_id: Option<ProposalID>,
+        token: PlayerToken,
+    },
+    RenameProposal {
+        proposal_id: ProposalID,
+        name: CString,
+        token: PlayerToken,
+    },
+    DescribeProposal {
+        proposal_id: ProposalID,
+        description: CString,
+        token: PlayerToken,
+    },
+    DeleteProposal {
+        proposal_id: ProposalID,
+        token: PlayerToken,
+    },
+    SetGovernance {
+        settings: GovernanceSettings,
+    },
+    SubmitForReview {
+        proposal_id: ProposalID,
+        token: PlayerToken,
+    },
+    Vote {
+        proposal_id: ProposalID,
+        token: PlayerToken,
+        approve: bool,
+    },
+    AddPlayer {
+        token: PlayerToken,
+        player: PlayerID,
+    },
+    ImportProposal {
+        proposal_id: ProposalID,
+        export: PlanExport,
+        importer: Option<PlayerToken>,
+    },
+    ForkProposal {
+        source_id: ProposalID,
+        proposal_id: ProposalID,
+        token: PlayerToken,
+    },
+    MergeProposals {
+        into: ProposalID,
+        from: ProposalID,
+        resolutions: CHashMap<GestureID, MergeResolution>,
+        token: PlayerToken,
+    },
+    SetSpeed {
+        speed: u16,
+    },
+    /// Recorded first, so a replay can start from the same world seed
+    WorldSeed {
+        seed: u64,
+    },
+}
+
+impl JournaledInput {
+    /// Inputs that are quickly followed by a finishing one, like every step of dragging
+    fn is_intermediate(&self) -> bool {
+        match *self {
+            JournaledInput::AddControlPoint { commit, .. } => !commit,
+            JournaledInput::MoveControlPoint {
+                is_move_finished, ..
+            }
+            | JournaledInput::SetIntent {
+                is_move_finished, ..
+            } => !is_move_finished,
+            _ => false,
+        }
+    }
+}
+
+/// An input that was applied right before the tick of `at`
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub at: Instant,
+    pub input: JournaledInput,
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(::std::io::Error),
+    Format { line: usize, error: serde_json::Error },
+}
+
+impl ::std::fmt::Display for JournalError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            JournalError::Io(ref err) => write!(f, "Couldn't access journal file: {}", err),
+            JournalError::Format { line, ref error } => {
+                write!(f, "Journal entry in line {} is malformed: {}", line, error)
+            }
+        }
+    }
+}
+
+pub fn read_from(path: &str) -> Result<Vec<JournalEntry>, JournalError> {
+    let file = File::open(path).map_err(JournalError::Io)?;
+
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map(|line| !line.trim().is_empty()).unwrap_or(true))
+        .map(|(line_idx, line)| {
+            let line = line.map_err(JournalError::Io)?;
+            serde_json::from_str(&line).map_err(|error| JournalError::Format {
+                line: line_idx + 1,
+                error,
+            })
+        }).collect()
+}
+
+pub fn recorded_world_seed(entries: &[JournalEntry]) -> Option<u64> {
+    entries.iter().find_map(|entry| match entry.input {
+        JournaledInput::WorldSeed { seed } => Some(seed),
+        _ => None,
+    })
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum JournalMode {
+    Off,
+    Recording,
+    Replaying,
+}
+
+#[derive(Compact, Clone)]
+pub struct InputJournal {
+    id: InputJournalID,
+    simulation: SimulationID,
+    plan_manager: PlanManagerID,
+    mode: JournalMode,
+    path: CString,
+    writer: External<Option<BufWriter<File>>>,
+    /// Inputs are applied between ticks, so they are applied before this one
+    next_tick: Instant,
+    // sorted latest-first, so due entries can be popped off the end
+    to_replay: CVec<JournalEntry>,
+}
+
+impl InputJournal {
+    pub fn spawn(
+        id: InputJournalID,
+        simulation: SimulationID,
+        plan_manager: PlanManagerID,
+        _: &mut World,
+    ) -> InputJournal {
+        InputJournal {
+            id,
+            simulation,
+            plan_manager,
+            mode: JournalMode::Off,
+            path: CString::new(),
+            writer: External::new(None),
+            next_tick: Instant::new(0),
+            to_replay: CVec::new(),
+        }
+    }
+
+    /// Starts a new journal at `path`, overwriting any previous one
+    pub fn record_to(&mut self, path: &CString, _: &mut World) {
+        match File::create(&**path) {
+            Ok(file) => {
+                println!("Recording inputs to {}", &**path);
+                self.path = path.clone();
+                *self.writer = Some(BufWriter::new(file));
+                self.mode = JournalMode::Recording;
+            }
+            Err(err) => println!("Couldn't create journal {}: {}", &**path, err),
+        }
+    }
+
+    pub fn replay(&mut self, entries: &CVec<JournalEntry>, world: &mut World) {
+        println!("Replaying {} journaled inputs", entries.len());
+        self.to_replay = entries.iter().rev().cloned().collect();
+        self.mode = JournalMode::Replaying;
+        self.hold_for_next_entry(world);
+    }
+
+    fn hold_for_next_entry(&mut self, world: &mut World) {
+        match self.to_replay.last() {
+            Some(entry) => self.simulation.hold_for_replay_at(entry.at, world),
+            None => {
+                println!("Replayed all journaled inputs before tick {}", self.next_tick.ticks());
+                self.mode = JournalMode::Off;
+            }
+        }
+    }
+
+    /// Called by the simulation while it holds before the tick of `instant`
+    pub fn apply_due(&mut self, instant: Instant, world: &mut World) {
+        if self.mode != JournalMode::Replaying {
+            return;
+        }
+
+        while self
+            .to_replay
+            .last()
+            .map(|entry| entry.at <= instant)
+            .unwrap_or(false)
+        {
+            let entry = self
+                .to_replay
+                .pop()
+                .expect("just checked that there are entries left");
+            self.apply(&entry.input, world);
+        }
+
+        self.hold_for_next_entry(world);
+    }
+
+    pub fn record(&mut self, input: &JournaledInput, _: &mut World) {
+        if self.mode != JournalMode::Recording {
+            return;
+        }
+
+        let entry = JournalEntry {
+            at: self.next_tick,
+            input: input.clone(),
+        };
+
+        let written = match *self.writer {
+            Some(ref mut writer) => serde_json::to_string(&entry)
+                .map_err(|err| err.to_string())
+                .and_then(|line| {
+                    writeln!(writer, "{}", line)
+                        .and_then(|_| {
+                            // the tick flushes what is left
+                            if input.is_intermediate() {
+                                Ok(())
+                            } else {
+                                writer.flush()
+                            }
+                        }).map_err(|err| err.to_string())
+                }),
+            None => Err("journal isn't open".to_owned()),
+        };
+
+        if let Err(err) = written {
+            println!("Writing to journal {} failed, stopping: {}", &*self.path, err);
+            self.mode = JournalMode::Off;
+        }
+    }
+
+    fn apply(&self, input: &JournaledInput, world: &mut World) {
+        match *input {
+            JournaledInput::StartNewGesture {
+                proposal_id,
+                machine,
+                gesture_id,
+                ref intent,
+                start,
+                token,
+            } => self.plan_manager.start_new_gesture(
+                proposal_id,
+                MachineID(machine),
+                gesture_id,
+                intent.clone(),
+                start,
+                token,
+                world,
+            ),
+            JournaledInput::FinishGesture { machine } => {
+                self.plan_manager.finish_gesture(MachineID(machine), world)
+            }
+            JournaledInput::AddControlPoint {
+                proposal_id,
+                gesture_id,
+                new_point,
+                add_to_end,
+                commit,
+                token,
+            } => self.plan_manager.add_control_point(
+                proposal_id,
+                gesture_id,
+                new_point,
+                add_to_end,
+                commit,
+                token,
+                world,
+            ),
+            JournaledInput::MoveControlPoint {
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_position,
+                is_move_finished,
+                token,
+            } => self.plan_manager.move_control_point(
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_position,
+                is_move_finished,
+                token,
+                world,
+            ),
+            JournaledInput::SetIntent {
+                proposal_id,
+                gesture_id,
+                ref new_intent,
+                is_move_finished,
+                token,
+            } => self.plan_manager.set_intent(
+                proposal_id,
+                gesture_id,
+                new_intent.clone(),
+                is_move_finished,
+                token,
+                world,
+            ),
+            JournaledInput::DeleteGesture {
+                proposal_id,
+                gesture_id,
+                token,
+            } => self
+                .plan_manager
+                .delete_gesture(proposal_id, gesture_id, token, world),
+            JournaledInput::InsertControlPoint {
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_point,
+                token,
+            } => self.plan_manager.insert_control_point(
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_point,
+                token,
+                world,
+            ),
+            JournaledInput::RemoveControlPoint {
+                proposal_id,
+                gesture_id,
+                point_index,
+                token,
+            } => self.plan_manager.remove_control_point(
+                proposal_id,
+                gesture_id,
+                point_index,
+                token,
+                world,
+            ),
+            JournaledInput::SplitGesture {
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_gesture_id,
+                token,
+            } => self.plan_manager.split_gesture(
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_gesture_id,
+                token,
+                world,
+            ),
+            JournaledInput::JoinGestures {
+                proposal_id,
+                gesture_id,
+                other_gesture_id,
+                token,
+            } => self.plan_manager.join_gestures(
+                proposal_id,
+                gesture_id,
+                other_gesture_id,
+                token,
+                world,
+            ),
+            JournaledInput::Undo { proposal_id, token } => {
+                self.plan_manager.undo(proposal_id, token, world)
+            }
+            JournaledInput::Redo { proposal_id, token } => {
+                self.plan_manager.redo(proposal_id, token, world)
+            }
+            JournaledInput::Implement { proposal_id } => {
+                self.plan_manager.implement(proposal_id, world)
+            }
+            JournaledInput::Revert { proposal_id, token } => {
+                self.plan_manager.revert(proposal_id, token, world)
+            }
+            JournaledInput::RenameProposal {
+                proposal_id,
+                ref name,
+                token,
+            } => self
+                .plan_manager
+                .rename_proposal(proposal_id, name.clone(), token, world),
+            JournaledInput::DescribeProposal {
+                proposal_id,
+                ref description,
+                token,
+            } => {
+                self.plan_manager
+                    .describe_proposal(proposal_id, description.clone(), token, world)
+            }
+            JournaledInput::DeleteProposal { proposal_id, token } => {
+                self.plan_manager.delete_proposal(proposal_id, token, world)
+            }
+            JournaledInput::SetGovernance { settings } => {
+                self.plan_manager.set_governance(settings, world)
+            }
+            JournaledInput::SubmitForReview { proposal_id, token } => self
+                .plan_manager
+                .submit_for_review(proposal_id, token, world),
+            JournaledInput::Vote {
+                proposal_id,
+                token,
+                approve,
+            } => self.plan_manager.vote(proposal_id, token, approve, world),
+            JournaledInput::AddPlayer { token, player } => {
+                self.plan_manager.add_player(token, player, world)
+            }
+            JournaledInput::ImportProposal {
+                proposal_id,
+                ref export,
+                importer,
+            } => self
+                .plan_manager
+                .import_proposal(proposal_id, export.clone(), importer, world),
+            JournaledInput::ForkProposal {
+                source_id,
+                proposal_id,
+                token,
+            } => self
+                .plan_manager
+                .fork_proposal(source_id, proposal_id, token, world),
+            JournaledInput::MergeProposals {
+                into,
+                from,
+                ref resolutions,
+                token,
+            } => self
+                .plan_manager
+                .merge_proposals(into, from, resolutions.clone(), token, world),
+            JournaledInput::SetSpeed { speed } => self.simulation.set_speed(speed, world),
+            // applied before the world is spawned, see `recorded_world_seed`
+            JournaledInput::WorldSeed { .. } => {}
+        }
+    }
+}
+
+impl Simulatable for InputJournal {
+    fn tick(&mut self, _dt: f32, current_instant: Instant, _: &mut World) {
+        self.next_tick = current_instant + Ticks(1);
+
+        if let Some(ref mut writer) = *self.writer {
+            if let Err(err) = writer.flush() {
+                println!("Flushing journal {} failed: {}", &*self.path, err);
+            }
+        }
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<InputJournal>();
+    auto_setup(system);
+}
+
+pub fn spawn(
+    world: &mut World,
+    simulation: SimulationID,
+    plan_manager: PlanManagerID,
+) -> InputJournalID {
+    InputJournalID::spawn(simulation, plan_manager, world)
+}
+
+mod kay_auto;
+pub use self::kay_auto::*;
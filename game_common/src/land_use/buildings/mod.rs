@@ -1,9 +1,10 @@
-use kay::{ActorSystem, World, Actor, Fate};
-use compact::{CVec, COption};
+use kay::{ActorSystem, World, Actor, Fate, TypedID};
+use compact::{CVec, COption, CString};
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use descartes::P2;
 
 use transport::lane::Lane;
-use simulation::Ticks;
+use simulation::{Ticks, WakeUpHandle};
 use construction::{ConstructionID, Constructable, ConstructableID};
 use planning::{Prototype, PrototypeKind};
 
@@ -15,11 +16,12 @@ use transport::pathfinding::PreciseLocation;
 use economy::immigration_and_development::ImmigrationManagerID;
 use land_use::zone_planning::{Lot, LandUse};
 use browser_ui::BrowserUIID;
+use planning::ProposalID;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Unit(Option<HouseholdID>, UnitType);
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum UnitType {
     Dwelling,
     Retail,
@@ -55,7 +57,7 @@ impl BuildingStyle {
     }
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Building {
     id: BuildingID,
     units: CVec<Unit>,
@@ -63,7 +65,7 @@ pub struct Building {
     pub location: Option<PreciseLocation>,
     style: BuildingStyle,
     being_destroyed_for: COption<ConstructionID>,
-    started_reconnect: bool,
+    reconnect_wake_up: COption<WakeUpHandle>,
 }
 
 //use stagemaster::geometry::add_debug_line;
@@ -74,7 +76,7 @@ impl Building {
 
         rendering::on_add(id, lot, style, world);
 
-        Simulation::local_first(world).wake_up_in(
+        let reconnect_wake_up = Simulation::local_first(world).wake_up_in(
             Ticks::from(Duration::from_minutes(10)),
             id.into(),
             world,
@@ -87,7 +89,7 @@ impl Building {
             location: None,
             style,
             being_destroyed_for: COption(None),
-            started_reconnect: false,
+            reconnect_wake_up: COption(Some(reconnect_wake_up)),
         }
     }
 
@@ -195,36 +197,24 @@ impl Attachee for Building {
                 .location = new;
         } else {
             self.location = None;
-            Simulation::local_first(world).wake_up_in(
-                Ticks::from(Duration::from_minutes(10)),
-                self.id_as(),
-                world,
-            );
+            self.schedule_reconnect(world);
         }
     }
 }
 
 impl Sleeper for Building {
     fn wake(&mut self, _instant: Instant, world: &mut World) {
-        if self.started_reconnect {
-            if self.location.is_none() {
-                // TODO: do we still need to destroy here?
-            } else {
-                self.started_reconnect = false;
-            }
-        } else {
+        self.reconnect_wake_up = COption(None);
+
+        if self.location.is_none() {
             println!("Trying to connect building {:?}", self.id);
             Lane::global_broadcast(world).try_reconnect_building(
                 self.id,
                 self.lot.best_road_connection().0,
                 world,
             );
-            Simulation::local_first(world).wake_up_in(
-                Ticks::from(Duration::from_minutes(10)),
-                self.id_as(),
-                world,
-            );
-            self.started_reconnect = true;
+            // try again later if none of the lanes could take us
+            self.schedule_reconnect(world);
         }
     }
 }
@@ -240,6 +230,22 @@ impl Building {
             println!("{:?} reconnected to {:?}", self.id, new_location);
             self.location = Some(new_location);
             new_location.node.add_attachee(self.id_as(), world);
+
+            if let Some(reconnect_wake_up) = self.reconnect_wake_up.take() {
+                Simulation::local_first(world).cancel_wake_up(reconnect_wake_up, world);
+            }
+        }
+    }
+
+    fn schedule_reconnect(&mut self, world: &mut World) {
+        let simulation = Simulation::local_first(world);
+        let in_ten_minutes = Ticks::from(Duration::from_minutes(10));
+
+        if let Some(reconnect_wake_up) = *self.reconnect_wake_up {
+            simulation.move_wake_up(reconnect_wake_up, in_ten_minutes, world);
+        } else {
+            self.reconnect_wake_up =
+                COption(Some(simulation.wake_up_in(in_ten_minutes, self.id_as(), world)));
         }
     }
 }
@@ -282,6 +288,47 @@ impl Building {
     pub fn get_ui_info(&mut self, requester: BrowserUIID, world: &mut World) {
         requester.on_building_ui_info(self.id, self.style, self.all_households().into(), world);
     }
+
+    pub fn report_displaced_households(
+        &mut self,
+        proposal_id: ProposalID,
+        requester: BrowserUIID,
+        world: &mut World,
+    ) {
+        let (residents, workers): (Vec<_>, Vec<_>) = self
+            .units
+            .iter()
+            .filter_map(|&Unit(user, unit_type)| user.map(|household| (household, unit_type)))
+            .partition(|&(_, unit_type)| unit_type == UnitType::Dwelling);
+
+        requester.on_displaced_households(
+            proposal_id,
+            self.id,
+            self.style,
+            residents.into_iter().map(|(household, _)| household).collect(),
+            workers.into_iter().map(|(household, _)| household).collect(),
+            world,
+        );
+    }
+}
+
+impl Building {
+    pub fn restore(id: BuildingID, saved: &Building, _: &mut World) -> Building {
+        Building {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for Building {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
 }
 
 pub fn setup(system: &mut ActorSystem) {
@@ -1,10 +1,22 @@
 use kay::{ World, Actor};
 use super::{Building, Lot, BuildingID, BuildingStyle};
 use browser_ui::{BrowserUI, BrowserUIID};
+use util::random::{seed, RngCore};
+
+// derived on the server, because browsers don't know the world seed
+fn architecture_seed(id: BuildingID) -> u64 {
+    seed(id).next_u64()
+}
 
 impl Building {
     pub fn get_render_info(&mut self, ui: BrowserUIID, world: &mut World) {
-        ui.on_building_constructed(self.id, self.lot.clone(), self.style, world)
+        ui.on_building_constructed(
+            self.id,
+            self.lot.clone(),
+            self.style,
+            architecture_seed(self.id),
+            world,
+        )
     }
 }
 
@@ -13,6 +25,7 @@ pub fn on_add(id: BuildingID, lot: &Lot, building_type: BuildingStyle, world: &m
         id,
         lot.clone(),
         building_type,
+        architecture_seed(id),
         world,
     );
 }
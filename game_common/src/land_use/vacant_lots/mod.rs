@@ -1,5 +1,6 @@
-use kay::{World, Fate, ActorSystem};
-use compact::CVec;
+use kay::{World, Fate, ActorSystem, TypedID};
+use compact::{CVec, CString};
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use descartes::{N, P2, V2, Area, WithUniqueOrthogonal, ClosedLinePath, LinePath, AreaError};
 use ordered_float::OrderedFloat;
 
@@ -12,7 +13,7 @@ use itertools::{Itertools, MinMaxResult};
 use construction::{ConstructionID, Constructable, ConstructableID};
 use planning::{Prototype, PrototypeID};
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct VacantLot {
     pub id: VacantLotID,
     pub lot: Lot,
@@ -273,6 +274,25 @@ impl Constructable for VacantLot {
     }
 }
 
+impl VacantLot {
+    pub fn restore(id: VacantLotID, saved: &VacantLot, _: &mut World) -> VacantLot {
+        VacantLot {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for VacantLot {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<VacantLot>();
     auto_setup(system);
@@ -1,13 +1,16 @@
 use compact::CVec;
 use descartes::{P2, V2, Area, ClosedLinePath, LinePath, PointContainer,
-AreaError, WithUniqueOrthogonal};
+AreaError, WithUniqueOrthogonal, BoundingBox};
 use land_use::buildings::BuildingStyle;
 use ordered_float::OrderedFloat;
 
 use transport::transport_planning::{RoadPrototype, LanePrototype};
 
 use planning::{PlanHistory, VersionedGesture, PlanResult, Prototype, PrototypeID,
-PrototypeKind, GestureIntent};
+PrototypeKind, GestureIntent, GestureID, StepID, PlanError};
+use util::generational_cache::GenerationalCache;
+use util::grid_accelerator::{bounding_box_of_area, bounding_box_of_points,
+bounding_boxes_overlap};
 
 #[derive(Compact, Clone, Debug, Serialize, Deserialize)]
 pub enum ZoneIntent {
@@ -104,10 +107,164 @@ pub enum LotOccupancy {
     Occupied(BuildingStyle),
 }
 
+/// Lots only depend on their gesture and the paved and built areas around them,
+/// so they can be reused as long as none of these change
+#[derive(Default)]
+pub struct ZonePlanningCache {
+    building_lots: GenerationalCache<(GestureID, StepID, Vec<PrototypeID>), Option<Prototype>>,
+    vacant_lots: GenerationalCache<(GestureID, StepID, Vec<PrototypeID>), Vec<Prototype>>,
+}
+
+impl ZonePlanningCache {
+    pub fn finish_generation(&mut self) {
+        self.building_lots.finish_generation();
+        self.vacant_lots.finish_generation();
+    }
+}
+
+fn building_lot(
+    lot: &Lot,
+    building_style: BuildingStyle,
+    gesture_step_id: StepID,
+    relevant_paved_areas: &[&(&Area, PrototypeID, BoundingBox)],
+) -> Result<Option<Prototype>, AreaError> {
+    let mut area = lot.area.clone();
+    let mut influenced_id = PrototypeID::from_influences(gesture_step_id);
+
+    for &&(paved_area_shape, paved_id, _) in relevant_paved_areas {
+        let (has_split, maybe_main_piece) = {
+            if let Some(split) = area.split_if_intersects(paved_area_shape) {
+                (
+                    true,
+                    split
+                        .a_minus_b()?
+                        .disjoint()
+                        .into_iter()
+                        .find(|piece| piece.contains(lot.center_point())),
+                )
+            } else {
+                (false, None)
+            }
+        };
+
+        if has_split {
+            if let Some(main_piece) = maybe_main_piece {
+                area = main_piece;
+                influenced_id = influenced_id.add_influences(paved_id)
+            } else {
+                println!("No piece contains center");
+                return Ok(None);
+            }
+        }
+    }
+
+    Ok(Some(Prototype {
+        kind: PrototypeKind::Lot(LotPrototype {
+            lot: Lot {
+                area,
+                ..lot.clone()
+            },
+            occupancy: LotOccupancy::Occupied(building_style),
+        }),
+        id: influenced_id,
+    }))
+}
+
+/// The area enclosed by the points of a zone gesture
+pub fn zone_area(points: &[P2]) -> Option<Area> {
+    LinePath::new(points.iter().chain(points.first()).cloned().collect())
+        .and_then(ClosedLinePath::new)
+        .map(|outline| Area::new_simple(outline.to_clockwise()))
+}
+
+fn vacant_lots_in_zone(
+    land_use: LandUse,
+    points: &CVec<P2>,
+    gesture_step_id: StepID,
+    relevant_paved_or_built_areas: &[&(&Area, PrototypeID, BoundingBox)],
+    relevant_paved_areas: &[&(&Area, PrototypeID, BoundingBox)],
+) -> Vec<Prototype> {
+    let shape = match zone_area(points) {
+        Some(shape) => shape,
+        None => return Vec::new(),
+    };
+
+    let mut shapes = vec![(shape, PrototypeID::from_influences(gesture_step_id))];
+
+    for &&(paved_or_built_area, paved_id, _) in relevant_paved_or_built_areas {
+        shapes = shapes
+            .into_iter()
+            .flat_map(|(shape, current_id)| {
+                if let Some(split) = shape.split_if_intersects(paved_or_built_area) {
+                    split
+                        .a_minus_b()
+                        .into_iter()
+                        .flat_map(|cut_shapes| {
+                            cut_shapes
+                                .disjoint()
+                                .into_iter()
+                                .enumerate()
+                                .map(|(i, cut_shape)| {
+                                    (cut_shape, current_id.add_influences((paved_id, i)))
+                                })
+                        }).collect()
+                } else {
+                    vec![(shape.clone(), current_id)]
+                }
+            }).collect()
+    }
+
+    shapes
+        .into_iter()
+        .filter_map(|(area, id)| {
+            let road_boundary_segments = area.primitives[0]
+                .boundary
+                .path()
+                .segments()
+                .filter(|&segment| {
+                    // TODO: this is a horribly slow way to find connection points
+                    relevant_paved_areas
+                        .iter()
+                        .any(|&&(paved_area, _, _)| paved_area.contains(segment.midpoint()))
+                }).collect::<Vec<_>>();
+
+            if road_boundary_segments.is_empty() {
+                println!("No road boundary found");
+                None
+            } else {
+                let mut road_boundary_paths: Vec<LinePath> = road_boundary_segments
+                    .into_iter()
+                    .map(|segment| {
+                        LinePath::new(vec![segment.start(), segment.end()].into()).unwrap()
+                    }).collect();
+
+                let _ = ::descartes::util::join_within_vec(
+                    &mut road_boundary_paths,
+                    |path_a, path_b| path_a.concat(path_b).map(Some),
+                );
+
+                Some(Prototype {
+                    kind: PrototypeKind::Lot(LotPrototype {
+                        lot: Lot {
+                            land_uses: vec![land_use].into(),
+                            max_height: 0,
+                            set_back: 0,
+                            road_boundaries: road_boundary_paths.into(),
+                            area,
+                        },
+                        occupancy: LotOccupancy::Vacant,
+                    }),
+                    id,
+                })
+            }
+        }).collect()
+}
+
 pub fn calculate_prototypes(
     history: &PlanHistory,
     current_result: &PlanResult,
-) -> Result<Vec<Prototype>, AreaError> {
+    cache: &mut ZonePlanningCache,
+) -> Result<Vec<Prototype>, PlanError> {
     let paved_area_areas = current_result
         .prototypes
         .values()
@@ -117,61 +274,38 @@ pub fn calculate_prototypes(
                 id,
             } = *prototype
             {
-                Some((shape, id))
+                Some((shape, id, bounding_box_of_area(shape)))
             } else {
                 None
             }
         }).collect::<Vec<_>>();
 
     let building_prototypes = history
-        .gestures
-        .values()
-        .map(|VersionedGesture(gesture, gesture_step_id)| {
+        .live_gestures()
+        .map(|(gesture_id, VersionedGesture(gesture, gesture_step_id))| {
             if let GestureIntent::Building(BuildingIntent {
                 ref lot,
                 building_style,
             }) = gesture.intent
             {
-                let mut area = lot.area.clone();
-                let mut influenced_id = PrototypeID::from_influences(gesture_step_id);
-
-                for (paved_area_shape, paved_id) in &paved_area_areas {
-                    let (has_split, maybe_main_piece) = {
-                        if let Some(split) = area.split_if_intersects(&paved_area_shape) {
-                            (
-                                true,
-                                split
-                                    .a_minus_b()?
-                                    .disjoint()
-                                    .into_iter()
-                                    .find(|piece| piece.contains(lot.center_point())),
-                            )
-                        } else {
-                            (false, None)
-                        }
-                    };
-
-                    if has_split {
-                        if let Some(main_piece) = maybe_main_piece {
-                            area = main_piece;
-                            influenced_id = influenced_id.add_influences(paved_id)
-                        } else {
-                            println!("No piece contains center");
-                            return Ok(None);
-                        }
-                    }
-                }
-
-                Ok(Some(Prototype {
-                    kind: PrototypeKind::Lot(LotPrototype {
-                        lot: Lot {
-                            area,
-                            ..lot.clone()
-                        },
-                        occupancy: LotOccupancy::Occupied(building_style),
-                    }),
-                    id: influenced_id,
-                }))
+                let lot_bounding_box = bounding_box_of_area(&lot.area);
+                let relevant_paved_areas = paved_area_areas
+                    .iter()
+                    .filter(|(_, _, bounding_box)| {
+                        bounding_boxes_overlap(&lot_bounding_box, bounding_box)
+                    }).collect::<Vec<_>>();
+                let key = (
+                    *gesture_id,
+                    *gesture_step_id,
+                    relevant_paved_areas.iter().map(|&&(_, id, _)| id).collect(),
+                );
+
+                cache.building_lots.try_get_or_insert_with(key, || {
+                    building_lot(lot, building_style, *gesture_step_id, &relevant_paved_areas)
+                        .map_err(|err| {
+                            PlanError::from_area_error(&err, vec![*gesture_id], lot.center_point())
+                        })
+                })
             } else {
                 Ok(None)
             }
@@ -272,113 +406,52 @@ pub fn calculate_prototypes(
                     ..
                 }) = prototype.kind
                 {
-                    (area, prototype.id)
+                    (area, prototype.id, bounding_box_of_area(area))
                 } else {
                     unreachable!()
                 }
             }).collect::<Vec<_>>();
 
-        let mut land_use_areas = history
-            .gestures
-            .values()
-            .filter_map(|VersionedGesture(gesture, step_id)| {
+        history
+            .live_gestures()
+            .filter_map(|(gesture_id, VersionedGesture(gesture, step_id))| {
                 if let GestureIntent::Zone(ZoneIntent::LandUse(land_use)) = gesture.intent {
-                    Some((land_use, &gesture.points, *step_id))
+                    Some((*gesture_id, land_use, &gesture.points, *step_id))
                 } else {
                     None
                 }
-            }).filter_map(|(land_use, points, step_id)| {
-                Some((
-                    land_use,
-                    Area::new_simple(
-                        ClosedLinePath::new(LinePath::new(
-                            points.iter().chain(points.first()).cloned().collect(),
-                        )?)?.to_clockwise(),
-                    ),
+            }).flat_map(|(gesture_id, land_use, points, step_id)| {
+                let zone_bounding_box = bounding_box_of_points(points.iter());
+                let overlaps_zone = |&&(_, _, ref bounding_box): &&(_, _, BoundingBox)| {
+                    bounding_boxes_overlap(&zone_bounding_box, bounding_box)
+                };
+                let relevant_paved_areas = paved_area_areas
+                    .iter()
+                    .filter(overlaps_zone)
+                    .collect::<Vec<_>>();
+                let relevant_paved_or_built_areas = paved_area_areas
+                    .iter()
+                    .chain(building_areas.iter())
+                    .filter(overlaps_zone)
+                    .collect::<Vec<_>>();
+                let key = (
+                    gesture_id,
                     step_id,
-                ))
-            }).collect::<Vec<_>>();
-
-        let paved_or_built_areas = || paved_area_areas.iter().chain(building_areas.iter());
-
-        let land_use_areas_influenced: Vec<(LandUse, Area, PrototypeID)> = land_use_areas
-            .into_iter()
-            .flat_map(|(land_use, shape, gesture_step_id)| {
-                let mut shapes = vec![(shape, PrototypeID::from_influences(gesture_step_id))];
-
-                for (paved_or_built_area, paved_id) in paved_or_built_areas() {
-                    shapes = shapes
-                        .into_iter()
-                        .flat_map(|(shape, current_id)| {
-                            if let Some(split) = shape.split_if_intersects(paved_or_built_area) {
-                                split
-                                    .a_minus_b()
-                                    .into_iter()
-                                    .flat_map(|cut_shapes| {
-                                        cut_shapes.disjoint().into_iter().enumerate().map(
-                                            |(i, cut_shape)| {
-                                                (
-                                                    cut_shape,
-                                                    current_id.add_influences((paved_id, i)),
-                                                )
-                                            },
-                                        )
-                                    }).collect()
-                            } else {
-                                vec![(shape.clone(), current_id)]
-                            }
-                        }).collect()
-                }
-
-                shapes
-                    .into_iter()
-                    .map(|(shape, id)| (land_use, shape, id))
-                    .collect::<Vec<_>>()
-            }).collect();
-
-        land_use_areas_influenced
-            .into_iter()
-            .filter_map(|(land_use, area, id)| {
-                let road_boundary_segments = area.primitives[0]
-                    .boundary
-                    .path()
-                    .segments()
-                    .filter(|&segment| {
-                        // TODO: this is a horribly slow way to find connection points
-                        paved_area_areas
-                            .iter()
-                            .any(|(paved_area, _)| paved_area.contains(segment.midpoint()))
-                    }).collect::<Vec<_>>();
-
-                if road_boundary_segments.is_empty() {
-                    println!("No road boundary found");
-                    None
-                } else {
-                    let mut road_boundary_paths: Vec<LinePath> = road_boundary_segments
-                        .into_iter()
-                        .map(|segment| {
-                            LinePath::new(vec![segment.start(), segment.end()].into()).unwrap()
-                        }).collect();
-
-                    let _ = ::descartes::util::join_within_vec(
-                        &mut road_boundary_paths,
-                        |path_a, path_b| path_a.concat(path_b).map(Some),
-                    );
-
-                    Some(Prototype {
-                        kind: PrototypeKind::Lot(LotPrototype {
-                            lot: Lot {
-                                land_uses: vec![land_use].into(),
-                                max_height: 0,
-                                set_back: 0,
-                                road_boundaries: road_boundary_paths.into(),
-                                area,
-                            },
-                            occupancy: LotOccupancy::Vacant,
-                        }),
-                        id,
-                    })
-                }
+                    relevant_paved_or_built_areas
+                        .iter()
+                        .map(|&&(_, id, _)| id)
+                        .collect(),
+                );
+
+                cache.vacant_lots.get_or_insert_with(key, || {
+                    vacant_lots_in_zone(
+                        land_use,
+                        points,
+                        step_id,
+                        &relevant_paved_or_built_areas,
+                        &relevant_paved_areas,
+                    )
+                })
             }).collect::<Vec<_>>()
     };
 
@@ -15,6 +15,8 @@ extern crate fnv;
 extern crate roaring;
 extern crate backtrace;
 extern crate uuid;
+extern crate serde;
+extern crate serde_json;
 
 pub extern crate compact;
 #[macro_use]
@@ -42,6 +44,8 @@ pub mod economy;
 pub mod land_use;
 pub mod style;
 pub mod browser_ui;
+pub mod savegame;
+pub mod journal;
 
 pub fn setup_all(system: &mut kay::ActorSystem) {
     for setup_fn in &[
@@ -52,6 +56,8 @@ pub fn setup_all(system: &mut kay::ActorSystem) {
         economy::setup,
         land_use::setup,
         browser_ui::setup,
+        savegame::setup,
+        journal::setup,
     ] {
         setup_fn(system)
     }
@@ -0,0 +1,360 @@
+//! Planners can try out alternative designs by forking a proposal from another one,
+//! and later combine the best parts by merging one proposal into another.
+//! Merges compare both proposals against the versions they were forked with, so only
+//! gestures that both proposals changed differently are conflicts that have to be resolved.
+
+use kay::{World, ActorSystem};
+use compact::{CVec, CHashMap};
+use browser_ui::BrowserUIID;
+use journal::{InputJournal, JournaledInput};
+use std::collections::HashMap;
+use super::{PlanManager, PlanManagerID, Plan, Proposal, ProposalID, ProposalMetadata,
+ProposalStatus, Gesture, GestureID, StepID, PlayerToken};
+
+/// Which version of a gesture that both proposals changed ends up in the merged proposal
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MergeResolution {
+    /// The version of the proposal that is merged into
+    Ours,
+    /// The version of the proposal that is merged from
+    Theirs,
+}
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct MergeConflict {
+    pub gesture_id: GestureID,
+    pub ours: Gesture,
+    pub theirs: Gesture,
+}
+
+/// Records that `step_id` of a proposal holds the `version` of `gesture_id`
+/// that it was copied from when forking or merging
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct CopiedVersion {
+    pub step_id: StepID,
+    pub gesture_id: GestureID,
+    pub version: StepID,
+}
+
+impl Proposal {
+    /// The latest version of every gesture that the proposal changes
+    fn changed_gestures(&self) -> CHashMap<GestureID, Gesture> {
+        let mut gestures = CHashMap::new();
+
+        for plan in self.current_history() {
+            for (gesture_id, gesture) in plan.gestures.pairs() {
+                gestures.insert(*gesture_id, gesture.clone());
+            }
+        }
+
+        gestures
+    }
+
+    /// For every gesture that the proposal changes, the step that created its latest version.
+    /// Gestures that were copied from another proposal keep the version they were copied at.
+    fn gesture_versions(&self) -> HashMap<GestureID, StepID> {
+        let copied = self
+            .metadata
+            .copied_versions
+            .iter()
+            .map(|copied| ((copied.step_id, copied.gesture_id), copied.version))
+            .collect::<HashMap<_, _>>();
+        let mut versions = HashMap::new();
+
+        for plan in self.current_history() {
+            for gesture_id in plan.gestures.keys() {
+                let version = copied
+                    .get(&(plan.step_id, *gesture_id))
+                    .cloned()
+                    .unwrap_or(plan.step_id);
+                versions.insert(*gesture_id, version);
+            }
+        }
+
+        versions
+    }
+
+    /// The versions of the gestures that the proposal was forked with
+    fn fork_point(&self) -> HashMap<GestureID, StepID> {
+        match self.metadata.fork_step {
+            Some(fork_step) => self
+                .metadata
+                .copied_versions
+                .iter()
+                .filter(|copied| copied.step_id == fork_step)
+                .map(|copied| (copied.gesture_id, copied.version))
+                .collect(),
+            None => HashMap::new(),
+        }
+    }
+}
+
+/// The versions of gestures that both proposals started out with,
+/// if one was forked from the other or both were forked from the same proposal
+fn common_base(
+    ours_id: ProposalID,
+    ours: &Proposal,
+    theirs_id: ProposalID,
+    theirs: &Proposal,
+) -> HashMap<GestureID, StepID> {
+    if theirs.metadata.forked_from == Some(ours_id) {
+        theirs.fork_point()
+    } else if ours.metadata.forked_from == Some(theirs_id) {
+        ours.fork_point()
+    } else if ours.metadata.forked_from.is_some()
+        && ours.metadata.forked_from == theirs.metadata.forked_from
+    {
+        let our_fork_point = ours.fork_point();
+        theirs
+            .fork_point()
+            .into_iter()
+            .filter(|&(gesture_id, version)| our_fork_point.get(&gesture_id) == Some(&version))
+            .collect()
+    } else {
+        HashMap::new()
+    }
+}
+
+struct MergePlan {
+    conflicts: CVec<MergeConflict>,
+    taken: Vec<(GestureID, Gesture, StepID)>,
+    their_versions: HashMap<GestureID, StepID>,
+}
+
+/// A three-way comparison of both proposals against their common base:
+/// gestures that only `theirs` changed are taken right away, gestures that `theirs` didn't
+/// change since the base or that are the same version in both are skipped,
+/// and only gestures that both changed differently are conflicts
+fn plan_merge(
+    ours_id: ProposalID,
+    ours: &Proposal,
+    theirs_id: ProposalID,
+    theirs: &Proposal,
+) -> MergePlan {
+    let base = common_base(ours_id, ours, theirs_id, theirs);
+    let our_gestures = ours.changed_gestures();
+    let our_versions = ours.gesture_versions();
+    let their_versions = theirs.gesture_versions();
+    let mut conflicts = CVec::new();
+    let mut taken = Vec::new();
+
+    for (gesture_id, their_gesture) in theirs.changed_gestures().pairs() {
+        let their_version = their_versions[gesture_id];
+        let base_version = base.get(gesture_id);
+
+        if base_version == Some(&their_version) {
+            continue;
+        }
+
+        match our_gestures.get(*gesture_id) {
+            Some(our_gesture) => {
+                let our_version = our_versions[gesture_id];
+
+                if our_version == their_version {
+                    continue;
+                } else if base_version == Some(&our_version) {
+                    taken.push((*gesture_id, their_gesture.clone(), their_version));
+                } else {
+                    conflicts.push(MergeConflict {
+                        gesture_id: *gesture_id,
+                        ours: our_gesture.clone(),
+                        theirs: their_gesture.clone(),
+                    });
+                }
+            }
+            None => taken.push((*gesture_id, their_gesture.clone(), their_version)),
+        }
+    }
+
+    MergePlan {
+        conflicts,
+        taken,
+        their_versions,
+    }
+}
+
+impl PlanManager {
+    /// The fork starts out with the current version of all gestures of `source_id`,
+    /// but as a single step, so undoing in the fork can't undo steps of the source.
+    /// The player with `token` becomes the author of the fork
+    pub fn fork_proposal(
+        &mut self,
+        source_id: ProposalID,
+        proposal_id: ProposalID,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::ForkProposal {
+                source_id,
+                proposal_id,
+                token,
+            },
+            world,
+        );
+
+        let player = match self.player_of(token) {
+            Some(player) => player,
+            None => {
+                println!("Only players that joined can fork proposals");
+                return;
+            }
+        };
+
+        if self.proposals.contains_key(proposal_id)
+            || self.implemented_proposals.contains_key(proposal_id)
+        {
+            println!("There already is a proposal {:?}", proposal_id);
+            return;
+        }
+
+        let fork = match self.proposals.get(source_id) {
+            Some(source) => {
+                let fork_plan = Plan::from_gestures(
+                    source
+                        .changed_gestures()
+                        .pairs()
+                        .map(|(gesture_id, gesture)| (*gesture_id, gesture.clone())),
+                );
+                let mut metadata = ProposalMetadata::new(Some(player), self.current_instant);
+                metadata.forked_from = Some(source_id);
+                metadata.fork_step = Some(fork_plan.step_id);
+                metadata.copied_versions = source
+                    .gesture_versions()
+                    .into_iter()
+                    .map(|(gesture_id, version)| CopiedVersion {
+                        step_id: fork_plan.step_id,
+                        gesture_id,
+                        version,
+                    })
+                    .collect();
+                if !source.metadata.name.is_empty() {
+                    metadata.name.push_str(&source.metadata.name);
+                    metadata.name.push_str(" (fork)");
+                }
+                Proposal::from_plan(fork_plan).with_metadata(metadata)
+            }
+            None => {
+                println!("There is no open proposal {:?} to fork", source_id);
+                return;
+            }
+        };
+
+        self.proposals.insert(proposal_id, fork);
+    }
+
+    /// Tells `requester` which gestures would conflict when merging `from` into `into`,
+    /// an empty list means they can be merged right away
+    pub fn get_merge_conflicts(
+        &mut self,
+        into: ProposalID,
+        from: ProposalID,
+        requester: BrowserUIID,
+        world: &mut World,
+    ) {
+        if let (Some(ours), Some(theirs)) = (self.proposals.get(into), self.proposals.get(from)) {
+            let conflicts = plan_merge(into, ours, from, theirs).conflicts;
+            requester.on_merge_conflicts(into, from, conflicts, world);
+        }
+    }
+
+    /// Adds all changes of `from` to `into` as a new, undoable step. `from` stays as it is.
+    /// Nothing is merged unless there is a resolution for every conflict.
+    /// With governance, only the author of `into` may merge into it. Nothing is merged
+    /// into proposals under review, which would silently discard their votes
+    pub fn merge_proposals(
+        &mut self,
+        into: ProposalID,
+        from: ProposalID,
+        resolutions: &CHashMap<GestureID, MergeResolution>,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::MergeProposals {
+                into,
+                from,
+                resolutions: resolutions.clone(),
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(into, token) {
+            self.refuse_change(into, token, world);
+            return;
+        }
+
+        let (merged_step, copied_versions) =
+            match (self.proposals.get(into), self.proposals.get(from)) {
+                (Some(ours), _) if ours.metadata.status == ProposalStatus::UnderReview => {
+                    println!("Can't merge into {:?} while it is under review", into);
+                    return;
+                }
+                (Some(ours), Some(theirs)) if into != from => {
+                    let merge = plan_merge(into, ours, from, theirs);
+
+                    if let Some(unresolved) = merge
+                        .conflicts
+                        .iter()
+                        .find(|conflict| !resolutions.contains_key(conflict.gesture_id))
+                    {
+                        println!(
+                            "Can't merge {:?} into {:?}, conflict in gesture {:?} is unresolved",
+                            from, into, unresolved.gesture_id
+                        );
+                        return;
+                    }
+
+                    let mut taken = merge.taken;
+                    taken.extend(merge.conflicts.iter().filter_map(|conflict| {
+                        match resolutions.get(conflict.gesture_id) {
+                            Some(&MergeResolution::Theirs) => Some((
+                                conflict.gesture_id,
+                                conflict.theirs.clone(),
+                                merge.their_versions[&conflict.gesture_id],
+                            )),
+                            _ => None,
+                        }
+                    }));
+
+                    let merged_step = Plan::from_gestures(
+                        taken
+                            .iter()
+                            .map(|&(gesture_id, ref gesture, _)| (gesture_id, gesture.clone())),
+                    );
+                    let copied_versions = taken
+                        .iter()
+                        .map(|&(gesture_id, _, version)| CopiedVersion {
+                            step_id: merged_step.step_id,
+                            gesture_id,
+                            version,
+                        })
+                        .collect::<Vec<_>>();
+
+                    (merged_step, copied_versions)
+                }
+                _ => {
+                    println!("Can't merge {:?} into {:?}", from, into);
+                    return;
+                }
+            };
+
+        if let Some(proposal) = self.proposals.get_mut(into) {
+            proposal.set_ongoing_step(merged_step);
+            proposal.start_new_step();
+            proposal.change_metadata(|metadata| {
+                metadata.copied_versions.extend(copied_versions);
+            });
+        }
+
+        self.clear_previews(into);
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    auto_setup(system);
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
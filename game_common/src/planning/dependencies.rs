@@ -0,0 +1,65 @@
+//! Finds gestures of later proposals that build on the gestures of an earlier one
+//! without changing them, like buildings on lots of a zone or roads that start at
+//! another road. Reverting the earlier proposal would pull the ground from under them.
+
+use descartes::{Area, PointContainer};
+use transport::transport_planning::{gesture_smooth_path, gesture_area_for_intersection};
+use land_use::zone_planning::{zone_area, ZoneIntent, BuildingIntent};
+use super::{Gesture, GestureIntent};
+
+/// The areas covered by the gestures that would be reverted
+pub struct RevertedGround {
+    zone_areas: Vec<Area>,
+    road_areas: Vec<Area>,
+}
+
+impl RevertedGround {
+    pub fn of<'a, I: IntoIterator<Item = &'a Gesture>>(gestures: I) -> RevertedGround {
+        let mut ground = RevertedGround {
+            zone_areas: Vec::new(),
+            road_areas: Vec::new(),
+        };
+
+        for gesture in gestures.into_iter().filter(|gesture| !gesture.is_deleted()) {
+            match gesture.intent {
+                GestureIntent::Zone(ZoneIntent::LandUse(_)) => {
+                    ground.zone_areas.extend(zone_area(&gesture.points));
+                }
+                GestureIntent::Road(road_intent) => {
+                    ground.road_areas.extend(
+                        gesture_smooth_path(&gesture.points)
+                            .map(|path| gesture_area_for_intersection(road_intent, &path)),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        ground
+    }
+
+    /// Buildings whose lot lies in a reverted zone
+    /// and roads that start or end on a reverted road build on it
+    pub fn is_built_on_by(&self, gesture: &Gesture) -> bool {
+        if gesture.is_deleted() {
+            return false;
+        }
+
+        match gesture.intent {
+            GestureIntent::Building(BuildingIntent { ref lot, .. }) => {
+                let building_location = lot.center_point();
+                self.zone_areas
+                    .iter()
+                    .any(|area| area.contains(building_location))
+            }
+            GestureIntent::Road(_) => match gesture_smooth_path(&gesture.points) {
+                Some(path) => self
+                    .road_areas
+                    .iter()
+                    .any(|area| area.contains(path.start()) || area.contains(path.end())),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Converts the result of the master plan to GeoJSON, for analysing a city in GIS tools
+//! and diffing cities across versions. Coordinates are the city's own planar coordinates
+//! in meters, not WGS84 longitudes and latitudes.
+//! Features are sorted by gesture and prototype ID, so unchanged cities export identically.
+
+use descartes::{P2, LinePath, Area};
+use serde_json;
+use transport::transport_planning::{RoadPrototype, LanePrototype, SwitchLanePrototype,
+IntersectionPrototype};
+use land_use::zone_planning::{LotPrototype, LotOccupancy, LandUse};
+use land_use::buildings::BuildingStyle;
+use super::super::{PlanHistory, PlanResult, PrototypeKind, PrototypeID, GestureID,
+GestureIntent, VersionedGesture};
+
+#[derive(Serialize)]
+#[serde(tag = "type", content = "coordinates")]
+pub enum Geometry {
+    LineString(Vec<[f32; 2]>),
+    MultiPolygon(Vec<Vec<Vec<[f32; 2]>>>),
+}
+
+impl Geometry {
+    fn from_path(path: &LinePath) -> Geometry {
+        Geometry::LineString(path.points.iter().map(as_coordinates).collect())
+    }
+
+    /// Every primitive area becomes its own polygon with a single (closed) ring
+    fn from_area(area: &Area) -> Geometry {
+        Geometry::MultiPolygon(
+            area.primitives
+                .iter()
+                .map(|primitive| {
+                    let points = &primitive.boundary.path().points;
+                    let mut ring = points.iter().map(as_coordinates).collect::<Vec<_>>();
+                    if points.first() != points.last() {
+                        ring.push(as_coordinates(&points[0]));
+                    }
+                    vec![ring]
+                }).collect(),
+        )
+    }
+}
+
+fn as_coordinates(point: &P2) -> [f32; 2] {
+    [point.x, point.y]
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FeatureProperties {
+    Road {
+        gesture_id: GestureID,
+        n_lanes_forward: u8,
+        n_lanes_backward: u8,
+    },
+    /// Green during the phases that are `true`, never switching if there are none
+    Lane {
+        prototype_id: PrototypeID,
+        length: f32,
+        signal_phases: Vec<bool>,
+    },
+    /// Connects lanes across an intersection, green during the phases that are `true`
+    ConnectingLane {
+        intersection_id: PrototypeID,
+        length: f32,
+        signal_phases: Vec<bool>,
+    },
+    /// Switch lanes are never signalled, their phases are only there
+    /// so that all lane features have the same properties
+    SwitchLane {
+        prototype_id: PrototypeID,
+        length: f32,
+        signal_phases: Vec<bool>,
+    },
+    Intersection {
+        prototype_id: PrototypeID,
+        n_connecting_lanes: usize,
+    },
+    PavedArea {
+        prototype_id: PrototypeID,
+    },
+    Lot {
+        prototype_id: PrototypeID,
+        land_uses: Vec<LandUse>,
+        max_height: u8,
+        set_back: u8,
+        occupied: bool,
+    },
+    /// Only the lot of the building, its footprint is decided by the browser
+    Building {
+        lot_prototype_id: PrototypeID,
+        building_style: BuildingStyle,
+    },
+}
+
+#[derive(Serialize)]
+pub struct Feature {
+    #[serde(rename = "type")]
+    pub feature_type: &'static str,
+    pub geometry: Geometry,
+    pub properties: FeatureProperties,
+}
+
+impl Feature {
+    fn new(geometry: Geometry, properties: FeatureProperties) -> Feature {
+        Feature {
+            feature_type: "Feature",
+            geometry,
+            properties,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FeatureCollection {
+    #[serde(rename = "type")]
+    pub collection_type: &'static str,
+    pub features: Vec<Feature>,
+}
+
+fn road_features(prototype_id: PrototypeID, road: &RoadPrototype) -> Vec<Feature> {
+    match *road {
+        RoadPrototype::Lane(LanePrototype(ref path, ref timings)) => vec![Feature::new(
+            Geometry::from_path(path),
+            FeatureProperties::Lane {
+                prototype_id,
+                length: path.length(),
+                signal_phases: timings.to_vec(),
+            },
+        )],
+        RoadPrototype::SwitchLane(SwitchLanePrototype(ref path)) => vec![Feature::new(
+            Geometry::from_path(path),
+            FeatureProperties::SwitchLane {
+                prototype_id,
+                length: path.length(),
+                signal_phases: Vec::new(),
+            },
+        )],
+        RoadPrototype::Intersection(IntersectionPrototype {
+            ref area,
+            ref connecting_lanes,
+            ..
+        }) => {
+            let mut features = vec![Feature::new(
+                Geometry::from_area(area),
+                FeatureProperties::Intersection {
+                    prototype_id,
+                    n_connecting_lanes: connecting_lanes.values().map(|lanes| lanes.len()).sum(),
+                },
+            )];
+            let mut sides_and_lanes = connecting_lanes.pairs().collect::<Vec<_>>();
+            sides_and_lanes.sort_by_key(|&(sides, _)| *sides);
+            features.extend(sides_and_lanes.into_iter().flat_map(|(_, lanes)| {
+                lanes.iter().map(|&LanePrototype(ref path, ref timings)| {
+                    Feature::new(
+                        Geometry::from_path(path),
+                        FeatureProperties::ConnectingLane {
+                            intersection_id: prototype_id,
+                            length: path.length(),
+                            signal_phases: timings.to_vec(),
+                        },
+                    )
+                })
+            }));
+            features
+        }
+        RoadPrototype::PavedArea(ref area) => vec![Feature::new(
+            Geometry::from_area(area),
+            FeatureProperties::PavedArea { prototype_id },
+        )],
+    }
+}
+
+fn lot_features(prototype_id: PrototypeID, lot_prototype: &LotPrototype) -> Vec<Feature> {
+    let lot = &lot_prototype.lot;
+    let mut features = vec![Feature::new(
+        Geometry::from_area(&lot.area),
+        FeatureProperties::Lot {
+            prototype_id,
+            land_uses: lot.land_uses.to_vec(),
+            max_height: lot.max_height,
+            set_back: lot.set_back,
+            occupied: lot_prototype.occupancy != LotOccupancy::Vacant,
+        },
+    )];
+
+    if let LotOccupancy::Occupied(building_style) = lot_prototype.occupancy {
+        features.push(Feature::new(
+            Geometry::from_area(&lot.area),
+            FeatureProperties::Building {
+                lot_prototype_id: prototype_id,
+                building_style,
+            },
+        ));
+    }
+
+    features
+}
+
+/// Roads are exported both as their gestures (with lane counts)
+/// and as the individual lanes that were calculated from them
+pub fn as_feature_collection(master_plan: &PlanHistory, result: &PlanResult) -> FeatureCollection {
+    let mut gestures = master_plan.live_gestures().collect::<Vec<_>>();
+    gestures.sort_by_key(|&(gesture_id, _)| *gesture_id);
+
+    let gesture_features = gestures
+        .into_iter()
+        .filter_map(|(gesture_id, VersionedGesture(gesture, _))| {
+            if let GestureIntent::Road(ref road_intent) = gesture.intent {
+                let points = gesture.points.iter().map(as_coordinates).collect();
+                Some(Feature::new(
+                    Geometry::LineString(points),
+                    FeatureProperties::Road {
+                        gesture_id: *gesture_id,
+                        n_lanes_forward: road_intent.n_lanes_forward,
+                        n_lanes_backward: road_intent.n_lanes_backward,
+                    },
+                ))
+            } else {
+                None
+            }
+        });
+
+    let mut prototypes = result.prototypes.values().collect::<Vec<_>>();
+    prototypes.sort_by_key(|prototype| prototype.id);
+
+    let prototype_features = prototypes
+        .into_iter()
+        .flat_map(|prototype| match prototype.kind {
+            PrototypeKind::Road(ref road) => road_features(prototype.id, road),
+            PrototypeKind::Lot(ref lot_prototype) => lot_features(prototype.id, lot_prototype),
+        });
+
+    FeatureCollection {
+        collection_type: "FeatureCollection",
+        features: gesture_features.chain(prototype_features).collect(),
+    }
+}
+
+pub fn as_geojson(
+    master_plan: &PlanHistory,
+    result: &PlanResult,
+) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&as_feature_collection(master_plan, result))
+}
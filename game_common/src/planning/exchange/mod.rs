@@ -0,0 +1,231 @@
+//! Plans can be exported to and imported from JSON files, so road layouts can be designed
+//! offline and shared between cities. An export contains the current version of every
+//! gesture of the master plan or of a proposal, an import always becomes a new proposal
+//! that can be previewed and implemented like any other.
+
+use kay::{World, ActorSystem};
+use compact::{CHashMap, CString};
+use std::fs::File;
+use std::io::Write;
+use serde_json;
+use browser_ui::{BrowserUI, BrowserUIID};
+use journal::{InputJournal, JournaledInput};
+use super::{PlanManager, PlanManagerID, PlanHistory, Plan, Proposal, ProposalID, Gesture,
+GestureID, VersionedGesture, ProposalMetadata, PlayerToken};
+
+pub mod geojson;
+pub mod osm;
+
+pub const PLAN_EXPORT_VERSION: u32 = 1;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PlanExportSource {
+    MasterPlan,
+    Proposal(ProposalID),
+}
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct PlanExport {
+    pub version: u32,
+    pub gestures: CHashMap<GestureID, Gesture>,
+}
+
+#[derive(Debug)]
+pub enum PlanExportError {
+    Io(::std::io::Error),
+    Format(serde_json::Error),
+    UnsupportedVersion(u32),
+    NoSuchProposal(ProposalID),
+}
+
+impl ::std::fmt::Display for PlanExportError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            PlanExportError::Io(ref err) => write!(f, "Couldn't access plan file: {}", err),
+            PlanExportError::Format(ref err) => write!(f, "Plan file is malformed: {}", err),
+            PlanExportError::UnsupportedVersion(version) => write!(
+                f,
+                "Plan file has version {}, this build only supports version {}",
+                version, PLAN_EXPORT_VERSION
+            ),
+            PlanExportError::NoSuchProposal(proposal_id) => {
+                write!(f, "There is no proposal {:?} to export", proposal_id)
+            }
+        }
+    }
+}
+
+impl PlanExport {
+    /// Gestures deleted from the master plan are left out, importing them would only
+    /// add deletions of gestures that the importing city doesn't have
+    pub fn from_master_plan(master_plan: &PlanHistory) -> PlanExport {
+        PlanExport::from_gestures(master_plan.live_gestures())
+    }
+
+    /// Only contains the gestures that the proposal adds, changes or deletes
+    pub fn from_proposal(proposal: &Proposal) -> PlanExport {
+        PlanExport::from_gestures(proposal.apply_to(&PlanHistory::new()).gestures.pairs())
+    }
+
+    fn from_gestures<'a, I: Iterator<Item = (&'a GestureID, &'a VersionedGesture)>>(
+        gestures: I,
+    ) -> PlanExport {
+        PlanExport {
+            version: PLAN_EXPORT_VERSION,
+            gestures: gestures
+                .map(|(gesture_id, VersionedGesture(gesture, _))| (*gesture_id, gesture.clone()))
+                .collect(),
+        }
+    }
+
+    /// The version is only checked when importing, so that every import path checks it
+    pub fn read_from(path: &str) -> Result<PlanExport, PlanExportError> {
+        let file = File::open(path).map_err(PlanExportError::Io)?;
+        serde_json::from_reader(file).map_err(PlanExportError::Format)
+    }
+
+    fn check_version(&self) -> Result<(), PlanExportError> {
+        if self.version == PLAN_EXPORT_VERSION {
+            Ok(())
+        } else {
+            Err(PlanExportError::UnsupportedVersion(self.version))
+        }
+    }
+
+    pub fn write_to(&self, path: &str) -> Result<(), PlanExportError> {
+        let file = File::create(path).map_err(PlanExportError::Io)?;
+        serde_json::to_writer_pretty(file, self).map_err(PlanExportError::Format)
+    }
+}
+
+impl PlanManager {
+    fn export(&self, source: PlanExportSource) -> Result<PlanExport, PlanExportError> {
+        match source {
+            PlanExportSource::MasterPlan => Ok(PlanExport::from_master_plan(&self.master_plan)),
+            PlanExportSource::Proposal(proposal_id) => self
+                .proposals
+                .get(proposal_id)
+                .map(PlanExport::from_proposal)
+                .ok_or(PlanExportError::NoSuchProposal(proposal_id)),
+        }
+    }
+
+    pub fn export_to_file(&mut self, source: PlanExportSource, path: &CString, _: &mut World) {
+        match self.export(source).and_then(|export| export.write_to(path)) {
+            Ok(()) => println!("Exported {:?} to {}", source, &**path),
+            Err(err) => println!("Exporting {:?} to {} failed: {}", source, &**path, err),
+        }
+    }
+
+    pub fn export_to_ui(&mut self, source: PlanExportSource, ui: BrowserUIID, world: &mut World) {
+        match self.export(source) {
+            Ok(export) => ui.on_plan_export(source, export, world),
+            Err(err) => println!("Exporting {:?} failed: {}", source, err),
+        }
+    }
+
+    pub fn export_geojson_to_file(&mut self, path: &CString, _: &mut World) {
+        let written = geojson::as_geojson(&self.master_plan, &self.master_result)
+            .map_err(PlanExportError::Format)
+            .and_then(|geojson| {
+                File::create(&**path)
+                    .and_then(|mut file| file.write_all(geojson.as_bytes()))
+                    .map_err(PlanExportError::Io)
+            });
+
+        match written {
+            Ok(()) => println!("Exported city as GeoJSON to {}", &**path),
+            Err(err) => println!("Exporting city as GeoJSON to {} failed: {}", &**path, err),
+        }
+    }
+
+    pub fn export_geojson_to_ui(&mut self, ui: BrowserUIID, world: &mut World) {
+        match geojson::as_geojson(&self.master_plan, &self.master_result) {
+            Ok(geojson) => {
+                let mut compact_geojson = CString::new();
+                compact_geojson.push_str(&geojson);
+                ui.on_geojson_export(compact_geojson, world);
+            }
+            Err(err) => println!("Exporting city as GeoJSON failed: {}", err),
+        }
+    }
+
+    pub fn import_proposal_from_file(&mut self, path: &CString, world: &mut World) {
+        match PlanExport::read_from(path) {
+            Ok(export) => {
+                let proposal_id = ProposalID::new();
+                self.import_proposal(proposal_id, &export, None, world);
+                if self.proposals.contains_key(proposal_id) {
+                    println!("Imported {} as proposal {:?}", &**path, proposal_id);
+                }
+            }
+            Err(err) => println!("Importing {} failed: {}", &**path, err),
+        }
+    }
+
+    pub fn import_osm_from_file(&mut self, path: &CString, world: &mut World) {
+        match osm::read_from(path) {
+            Ok(export) => {
+                let proposal_id = ProposalID::new();
+                let n_gestures = export.gestures.len();
+                self.import_proposal(proposal_id, &export, None, world);
+                println!(
+                    "Imported {} gestures from {} as proposal {:?}",
+                    n_gestures, &**path, proposal_id
+                );
+            }
+            Err(err) => println!("Importing {} failed: {}", &**path, err),
+        }
+    }
+
+    /// Gestures that exist in the master plan are kept as changes to them,
+    /// all others get new IDs, so they can't clash with gestures of this city.
+    /// Exports of unsupported versions are rejected. The importing player becomes the author,
+    /// imports from files of the server have no author
+    pub fn import_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        export: &PlanExport,
+        importer: Option<PlayerToken>,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::ImportProposal {
+                proposal_id,
+                export: export.clone(),
+                importer,
+            },
+            world,
+        );
+
+        if let Err(err) = export.check_version() {
+            println!("Importing proposal {:?} failed: {}", proposal_id, err);
+            let mut reason = CString::new();
+            reason.push_str(&err.to_string());
+            BrowserUI::global_broadcast(world).on_import_rejected(proposal_id, reason, world);
+            return;
+        }
+
+        let master_gestures = &self.master_plan.gestures;
+        let mut proposal = Proposal::from_plan(Plan::from_gestures(export.gestures.pairs().map(
+            |(gesture_id, gesture)| {
+                if master_gestures.contains_key(*gesture_id) {
+                    (*gesture_id, gesture.clone())
+                } else {
+                    (GestureID::new(), gesture.clone())
+                }
+            },
+        )));
+        let author = importer.and_then(|token| self.player_of(token));
+        proposal.metadata = ProposalMetadata::new(author, self.current_instant);
+
+        self.proposals.insert(proposal_id, proposal);
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    auto_setup(system);
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
@@ -0,0 +1,459 @@
+//! Converts OpenStreetMap XML extracts into plans: highways become road gestures with lane
+//! counts from their `lanes`/`oneway` tags, landuse polygons become zone gestures.
+//!
+//! Only the flat subset of XML that OSM extracts use is understood. PBF extracts are not
+//! supported directly, convert them first (for example with `osmium cat extract.pbf -o
+//! extract.osm`). Relations (like multipolygons) are ignored.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use compact::CHashMap;
+use descartes::P2;
+use transport::transport_planning::RoadIntent;
+use land_use::zone_planning::{ZoneIntent, LandUse};
+use super::{PlanExport, PLAN_EXPORT_VERSION};
+use super::super::{Gesture, GestureID, GestureIntent};
+
+const METERS_PER_DEGREE_LATITUDE: f64 = 110_574.0;
+const METERS_PER_DEGREE_LONGITUDE_AT_EQUATOR: f64 = 111_320.0;
+const MAX_LANES_PER_DIRECTION: u8 = 6;
+
+#[derive(Debug)]
+pub enum OsmImportError {
+    Io(::std::io::Error),
+    Pbf,
+    Malformed { offset: usize, reason: &'static str },
+    NothingToImport,
+}
+
+impl ::std::fmt::Display for OsmImportError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            OsmImportError::Io(ref err) => write!(f, "Couldn't read OSM extract: {}", err),
+            OsmImportError::Pbf => write!(
+                f,
+                "OSM PBF extracts are not supported, convert to OSM XML first \
+                 (e.g. `osmium cat extract.osm.pbf -o extract.osm`)"
+            ),
+            OsmImportError::Malformed { offset, reason } => {
+                write!(f, "OSM extract is malformed at byte {}: {}", offset, reason)
+            }
+            OsmImportError::NothingToImport => {
+                write!(f, "OSM extract contains no highways or landuse areas")
+            }
+        }
+    }
+}
+
+struct XmlTag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, String)>,
+    is_closing: bool,
+    is_self_closing: bool,
+}
+
+impl<'a> XmlTag<'a> {
+    fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|&&(attribute_key, _)| attribute_key == key)
+            .map(|&(_, ref value)| value.as_str())
+    }
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The index of the `>` that ends the tag starting at `tag_start`,
+/// skipping over `>` within quoted attribute values
+fn find_tag_end(xml: &str, tag_start: usize) -> Option<usize> {
+    let mut open_quote = None;
+
+    for (idx, character) in xml[tag_start..].char_indices() {
+        match (open_quote, character) {
+            (None, '"') | (None, '\'') => open_quote = Some(character),
+            (None, '>') => return Some(tag_start + idx),
+            (Some(quote), _) if character == quote => open_quote = None,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn parse_tag(content: &str, offset: usize) -> Result<XmlTag, OsmImportError> {
+    let malformed = |reason| OsmImportError::Malformed { offset, reason };
+
+    let is_closing = content.starts_with('/');
+    let is_self_closing = content.ends_with('/');
+    let content = content.trim_matches('/');
+    let name_end = content
+        .find(char::is_whitespace)
+        .unwrap_or_else(|| content.len());
+    let (name, mut rest) = content.split_at(name_end);
+
+    let mut attributes = Vec::new();
+
+    loop {
+        rest = rest.trim_left();
+        if rest.is_empty() {
+            break;
+        }
+
+        let equals_idx = rest.find('=').ok_or_else(|| malformed("attribute without value"))?;
+        let key = rest[..equals_idx].trim();
+        let after_equals = rest[equals_idx + 1..].trim_left();
+        let quote = after_equals
+            .chars()
+            .next()
+            .filter(|&quote| quote == '"' || quote == '\'')
+            .ok_or_else(|| malformed("unquoted attribute value"))?;
+        let value_end = after_equals[1..]
+            .find(quote)
+            .ok_or_else(|| malformed("unterminated attribute value"))?;
+        attributes.push((key, unescape(&after_equals[1..1 + value_end])));
+        rest = &after_equals[value_end + 2..];
+    }
+
+    Ok(XmlTag {
+        name,
+        attributes,
+        is_closing,
+        is_self_closing,
+    })
+}
+
+struct OsmWay {
+    node_ids: Vec<i64>,
+    tags: HashMap<String, String>,
+}
+
+impl OsmWay {
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(|value| value.as_str())
+    }
+
+    fn is_closed(&self) -> bool {
+        self.node_ids.len() > 3 && self.node_ids.first() == self.node_ids.last()
+    }
+}
+
+struct OsmData {
+    nodes: HashMap<i64, (f64, f64)>,
+    ways: Vec<OsmWay>,
+    bounds_center: Option<(f64, f64)>,
+}
+
+fn parse_osm_xml(xml: &str) -> Result<OsmData, OsmImportError> {
+    let mut data = OsmData {
+        nodes: HashMap::new(),
+        ways: Vec::new(),
+        bounds_center: None,
+    };
+    let mut current_way: Option<OsmWay> = None;
+    let mut position = 0;
+
+    while let Some(tag_start) = xml[position..].find('<').map(|idx| position + idx) {
+        if xml[tag_start..].starts_with("<!--") {
+            position = tag_start
+                + xml[tag_start..].find("-->").ok_or(OsmImportError::Malformed {
+                    offset: tag_start,
+                    reason: "unterminated comment",
+                })? + 3;
+            continue;
+        }
+
+        let tag_end = find_tag_end(xml, tag_start).ok_or(OsmImportError::Malformed {
+            offset: tag_start,
+            reason: "unterminated tag",
+        })?;
+        position = tag_end + 1;
+
+        let content = &xml[tag_start + 1..tag_end];
+        if content.starts_with('?') || content.starts_with('!') {
+            continue;
+        }
+
+        let tag = parse_tag(content, tag_start)?;
+        let malformed = |reason| OsmImportError::Malformed {
+            offset: tag_start,
+            reason,
+        };
+
+        match (tag.name, tag.is_closing) {
+            ("bounds", false) => {
+                let coordinate = |key| {
+                    tag.attribute(key)
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .ok_or_else(|| malformed("bounds without valid coordinates"))
+                };
+                data.bounds_center = Some((
+                    (coordinate("minlat")? + coordinate("maxlat")?) / 2.0,
+                    (coordinate("minlon")? + coordinate("maxlon")?) / 2.0,
+                ));
+            }
+            ("node", false) => {
+                let parse = |key| {
+                    tag.attribute(key)
+                        .and_then(|value| value.parse::<f64>().ok())
+                        .ok_or_else(|| malformed("node without valid coordinates"))
+                };
+                let id = tag
+                    .attribute("id")
+                    .and_then(|value| value.parse::<i64>().ok())
+                    .ok_or_else(|| malformed("node without valid id"))?;
+                data.nodes.insert(id, (parse("lat")?, parse("lon")?));
+            }
+            ("way", false) => {
+                let way = OsmWay {
+                    node_ids: Vec::new(),
+                    tags: HashMap::new(),
+                };
+                if tag.is_self_closing {
+                    data.ways.push(way);
+                } else {
+                    current_way = Some(way);
+                }
+            }
+            ("way", true) => {
+                data.ways
+                    .extend(current_way.take().ok_or_else(|| malformed("unopened way"))?);
+            }
+            ("nd", false) => {
+                if let Some(ref mut way) = current_way {
+                    way.node_ids.push(
+                        tag.attribute("ref")
+                            .and_then(|value| value.parse().ok())
+                            .ok_or_else(|| malformed("way node without valid ref"))?,
+                    );
+                }
+            }
+            ("tag", false) => {
+                if let Some(ref mut way) = current_way {
+                    if let (Some(key), Some(value)) = (tag.attribute("k"), tag.attribute("v")) {
+                        way.tags.insert(key.to_owned(), value.to_owned());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(data)
+}
+
+/// Lanes in (forward, backward) direction, `None` if the way is not a road for cars
+fn road_lanes(way: &OsmWay) -> Option<(u8, u8)> {
+    let highway = way.tag("highway")?;
+    let default_lanes_per_direction = match highway {
+        "motorway" | "trunk" | "primary" => 2,
+        "secondary" | "tertiary" | "unclassified" | "residential" | "living_street"
+        | "service" | "motorway_link" | "trunk_link" | "primary_link" | "secondary_link"
+        | "tertiary_link" => 1,
+        _ => return None,
+    };
+
+    let is_oneway = match way.tag("oneway") {
+        Some("yes") | Some("true") | Some("1") | Some("-1") | Some("reverse") => true,
+        Some("no") | Some("false") | Some("0") => false,
+        _ => highway == "motorway" || way.tag("junction") == Some("roundabout"),
+    };
+
+    // wider than the result, so absurd lane counts can't overflow
+    let parse_lanes = |key| way.tag(key).and_then(|value| value.parse::<u32>().ok());
+
+    let (forward, backward) = match (
+        parse_lanes("lanes:forward"),
+        parse_lanes("lanes:backward"),
+        parse_lanes("lanes"),
+    ) {
+        (Some(forward), Some(backward), _) => (forward, backward),
+        (_, _, Some(lanes)) if is_oneway => (lanes, 0),
+        (_, _, Some(lanes)) => ((lanes + 1) / 2, (lanes / 2).max(1)),
+        _ if is_oneway => (default_lanes_per_direction, 0),
+        _ => (default_lanes_per_direction, default_lanes_per_direction),
+    };
+
+    Some((
+        forward.max(1).min(u32::from(MAX_LANES_PER_DIRECTION)) as u8,
+        backward.min(u32::from(MAX_LANES_PER_DIRECTION)) as u8,
+    ))
+}
+
+fn land_use(way: &OsmWay) -> Option<LandUse> {
+    match (way.tag("landuse"), way.tag("leisure")) {
+        (Some("residential"), _) => Some(LandUse::Residential),
+        (Some("commercial"), _) | (Some("retail"), _) => Some(LandUse::Commercial),
+        (Some("industrial"), _) => Some(LandUse::Industrial),
+        (Some("farmland"), _) | (Some("farmyard"), _) | (Some("meadow"), _)
+        | (Some("orchard"), _) | (Some("vineyard"), _) => Some(LandUse::Agricultural),
+        (Some("recreation_ground"), _) | (Some("village_green"), _) | (_, Some("park")) => {
+            Some(LandUse::Recreational)
+        }
+        (Some("education"), _) | (Some("institutional"), _) | (Some("religious"), _) => {
+            Some(LandUse::Official)
+        }
+        _ => None,
+    }
+}
+
+/// Projects coordinates onto a plane around `center` (equirectangular,
+/// which is precise enough for the extent of a city)
+fn project(center: (f64, f64), (lat, lon): (f64, f64)) -> P2 {
+    let (center_lat, center_lon) = center;
+    P2::new(
+        ((lon - center_lon)
+            * METERS_PER_DEGREE_LONGITUDE_AT_EQUATOR
+            * center_lat.to_radians().cos()) as f32,
+        ((lat - center_lat) * METERS_PER_DEGREE_LATITUDE) as f32,
+    )
+}
+
+pub fn plan_from_osm_xml(xml: &str) -> Result<PlanExport, OsmImportError> {
+    let data = parse_osm_xml(xml)?;
+
+    let center = data.bounds_center.unwrap_or_else(|| {
+        let n_nodes = data.nodes.len().max(1) as f64;
+        let (lat_sum, lon_sum) = data
+            .nodes
+            .values()
+            .fold((0.0, 0.0), |(lat_sum, lon_sum), &(lat, lon)| {
+                (lat_sum + lat, lon_sum + lon)
+            });
+        (lat_sum / n_nodes, lon_sum / n_nodes)
+    });
+
+    let mut gestures = CHashMap::new();
+
+    for way in &data.ways {
+        // extracts can cut off ways at their border, so we skip nodes we don't know
+        let mut points = way
+            .node_ids
+            .iter()
+            .filter_map(|node_id| data.nodes.get(node_id))
+            .map(|&coordinates| project(center, coordinates))
+            .collect::<Vec<_>>();
+        points.dedup();
+
+        if let Some((n_lanes_forward, n_lanes_backward)) = road_lanes(way) {
+            if points.len() < 2 {
+                continue;
+            }
+            if let Some("-1") | Some("reverse") = way.tag("oneway") {
+                points.reverse();
+            }
+            gestures.insert(
+                GestureID::new(),
+                Gesture::new(
+                    points.into(),
+                    GestureIntent::Road(RoadIntent::new(n_lanes_forward, n_lanes_backward)),
+                ),
+            );
+        } else if let Some(land_use) = land_use(way) {
+            if !way.is_closed() {
+                continue;
+            }
+            // zone gestures are implicitly closed
+            if points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+            if points.len() < 3 {
+                continue;
+            }
+            gestures.insert(
+                GestureID::new(),
+                Gesture::new(
+                    points.into(),
+                    GestureIntent::Zone(ZoneIntent::LandUse(land_use)),
+                ),
+            );
+        }
+    }
+
+    if gestures.is_empty() {
+        Err(OsmImportError::NothingToImport)
+    } else {
+        Ok(PlanExport {
+            version: PLAN_EXPORT_VERSION,
+            gestures,
+        })
+    }
+}
+
+pub fn read_from(path: &str) -> Result<PlanExport, OsmImportError> {
+    if path.ends_with(".pbf") {
+        return Err(OsmImportError::Pbf);
+    }
+
+    let mut xml = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut xml))
+        .map_err(OsmImportError::Io)?;
+
+    plan_from_osm_xml(&xml)
+}
+
+#[test]
+fn test_osm_roads_and_zones() {
+    let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <bounds minlat="50.0" minlon="8.0" maxlat="50.002" maxlon="8.002"/>
+  <node id="1" lat="50.0" lon="8.0"/>
+  <node id="2" lat="50.001" lon="8.0"/>
+  <node id="3" lat="50.001" lon="8.001"/>
+  <node id="4" lat="50.0" lon="8.001"/>
+  <way id="10">
+    <nd ref="1"/><nd ref="2"/>
+    <tag k="highway" v="primary"/>
+    <tag k="lanes" v="3"/>
+  </way>
+  <way id="11">
+    <nd ref="2"/><nd ref="3"/>
+    <tag k="highway" v="residential"/>
+    <tag k="oneway" v="-1"/>
+  </way>
+  <way id="12">
+    <nd ref="3"/><nd ref="4"/>
+    <tag k="highway" v="footway"/>
+  </way>
+  <way id="13">
+    <nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="1"/>
+    <tag k="landuse" v="residential"/>
+  </way>
+  <way id="14">
+    <nd ref="4"/><nd ref="1"/>
+    <tag k="note" v="lanes > 2 'planned'"/>
+    <tag k="highway" v="secondary"/>
+    <tag k="lanes" v="255"/>
+  </way>
+</osm>"#;
+
+    let export = plan_from_osm_xml(xml).expect("should import");
+    let mut road_lanes = export
+        .gestures
+        .values()
+        .filter_map(|gesture| match gesture.intent {
+            GestureIntent::Road(ref road) => Some((road.n_lanes_forward, road.n_lanes_backward)),
+            _ => None,
+        }).collect::<Vec<_>>();
+    road_lanes.sort();
+    assert!(road_lanes == vec![(1, 0), (2, 1), (6, 6)]);
+
+    let zone_points = export
+        .gestures
+        .values()
+        .filter_map(|gesture| match gesture.intent {
+            GestureIntent::Zone(ZoneIntent::LandUse(LandUse::Residential)) => {
+                Some(gesture.points.len())
+            }
+            _ => None,
+        }).collect::<Vec<_>>();
+    assert!(zone_points == vec![4]);
+}
@@ -0,0 +1,82 @@
+//! Summarizes what implementing a proposal would change in the city,
+//! so planners can confirm before anything is actually constructed or destroyed.
+
+use compact::CVec;
+use transport::transport_planning::RoadPrototype;
+use land_use::zone_planning::{LotPrototype, LotOccupancy};
+use super::{PlanResult, Prototype, PrototypeKind, PrototypeID, Action, ActionGroups};
+
+#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
+pub struct ActionCounts {
+    pub constructed: u32,
+    pub morphed: u32,
+    pub destructed: u32,
+}
+
+impl ActionCounts {
+    fn count(&mut self, action: &Action) {
+        match *action {
+            Action::Construct(_) => self.constructed += 1,
+            Action::Morph(..) => self.morphed += 1,
+            Action::Destruct(_) => self.destructed += 1,
+        }
+    }
+}
+
+/// Switch lanes are counted as lanes, paved areas aren't counted at all,
+/// since they are only a side effect of lanes and intersections
+#[derive(Copy, Clone, Default, Debug, Serialize, Deserialize)]
+pub struct ImpactReport {
+    pub lanes: ActionCounts,
+    pub intersections: ActionCounts,
+    pub lots: ActionCounts,
+}
+
+impl ImpactReport {
+    /// `old_result` and `new_result` are the results that `actions` lead from and to.
+    /// Also returns the IDs of all lots in `old_result` whose buildings will be destroyed
+    pub fn from_actions(
+        actions: &ActionGroups,
+        old_result: &PlanResult,
+        new_result: &PlanResult,
+    ) -> (ImpactReport, CVec<PrototypeID>) {
+        let mut report = ImpactReport::default();
+        let mut lots_with_destroyed_buildings = CVec::new();
+
+        for action in actions.0.iter().flat_map(|group| group.0.iter()) {
+            let prototype = match *action {
+                Action::Construct(prototype_id) | Action::Morph(_, prototype_id) => {
+                    new_result.prototypes.get(prototype_id)
+                }
+                Action::Destruct(prototype_id) => old_result.prototypes.get(prototype_id),
+            };
+
+            match prototype {
+                Some(&Prototype {
+                    kind: PrototypeKind::Road(RoadPrototype::Lane(_)),
+                    ..
+                })
+                | Some(&Prototype {
+                    kind: PrototypeKind::Road(RoadPrototype::SwitchLane(_)),
+                    ..
+                }) => report.lanes.count(action),
+                Some(&Prototype {
+                    kind: PrototypeKind::Road(RoadPrototype::Intersection(_)),
+                    ..
+                }) => report.intersections.count(action),
+                Some(&Prototype {
+                    kind: PrototypeKind::Lot(LotPrototype { occupancy, .. }),
+                    id,
+                }) => {
+                    report.lots.count(action);
+                    if let (Action::Destruct(_), LotOccupancy::Occupied(_)) = (action, occupancy) {
+                        lots_with_destroyed_buildings.push(id);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        (report, lots_with_destroyed_buildings)
+    }
+}
@@ -1,12 +1,17 @@
 use kay::{World, MachineID,   ActorSystem};
-use compact::{CHashMap, COption};
-use descartes::{P2, AreaError};
+use compact::{CVec, CHashMap, COption};
+use descartes::{N, P2};
 use super::{Plan, PlanHistory, PlanResult,  GestureID, ProposalID,
 PlanManager, PlanManagerID, Gesture, GestureIntent,
 KnownHistoryState, KnownProposalState, ProposalUpdate,
-KnownPlanResultState,
-ActionGroups};
+KnownPlanResultState, PlanResultUpdate,
+ActionGroups, Action, PlanError, PlayerToken};
+use super::validation::{validate, PlanWarning};
 use browser_ui::BrowserUIID;
+use journal::{InputJournal, JournaledInput};
+
+pub mod snapping;
+use self::snapping::{SnappingOptions, SnappedPoint};
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct ControlPointRef(pub GestureID, pub usize);
@@ -18,6 +23,8 @@ pub struct PlanManagerUIState {
     current_preview: COption<PlanHistory>,
     current_result_preview: COption<PlanResult>,
     current_action_preview: COption<ActionGroups>,
+    current_error_preview: COption<PlanError>,
+    current_warnings_preview: CVec<PlanWarning>,
 }
 
 impl PlanManager {
@@ -75,14 +82,44 @@ impl PlanManager {
             self.switch_to(MachineID(0), proposal_id, world);
         }
 
-        let (_, maybe_result, maybe_actions) =
+        let (_, maybe_result, maybe_actions, maybe_error, warnings) =
             self.try_ensure_preview(world.local_machine_id(), proposal_id);
 
-        if let (Some(result), Some(actions)) = (maybe_result, maybe_actions) {
+        if let Some(error) = maybe_error {
+            // the UI keeps showing the last valid preview, together with what's wrong now
+            ui.on_proposal_preview_update(
+                proposal_id,
+                PlanResultUpdate {
+                    prototypes_to_drop: CVec::new(),
+                    new_prototypes: CVec::new(),
+                },
+                ActionGroups::new(),
+                CVec::new(),
+                COption(Some(error.clone())),
+                CVec::new(),
+                world,
+            );
+        } else if let (Some(result), Some(actions)) = (maybe_result, maybe_actions) {
+            // the UI only knows about the preview result, but destructed prototypes
+            // are only part of the master result
+            let prototypes_to_destruct = actions
+                .0
+                .iter()
+                .flat_map(|group| group.0.iter())
+                .filter_map(|action| match *action {
+                    Action::Destruct(prototype_id) => {
+                        self.master_result.prototypes.get(prototype_id).cloned()
+                    }
+                    _ => None,
+                }).collect::<CVec<_>>();
+
             ui.on_proposal_preview_update(
                 proposal_id,
                 result.update_for(known_result),
                 actions.clone(),
+                prototypes_to_destruct,
+                COption(None),
+                warnings.clone(),
                 world,
             );
         }
@@ -99,6 +136,8 @@ impl PlanManager {
                 current_preview: COption(None),
                 current_result_preview: COption(None),
                 current_action_preview: COption(None),
+                current_error_preview: COption(None),
+                current_warnings_preview: CVec::new(),
             },
         );
     }
@@ -112,6 +151,8 @@ impl PlanManager {
             state.current_preview = COption(None);
             state.current_result_preview = COption(None);
             state.current_action_preview = COption(None);
+            state.current_error_preview = COption(None);
+            state.current_warnings_preview = CVec::new();
         }
     }
 
@@ -120,7 +161,13 @@ impl PlanManager {
         &self,
         machine_id: MachineID,
         proposal_id: ProposalID,
-    ) -> (&PlanHistory, Option<&PlanResult>, &Option<ActionGroups>) {
+    ) -> (
+        &PlanHistory,
+        Option<&PlanResult>,
+        &Option<ActionGroups>,
+        Option<&PlanError>,
+        &CVec<PlanWarning>,
+    ) {
         let ui_state = self
             .ui_state
             .get(machine_id)
@@ -141,20 +188,21 @@ impl PlanManager {
                     .unwrap()
                     .apply_to_with_ongoing(&self.master_plan);
 
-                match preview_plan.calculate_result() {
+                match preview_plan
+                    .calculate_result_incrementally(&mut self.result_cache.borrow_mut())
+                {
                     Ok(preview_plan_result) => {
                         let (actions, _) = self.master_result.actions_to(&preview_plan_result);
+                        ui_state_mut.current_warnings_preview =
+                            validate(&preview_plan_result, &actions);
                         ui_state_mut.current_result_preview = COption(Some(preview_plan_result));
                         ui_state_mut.current_action_preview = COption(Some(actions));
+                        ui_state_mut.current_error_preview = COption(None);
+                    }
+                    Err(err) => {
+                        ui_state_mut.current_error_preview = COption(Some(err));
+                        ui_state_mut.current_warnings_preview = CVec::new();
                     }
-                    Err(err) => match err {
-                        AreaError::LeftOver(string) => {
-                            println!("Preview Plan Error: {}", string);
-                        }
-                        _ => {
-                            println!("Preview Plan Error: {:?}", err);
-                        }
-                    },
                 }
 
                 ui_state_mut.current_preview = COption(Some(preview_plan));
@@ -165,6 +213,8 @@ impl PlanManager {
             ui_state.current_preview.as_ref().unwrap(),
             ui_state.current_result_preview.as_ref(),
             &*ui_state.current_action_preview,
+            ui_state.current_error_preview.as_ref(),
+            &ui_state.current_warnings_preview,
         )
     }
 
@@ -175,8 +225,26 @@ impl PlanManager {
         new_gesture_id: GestureID,
         intent: &GestureIntent,
         start: P2,
-        _: &mut World,
+        token: PlayerToken,
+        world: &mut World,
     ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::StartNewGesture {
+                proposal_id,
+                machine: machine_id.0,
+                gesture_id: new_gesture_id,
+                intent: intent.clone(),
+                start,
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
         let new_gesture = Gesture::new(vec![start].into(), intent.clone());
 
         let new_step = Plan::from_gestures(Some((new_gesture_id, new_gesture)));
@@ -198,7 +266,14 @@ impl PlanManager {
         self.clear_previews(proposal_id);
     }
 
-    pub fn finish_gesture(&mut self, machine_id: MachineID, _: &mut World) {
+    pub fn finish_gesture(&mut self, machine_id: MachineID, world: &mut World) {
+        InputJournal::global_first(world).record(
+            JournaledInput::FinishGesture {
+                machine: machine_id.0,
+            },
+            world,
+        );
+
         self.ui_state
             .get_mut(machine_id)
             .expect("should already have ui state")
@@ -212,8 +287,26 @@ impl PlanManager {
         new_point: P2,
         add_to_end: bool,
         commit: bool,
-        _: &mut World,
+        token: PlayerToken,
+        world: &mut World,
     ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::AddControlPoint {
+                proposal_id,
+                gesture_id,
+                new_point,
+                add_to_end,
+                commit,
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
         let new_step = {
             let current_gesture = self.get_current_version_of(gesture_id, proposal_id);
 
@@ -262,8 +355,26 @@ impl PlanManager {
         point_index: u32,
         new_position: P2,
         is_move_finished: bool,
-        _: &mut World,
+        token: PlayerToken,
+        world: &mut World,
     ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::MoveControlPoint {
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_position,
+                is_move_finished,
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
         let current_change = {
             let current_gesture = self.get_current_version_of(gesture_id, proposal_id);
 
@@ -301,8 +412,25 @@ impl PlanManager {
         gesture_id: GestureID,
         new_intent: &GestureIntent,
         is_move_finished: bool,
-        _: &mut World,
+        token: PlayerToken,
+        world: &mut World,
     ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::SetIntent {
+                proposal_id,
+                gesture_id,
+                new_intent: new_intent.clone(),
+                is_move_finished,
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
         let current_change = {
             let current_gesture = self.get_current_version_of(gesture_id, proposal_id);
 
@@ -331,12 +459,399 @@ impl PlanManager {
         }
     }
 
-    pub fn undo(&mut self, proposal_id: ProposalID, _: &mut World) {
+    /// Also works for gestures of the master plan, implementing the proposal
+    /// then destructs everything that was built from them
+    pub fn delete_gesture(
+        &mut self,
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        let deletion = match self.editable_gesture(proposal_id, gesture_id) {
+            Some(current_gesture) => {
+                let deleted_gesture = Gesture {
+                    deleted: true,
+                    ..current_gesture.clone()
+                };
+
+                Plan::from_gestures(Some((gesture_id, deleted_gesture)))
+            }
+            None => return,
+        };
+
+        InputJournal::global_first(world).record(
+            JournaledInput::DeleteGesture {
+                proposal_id,
+                gesture_id,
+                token,
+            },
+            world,
+        );
+
+        self.commit_step(proposal_id, deletion);
+    }
+
+    /// Inserts the new point before the control point at `point_index`
+    pub fn insert_control_point(
+        &mut self,
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        point_index: u32,
+        new_point: P2,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        let insertion = {
+            let current_gesture = match self.editable_gesture(proposal_id, gesture_id) {
+                Some(current_gesture) => current_gesture,
+                None => return,
+            };
+
+            if point_index as usize > current_gesture.points.len() {
+                println!("Can't insert control point at {}", point_index);
+                return;
+            }
+
+            let mut new_gesture_points = current_gesture.points.to_vec();
+            new_gesture_points.insert(point_index as usize, new_point);
+
+            let new_gesture = Gesture {
+                points: new_gesture_points.into(),
+                ..current_gesture.clone()
+            };
+
+            Plan::from_gestures(Some((gesture_id, new_gesture)))
+        };
+
+        InputJournal::global_first(world).record(
+            JournaledInput::InsertControlPoint {
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_point,
+                token,
+            },
+            world,
+        );
+
+        self.commit_step(proposal_id, insertion);
+    }
+
+    /// Deletes the whole gesture if it would be left with a single point
+    pub fn remove_control_point(
+        &mut self,
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        point_index: u32,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        let removal = {
+            let current_gesture = match self.editable_gesture(proposal_id, gesture_id) {
+                Some(current_gesture) => current_gesture,
+                None => return,
+            };
+
+            if point_index as usize >= current_gesture.points.len() {
+                println!("Can't remove nonexistent control point {}", point_index);
+                return;
+            }
+
+            let mut new_gesture_points = current_gesture.points.to_vec();
+            new_gesture_points.remove(point_index as usize);
+
+            let new_gesture = if new_gesture_points.len() < 2 {
+                Gesture {
+                    deleted: true,
+                    ..current_gesture.clone()
+                }
+            } else {
+                Gesture {
+                    points: new_gesture_points.into(),
+                    ..current_gesture.clone()
+                }
+            };
+
+            Plan::from_gestures(Some((gesture_id, new_gesture)))
+        };
+
+        InputJournal::global_first(world).record(
+            JournaledInput::RemoveControlPoint {
+                proposal_id,
+                gesture_id,
+                point_index,
+                token,
+            },
+            world,
+        );
+
+        self.commit_step(proposal_id, removal);
+    }
+
+    /// The original gesture keeps the points up to and including the split point,
+    /// the new gesture (with the same intent) starts at the split point
+    pub fn split_gesture(
+        &mut self,
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        point_index: u32,
+        new_gesture_id: GestureID,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        let split = {
+            let current_gesture = match self.editable_gesture(proposal_id, gesture_id) {
+                Some(current_gesture) => current_gesture,
+                None => return,
+            };
+            let split_index = point_index as usize;
+
+            let is_road = if let GestureIntent::Road(_) = current_gesture.intent {
+                true
+            } else {
+                false
+            };
+
+            if !is_road {
+                println!("Only road gestures can be split");
+                return;
+            }
+
+            if split_index == 0 || split_index + 1 >= current_gesture.points.len() {
+                println!("Can't split gesture at its end points");
+                return;
+            }
+
+            let first_part = Gesture {
+                points: current_gesture.points[..=split_index].to_vec().into(),
+                ..current_gesture.clone()
+            };
+            let second_part = Gesture::new(
+                current_gesture.points[split_index..].to_vec().into(),
+                current_gesture.intent.clone(),
+            );
+
+            Plan::from_gestures(vec![(gesture_id, first_part), (new_gesture_id, second_part)])
+        };
+
+        InputJournal::global_first(world).record(
+            JournaledInput::SplitGesture {
+                proposal_id,
+                gesture_id,
+                point_index,
+                new_gesture_id,
+                token,
+            },
+            world,
+        );
+
+        self.commit_step(proposal_id, split);
+    }
+
+    /// Joins two road gestures whose ends meet into the first one, which keeps
+    /// its direction, the second one is deleted. Both roads need to have the same lanes
+    /// in the joined direction, so joining never changes any lanes
+    pub fn join_gestures(
+        &mut self,
+        proposal_id: ProposalID,
+        gesture_id: GestureID,
+        other_gesture_id: GestureID,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        const JOIN_TOLERANCE: N = 1.0;
+
+        let join = {
+            let (gesture, other_gesture) = match (
+                self.editable_gesture(proposal_id, gesture_id),
+                self.editable_gesture(proposal_id, other_gesture_id),
+            ) {
+                (Some(gesture), Some(other_gesture)) => (gesture, other_gesture),
+                _ => return,
+            };
+
+            let (road_intent, other_road_intent) = match (&gesture.intent, &other_gesture.intent) {
+                (&GestureIntent::Road(road_intent), &GestureIntent::Road(other_road_intent))
+                    if gesture_id != other_gesture_id
+                        && gesture.points.len() >= 2
+                        && other_gesture.points.len() >= 2 =>
+                {
+                    (road_intent, other_road_intent)
+                }
+                _ => {
+                    println!("Only two different road gestures can be joined");
+                    return;
+                }
+            };
+
+            let meet = |a: P2, b: P2| (a - b).norm() < JOIN_TOLERANCE;
+            let points = gesture.points.to_vec();
+            let mut other_points = other_gesture.points.to_vec();
+            let (start, end) = (points[0], points[points.len() - 1]);
+            let (other_start, other_end) = (other_points[0], other_points[other_points.len() - 1]);
+
+            let other_reversed = if meet(end, other_start) || meet(start, other_end) {
+                false
+            } else if meet(end, other_end) || meet(start, other_start) {
+                true
+            } else {
+                println!("Can't join gestures whose ends don't meet");
+                return;
+            };
+
+            let other_road_intent_along_joined = if other_reversed {
+                other_points.reverse();
+                other_road_intent.reversed()
+            } else {
+                other_road_intent
+            };
+
+            if other_road_intent_along_joined != road_intent {
+                println!("Can't join roads with different lanes");
+                return;
+            }
+
+            let joined_points: Vec<P2> = if meet(end, other_points[0]) {
+                points.into_iter().chain(other_points.into_iter().skip(1)).collect()
+            } else {
+                other_points.into_iter().chain(points.into_iter().skip(1)).collect()
+            };
+
+            let joined_gesture = Gesture {
+                points: joined_points.into(),
+                ..gesture.clone()
+            };
+            let deleted_other_gesture = Gesture {
+                deleted: true,
+                ..other_gesture.clone()
+            };
+
+            Plan::from_gestures(vec![
+                (gesture_id, joined_gesture),
+                (other_gesture_id, deleted_other_gesture),
+            ])
+        };
+
+        InputJournal::global_first(world).record(
+            JournaledInput::JoinGestures {
+                proposal_id,
+                gesture_id,
+                other_gesture_id,
+                token,
+            },
+            world,
+        );
+
+        self.commit_step(proposal_id, join);
+    }
+
+    /// Snaps against the gestures of the proposal and the result of the master plan,
+    /// for scripted plans
+    pub fn snap(
+        &self,
+        proposal_id: ProposalID,
+        candidate: P2,
+        previous: Option<P2>,
+        options: &SnappingOptions,
+    ) -> SnappedPoint {
+        let history = self
+            .proposals
+            .get(proposal_id)
+            .map(|proposal| proposal.apply_to_with_ongoing(&self.master_plan))
+            .unwrap_or_else(|| self.master_plan.clone());
+
+        snapping::snap(candidate, previous, &history, &self.master_result, options)
+    }
+
+    pub fn snap_point(
+        &mut self,
+        proposal_id: ProposalID,
+        candidate: P2,
+        previous: Option<P2>,
+        options: SnappingOptions,
+        ui: BrowserUIID,
+        world: &mut World,
+    ) {
+        let snapped = self.snap(proposal_id, candidate, previous, &options);
+        ui.on_point_snapped(candidate, snapped, world);
+    }
+
+    /// Edits from UIs that are out of date can refer to proposals or gestures that don't exist
+    /// (anymore), these edits are ignored instead of being journaled
+    fn editable_gesture(&self, proposal_id: ProposalID, gesture_id: GestureID) -> Option<&Gesture> {
+        match self.try_get_current_version_of(gesture_id, proposal_id) {
+            Some(gesture) if !gesture.is_deleted() => Some(gesture),
+            Some(_) => {
+                println!("Can't edit deleted gesture {:?}", gesture_id);
+                None
+            }
+            None => {
+                println!(
+                    "There is no gesture {:?} in proposal {:?} to edit",
+                    gesture_id, proposal_id
+                );
+                None
+            }
+        }
+    }
+
+    /// Makes a finished edit its own undoable step, the proposal has to exist
+    fn commit_step(&mut self, proposal_id: ProposalID, step: Plan) {
+        let proposal = self.proposals.get_mut(proposal_id).unwrap();
+        proposal.set_ongoing_step(step);
+        proposal.start_new_step();
+
+        self.clear_previews(proposal_id);
+    }
+
+    pub fn undo(&mut self, proposal_id: ProposalID, token: PlayerToken, world: &mut World) {
+        InputJournal::global_first(world)
+            .record(JournaledInput::Undo { proposal_id, token }, world);
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
         self.proposals.get_mut(proposal_id).unwrap().undo();
         self.clear_previews(proposal_id);
     }
 
-    pub fn redo(&mut self, proposal_id: ProposalID, _: &mut World) {
+    pub fn redo(&mut self, proposal_id: ProposalID, token: PlayerToken, world: &mut World) {
+        InputJournal::global_first(world)
+            .record(JournaledInput::Redo { proposal_id, token }, world);
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
         self.proposals.get_mut(proposal_id).unwrap().redo();
         self.clear_previews(proposal_id);
     }
@@ -0,0 +1,187 @@
+//! Snaps candidate control points to existing geometry, so that roads which should meet
+//! actually end up connected. Existing geometry always wins over the grid and angle
+//! increments, which only apply if nothing is within the snapping radius.
+
+use descartes::{N, P2, V2, LinePath};
+use transport::transport_planning::{RoadPrototype, LanePrototype};
+use land_use::zone_planning::LotPrototype;
+use super::super::{PlanHistory, PlanResult, PrototypeKind, PrototypeID, GestureID,
+VersionedGesture};
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SnappingOptions {
+    /// How far away existing geometry can be to still be snapped to
+    pub radius: N,
+    pub grid_size: Option<N>,
+    /// In radians, relative to the x axis, measured from the previous point of the gesture
+    pub angle_increment: Option<N>,
+    /// Usually the gesture being edited, which shouldn't snap to itself
+    pub ignored_gesture: Option<GestureID>,
+}
+
+impl Default for SnappingOptions {
+    fn default() -> Self {
+        SnappingOptions {
+            radius: 3.0,
+            grid_size: None,
+            angle_increment: None,
+            ignored_gesture: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SnapTarget {
+    ControlPoint {
+        gesture_id: GestureID,
+        point_index: u32,
+    },
+    LanePath {
+        prototype_id: PrototypeID,
+    },
+    LotBoundary {
+        prototype_id: PrototypeID,
+    },
+    Angle {
+        angle: N,
+    },
+    Grid,
+    Nothing,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct SnappedPoint {
+    pub point: P2,
+    pub target: SnapTarget,
+}
+
+fn nearest_within<I: Iterator<Item = (P2, SnapTarget)>>(
+    candidate: P2,
+    radius: N,
+    points: I,
+) -> Option<SnappedPoint> {
+    points
+        .map(|(point, target)| ((point - candidate).norm(), point, target))
+        .filter(|&(distance, _, _)| distance <= radius)
+        .min_by(|a, b| a.0.partial_cmp(&b.0).expect("distances should be comparable"))
+        .map(|(_, point, target)| SnappedPoint { point, target })
+}
+
+fn projected_onto(path: &LinePath, candidate: P2, radius: N) -> Option<P2> {
+    path.project_with_max_distance(candidate, 0.5, radius)
+        .map(|(_, projected_point)| projected_point)
+}
+
+/// Control points of `history` are snapped to first, then lane paths of `result`
+/// and then lot boundaries of `result`. `previous` is the point that the candidate
+/// will be connected to, if any, and is needed for snapping to angle increments
+pub fn snap(
+    candidate: P2,
+    previous: Option<P2>,
+    history: &PlanHistory,
+    result: &PlanResult,
+    options: &SnappingOptions,
+) -> SnappedPoint {
+    let to_control_point = || {
+        nearest_within(
+            candidate,
+            options.radius,
+            history
+                .live_gestures()
+                .filter(|&(gesture_id, _)| Some(*gesture_id) != options.ignored_gesture)
+                .flat_map(|(gesture_id, &VersionedGesture(ref gesture, _))| {
+                    gesture
+                        .points
+                        .iter()
+                        .enumerate()
+                        .map(move |(point_index, point)| {
+                            (
+                                *point,
+                                SnapTarget::ControlPoint {
+                                    gesture_id: *gesture_id,
+                                    point_index: point_index as u32,
+                                },
+                            )
+                        })
+                }),
+        )
+    };
+
+    let to_lane_path = || {
+        nearest_within(
+            candidate,
+            options.radius,
+            result.prototypes.values().filter_map(|prototype| {
+                if let PrototypeKind::Road(RoadPrototype::Lane(LanePrototype(ref path, _))) =
+                    prototype.kind
+                {
+                    projected_onto(path, candidate, options.radius).map(|point| {
+                        (
+                            point,
+                            SnapTarget::LanePath {
+                                prototype_id: prototype.id,
+                            },
+                        )
+                    })
+                } else {
+                    None
+                }
+            }),
+        )
+    };
+
+    let to_lot_boundary = || {
+        nearest_within(
+            candidate,
+            options.radius,
+            result.prototypes.values().filter_map(|prototype| {
+                if let PrototypeKind::Lot(LotPrototype { ref lot, .. }) = prototype.kind {
+                    let boundary = lot.area.primitives[0].boundary.path();
+                    projected_onto(boundary, candidate, options.radius).map(|point| {
+                        (
+                            point,
+                            SnapTarget::LotBoundary {
+                                prototype_id: prototype.id,
+                            },
+                        )
+                    })
+                } else {
+                    None
+                }
+            }),
+        )
+    };
+
+    let to_angle = || match (previous, options.angle_increment) {
+        (Some(previous), Some(angle_increment)) if angle_increment > 0.0 => {
+            let delta = candidate - previous;
+            let angle = (delta.y.atan2(delta.x) / angle_increment).round() * angle_increment;
+            Some(SnappedPoint {
+                point: previous + V2::new(angle.cos(), angle.sin()) * delta.norm(),
+                target: SnapTarget::Angle { angle },
+            })
+        }
+        _ => None,
+    };
+
+    let to_grid = || match options.grid_size {
+        Some(grid_size) if grid_size > 0.0 => Some(SnappedPoint {
+            point: P2::new(
+                (candidate.x / grid_size).round() * grid_size,
+                (candidate.y / grid_size).round() * grid_size,
+            ),
+            target: SnapTarget::Grid,
+        }),
+        _ => None,
+    };
+
+    to_control_point()
+        .or_else(to_lane_path)
+        .or_else(to_lot_boundary)
+        .or_else(to_angle)
+        .or_else(to_grid)
+        .unwrap_or(SnappedPoint {
+            point: candidate,
+            target: SnapTarget::Nothing,
+        })
+}
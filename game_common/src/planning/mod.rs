@@ -1,17 +1,31 @@
 #![cfg_attr(feature = "cargo-clippy", allow(new_without_default_derive))]
 #![cfg_attr(feature = "cargo-clippy", allow(new_without_default))]
-use kay::{World, MachineID, ActorSystem, Actor};
-use compact::{CVec, COption, CHashMap};
+use kay::{World, MachineID, ActorSystem, Actor, External};
+use compact::{CVec, COption, CHashMap, CString};
 use descartes::{P2, AreaError};
-use util::random::{seed, RngCore, Uuid, uuid};
+use util::random::{seed, Rng, RngCore, Uuid, uuid};
 use std::hash::Hash;
+use std::collections::HashSet;
+use std::cell::RefCell;
 
-use transport::transport_planning::{RoadIntent, RoadPrototype};
-use land_use::zone_planning::{ZoneIntent, BuildingIntent, LotPrototype};
+use transport::transport_planning::{RoadIntent, RoadPrototype, TransportPlanningCache};
+use land_use::zone_planning::{ZoneIntent, BuildingIntent, LotPrototype, ZonePlanningCache};
 use construction::Construction;
+use savegame::SavegameManagerID;
+use journal::{InputJournal, JournaledInput};
+use browser_ui::{BrowserUI, BrowserUIID};
+use simulation::{Instant, Simulatable, SimulatableID};
 
 pub mod rendering;
 pub mod interaction;
+pub mod exchange;
+pub mod validation;
+pub mod impact;
+pub mod dependencies;
+pub mod review;
+pub mod branching;
+
+pub use self::exchange::{PlanExport, PlanExportSource};
 
 // idea for improvement:
 // - everything (Gestures, Prototypes) immutable (helps caching)
@@ -21,6 +35,7 @@ pub mod interaction;
 pub struct Gesture {
     pub points: CVec<P2>,
     pub intent: GestureIntent,
+    #[serde(default)]
     deleted: bool,
 }
 
@@ -32,6 +47,10 @@ impl Gesture {
             deleted: false,
         }
     }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
 }
 
 #[derive(Compact, Clone, Debug, Serialize, Deserialize)]
@@ -41,7 +60,7 @@ pub enum GestureIntent {
     Building(BuildingIntent),
 }
 
-#[derive(Copy, Clone, Hash, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
 pub struct GestureID(pub Uuid);
 
 impl GestureID {
@@ -92,6 +111,16 @@ impl PlanHistory {
         }
     }
 
+    /// Deleted gestures stay in the history (so deleting them can be undone),
+    /// but no prototypes are calculated from them
+    pub fn live_gestures<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (&'a GestureID, &'a VersionedGesture)> + 'a {
+        self.gestures
+            .pairs()
+            .filter(|&(_, &VersionedGesture(ref gesture, _))| !gesture.is_deleted())
+    }
+
     pub fn and_then<'a, I: IntoIterator<Item = &'a Plan>>(&self, plans: I) -> PlanHistory {
         let mut history = self.clone();
 
@@ -229,11 +258,72 @@ impl PlanHistoryUpdate {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    Draft,
+    UnderReview,
+    Implemented,
+    Rejected,
+}
+
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProposalMetadata {
+    pub name: CString,
+    pub description: CString,
+    /// Nobody for proposals that no player started, like the initial one
+    pub author: Option<PlayerID>,
+    pub created_at: Instant,
+    pub status: ProposalStatus,
+    /// The last step of the proposal when it was submitted for review
+    pub reviewed_step: Option<StepID>,
+    pub votes: CVec<Vote>,
+    /// The proposal this one was forked from, if any
+    pub forked_from: Option<ProposalID>,
+    /// The step that started the fork, holding the gestures it was forked with
+    pub fork_step: Option<StepID>,
+    /// Which version of another proposal's gesture a fork or merge step copied,
+    /// so merges can tell copied gestures from ones that were changed since
+    pub copied_versions: CVec<CopiedVersion>,
+    /// Implemented by the simulation itself, like households developing a lot,
+    /// so it can't be reverted on its own
+    pub artificial: bool,
+    /// Increased with every change, so UIs know when to update their copy
+    pub revision: u32,
+}
+
+impl ProposalMetadata {
+    pub fn new(author: Option<PlayerID>, created_at: Instant) -> ProposalMetadata {
+        ProposalMetadata {
+            name: CString::new(),
+            description: CString::new(),
+            author,
+            created_at,
+            status: ProposalStatus::Draft,
+            reviewed_step: None,
+            votes: CVec::new(),
+            forked_from: None,
+            fork_step: None,
+            copied_versions: CVec::new(),
+            artificial: false,
+            revision: 0,
+        }
+    }
+}
+
+impl Default for ProposalMetadata {
+    fn default() -> ProposalMetadata {
+        ProposalMetadata::new(None, Instant::new(0))
+    }
+}
+
 #[derive(Compact, Clone, Debug, Serialize, Deserialize)]
 pub struct Proposal {
     undoable_history: CVec<Plan>,
     ongoing: Plan,
     redoable_history: CVec<Plan>,
+    #[serde(default)]
+    pub metadata: ProposalMetadata,
 }
 
 impl Proposal {
@@ -242,6 +332,7 @@ impl Proposal {
             undoable_history: CVec::new(),
             ongoing: Plan::new(),
             redoable_history: CVec::new(),
+            metadata: ProposalMetadata::default(),
         }
     }
 
@@ -250,9 +341,19 @@ impl Proposal {
             undoable_history: vec![plan].into(),
             ongoing: Plan::new(),
             redoable_history: CVec::new(),
+            metadata: ProposalMetadata::default(),
         }
     }
 
+    pub fn with_metadata(self, metadata: ProposalMetadata) -> Proposal {
+        Proposal { metadata, ..self }
+    }
+
+    pub fn change_metadata<F: FnOnce(&mut ProposalMetadata)>(&mut self, change: F) {
+        change(&mut self.metadata);
+        self.metadata.revision += 1;
+    }
+
     pub fn start_new_step(&mut self) {
         self.undoable_history.push(self.ongoing.clone());
         self.ongoing = Plan::new();
@@ -294,6 +395,7 @@ impl Proposal {
             known_last_undoable: COption(self.undoable_history.last().map(|plan| plan.step_id)),
             known_ongoing: COption(Some(self.ongoing.step_id)),
             known_first_redoable: COption(self.redoable_history.first().map(|plan| plan.step_id)),
+            known_metadata_revision: COption(Some(self.metadata.revision)),
         }
     }
 
@@ -307,6 +409,7 @@ impl Proposal {
             == known_state.known_last_undoable.0
             && self.redoable_history.first().map(|plan| plan.step_id)
                 == known_state.known_first_redoable.0
+            && Some(self.metadata.revision) == known_state.known_metadata_revision.0
         {
             if known_state.known_ongoing.0 == Some(self.ongoing.step_id) {
                 ProposalUpdate::None
@@ -335,6 +438,7 @@ pub struct KnownProposalState {
     known_last_undoable: COption<StepID>,
     known_ongoing: COption<StepID>,
     known_first_redoable: COption<StepID>,
+    known_metadata_revision: COption<u32>,
 }
 
 impl Default for KnownProposalState {
@@ -343,6 +447,7 @@ impl Default for KnownProposalState {
             known_last_undoable: COption(None),
             known_ongoing: COption(None),
             known_first_redoable: COption(None),
+            known_metadata_revision: COption(None),
         }
     }
 }
@@ -477,7 +582,7 @@ pub enum PrototypeKind {
     Lot(LotPrototype),
 }
 
-#[derive(Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Debug)]
 pub struct PrototypeID(u64);
 
 impl PrototypeID {
@@ -589,31 +694,99 @@ impl ActionGroups {
     }
 }
 
+/// Why the result of a plan couldn't be calculated,
+/// naming the gestures involved so the UI can highlight them
+#[derive(Compact, Clone, Debug, Serialize, Deserialize)]
+pub struct PlanError {
+    pub gesture_ids: CVec<GestureID>,
+    pub location: P2,
+    pub reason: CString,
+}
+
+impl PlanError {
+    pub fn from_area_error(err: &AreaError, gesture_ids: Vec<GestureID>, location: P2) -> Self {
+        let mut reason = CString::new();
+        match *err {
+            AreaError::LeftOver(ref left_over) => reason.push_str(&format!(
+                "Overlapping shapes couldn't be combined ({})",
+                left_over
+            )),
+            ref other => reason.push_str(&format!("Invalid shape ({:?})", other)),
+        }
+
+        PlanError {
+            gesture_ids: gesture_ids.into(),
+            location,
+            reason,
+        }
+    }
+}
+
+impl ::std::fmt::Display for PlanError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(
+            f,
+            "{} near ({:.1}, {:.1}), caused by gestures {:?}",
+            &*self.reason,
+            self.location.x,
+            self.location.y,
+            &*self.gesture_ids
+        )
+    }
+}
+
+/// Keeps the prototypes calculated for unchanged gestures between calculations,
+/// so that editing one gesture doesn't recalculate the whole city
+#[derive(Default)]
+pub struct PlanResultCache {
+    pub transport: TransportPlanningCache,
+    pub zones: ZonePlanningCache,
+}
+
 impl PlanHistory {
-    pub fn calculate_result(&self) -> Result<PlanResult, AreaError> {
+    pub fn calculate_result(&self) -> Result<PlanResult, PlanError> {
+        self.calculate_result_incrementally(&mut PlanResultCache::default())
+    }
+
+    pub fn calculate_result_incrementally(
+        &self,
+        cache: &mut PlanResultCache,
+    ) -> Result<PlanResult, PlanError> {
         let mut result = PlanResult {
             prototypes: CHashMap::new(),
         };
 
-        for prototype_fn in &[
-            ::transport::transport_planning::calculate_prototypes,
-            ::land_use::zone_planning::calculate_prototypes,
-        ] {
-            let new_prototypes = prototype_fn(self, &result)?;
+        let transport_prototypes = ::transport::transport_planning::calculate_prototypes(
+            self,
+            &result,
+            &mut cache.transport,
+        )?;
+        result.add_prototypes(transport_prototypes);
 
-            for (id, prototype) in new_prototypes
-                .into_iter()
-                .map(|prototype| (prototype.id, prototype))
-            {
-                result.prototypes.insert(id, prototype);
-            }
-        }
+        let zone_prototypes =
+            ::land_use::zone_planning::calculate_prototypes(self, &result, &mut cache.zones)?;
+        result.add_prototypes(zone_prototypes);
+
+        cache.transport.finish_generation();
+        cache.zones.finish_generation();
 
         Ok(result)
     }
 }
 
+impl PlanResult {
+    fn add_prototypes(&mut self, prototypes: Vec<Prototype>) {
+        for prototype in prototypes {
+            self.prototypes.insert(prototype.id, prototype);
+        }
+    }
+}
+
 use self::interaction::PlanManagerUIState;
+use self::impact::ImpactReport;
+use self::dependencies::RevertedGround;
+use self::review::{GovernanceSettings, Vote};
+use self::branching::CopiedVersion;
 
 #[derive(Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Debug)]
 pub struct ProposalID(pub Uuid);
@@ -622,8 +795,25 @@ impl ProposalID {
     pub fn new() -> ProposalID {
         ProposalID(uuid())
     }
+
+    /// Deterministic, so that proposals created in reaction to journaled inputs
+    /// get the same IDs again when the journal is replayed
+    pub fn derived_from<H: Hash>(influences: H) -> ProposalID {
+        ProposalID(Uuid::from_random_bytes(seed(influences).gen()))
+    }
 }
 
+/// Identifies a player, even across reconnects. Unlike `MachineID`s, which all
+/// browser clients share, every player has their own. Player IDs are public,
+/// so they are only used to show who did what
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct PlayerID(pub Uuid);
+
+/// Handed out by the server to each player when their browser joins and only known
+/// to that browser. Inputs on behalf of a player carry it, so players can't pose as others
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct PlayerToken(pub Uuid);
+
 #[derive(Compact, Clone)]
 pub struct PlanManager {
     id: PlanManagerID,
@@ -632,6 +822,10 @@ pub struct PlanManager {
     proposals: CHashMap<ProposalID, Proposal>,
     implemented_proposals: CHashMap<ProposalID, Proposal>,
     ui_state: CHashMap<MachineID, PlanManagerUIState>,
+    result_cache: External<RefCell<PlanResultCache>>,
+    current_instant: Instant,
+    governance: GovernanceSettings,
+    players: CHashMap<PlayerToken, PlayerID>,
 }
 
 impl PlanManager {
@@ -645,17 +839,36 @@ impl PlanManager {
                 .collect(),
             implemented_proposals: CHashMap::new(),
             ui_state: CHashMap::new(),
+            result_cache: External::new(RefCell::new(PlanResultCache::default())),
+            current_instant: Instant::new(0),
+            governance: GovernanceSettings::default(),
+            players: CHashMap::new(),
         }
     }
 
+    /// Empty proposals that machines get to continue planning on belong to
+    /// the player whose action made them necessary, if any
+    fn new_proposal(&self, creator: Option<PlayerID>) -> Proposal {
+        Proposal::new().with_metadata(ProposalMetadata::new(creator, self.current_instant))
+    }
+
     pub fn get_current_version_of(
         &self,
         gesture_id: GestureID,
         proposal_id: ProposalID,
     ) -> &Gesture {
+        self.try_get_current_version_of(gesture_id, proposal_id)
+            .expect("Expected proposal and gesture (that point should be added to) to exist!")
+    }
+
+    /// Like `get_current_version_of`, for proposals or gestures that might not exist (anymore)
+    pub fn try_get_current_version_of(
+        &self,
+        gesture_id: GestureID,
+        proposal_id: ProposalID,
+    ) -> Option<&Gesture> {
         self.proposals
-            .get(proposal_id)
-            .expect("Expected proposal to exist")
+            .get(proposal_id)?
             .current_history()
             .iter()
             .rfold(None, |found, step| {
@@ -667,19 +880,38 @@ impl PlanManager {
                     .get(gesture_id)
                     .map(|VersionedGesture(ref g, _)| g),
             ).next()
-            .expect("Expected gesture (that point should be added to) to exist!")
     }
 
     pub fn implement(&mut self, proposal_id: ProposalID, world: &mut World) {
-        let proposal = self
-            .proposals
-            .remove(proposal_id)
-            .expect("Proposal should exist");
+        InputJournal::global_first(world).record(JournaledInput::Implement { proposal_id }, world);
 
-        self.master_plan = proposal.apply_to(&self.master_plan);
+        if self.may_implement(proposal_id) {
+            self.implement_without_review(proposal_id, world);
+        } else {
+            println!("Proposal {:?} has to be approved first", proposal_id);
+            BrowserUI::global_broadcast(world).on_review_required(proposal_id, world);
+        }
+    }
+
+    fn implement_without_review(&mut self, proposal_id: ProposalID, world: &mut World) {
+        let new_master_plan = self
+            .proposals
+            .get(proposal_id)
+            .expect("Proposal should exist")
+            .apply_to(&self.master_plan);
 
-        match self.master_plan.calculate_result() {
+        // a proposal that can't be calculated is not implemented at all,
+        // so it can still be fixed
+        match new_master_plan.calculate_result_incrementally(&mut self.result_cache.borrow_mut()) {
             Ok(result) => {
+                let mut proposal = self
+                    .proposals
+                    .remove(proposal_id)
+                    .expect("Proposal should exist");
+                proposal.change_metadata(|metadata| metadata.status = ProposalStatus::Implemented);
+                let author = proposal.metadata.author;
+                self.master_plan = new_master_plan;
+
                 let (actions, new_prototypes) = self.master_result.actions_to(&result);
                 Construction::global_first(world).implement(actions, new_prototypes, world);
                 self.implemented_proposals.insert(proposal_id, proposal);
@@ -693,9 +925,10 @@ impl PlanManager {
 
                 for (machine, current_proposal) in potentially_affected_ui_states {
                     if current_proposal == proposal_id {
-                        let new_proposal_id = ProposalID::new();
+                        let new_proposal_id = ProposalID::derived_from((proposal_id, machine.0));
 
-                        self.proposals.insert(new_proposal_id, Proposal::new());
+                        let new_proposal = self.new_proposal(author);
+                        self.proposals.insert(new_proposal_id, new_proposal);
 
                         self.switch_to(machine, new_proposal_id, world);
                     }
@@ -708,14 +941,52 @@ impl PlanManager {
                     }
                 }
             }
-            Err(err) => match err {
-                ::descartes::AreaError::LeftOver(string) => {
-                    println!("Implement Plan Error: {}", string);
-                }
-                _ => {
-                    println!("Implement Plan Error: {:?}", err);
-                }
-            },
+            Err(err) => {
+                println!("Can't implement proposal {:?}: {}", proposal_id, err);
+                BrowserUI::global_broadcast(world).on_implement_blocked(proposal_id, err, world);
+            }
+        }
+    }
+
+    /// Tells `requester` what implementing the proposal would change, followed by
+    /// the households that would be displaced from each building that would be destroyed
+    pub fn get_impact_report(
+        &mut self,
+        proposal_id: ProposalID,
+        requester: BrowserUIID,
+        world: &mut World,
+    ) {
+        // the requester might not know yet that the proposal was implemented or deleted
+        let new_master_plan = match self.proposals.get(proposal_id) {
+            Some(proposal) => proposal.apply_to(&self.master_plan),
+            None => {
+                let mut reason = CString::new();
+                reason.push_str("The proposal was already implemented or deleted");
+                requester.on_impact_report_unavailable(proposal_id, reason, world);
+                return;
+            }
+        };
+
+        match new_master_plan.calculate_result_incrementally(&mut self.result_cache.borrow_mut()) {
+            Ok(result) => {
+                let (actions, _) = self.master_result.actions_to(&result);
+                let (report, lots_with_destroyed_buildings) =
+                    ImpactReport::from_actions(&actions, &self.master_result, &result);
+
+                requester.on_impact_report(
+                    proposal_id,
+                    report,
+                    lots_with_destroyed_buildings.len() as u32,
+                    world,
+                );
+                Construction::global_first(world).report_displaced_households(
+                    lots_with_destroyed_buildings,
+                    proposal_id,
+                    requester,
+                    world,
+                );
+            }
+            Err(err) => requester.on_implement_blocked(proposal_id, err, world),
         }
     }
 
@@ -730,25 +1001,434 @@ impl PlanManager {
             .all(|prototype_id| self.master_result.prototypes.contains_key(*prototype_id))
         {
             let proposal_id = ProposalID::new();
-            self.proposals.insert(proposal_id, proposal.clone());
-            self.implement(proposal_id, world);
+            let mut proposal = proposal.clone();
+            proposal.metadata.artificial = true;
+            self.proposals.insert(proposal_id, proposal);
+            self.implement_without_review(proposal_id, world);
+            // still there if it couldn't be implemented
+            self.proposals.remove(proposal_id);
         } else {
             println!("Tried to implement artificial proposal based on outdated prototypes");
         }
     }
 }
 
+impl PlanManager {
+    /// Both open and implemented proposals, except those implemented by the simulation itself
+    pub fn list_proposals(&mut self, requester: BrowserUIID, world: &mut World) {
+        let all_metadata = self
+            .proposals
+            .pairs()
+            .chain(self.implemented_proposals.pairs())
+            .filter(|(_, proposal)| !proposal.metadata.artificial)
+            .map(|(proposal_id, proposal)| (*proposal_id, proposal.metadata.clone()))
+            .collect();
+        requester.on_proposal_list(all_metadata, world);
+    }
+
+    pub fn rename_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        name: &CString,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::RenameProposal {
+                proposal_id,
+                name: name.clone(),
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+            proposal.change_metadata(|metadata| metadata.name = name.clone());
+        }
+    }
+
+    pub fn describe_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        description: &CString,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::DescribeProposal {
+                proposal_id,
+                description: description.clone(),
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+            proposal.change_metadata(|metadata| metadata.description = description.clone());
+        }
+    }
+
+    /// Only open proposals can be deleted, implemented ones have to be reverted first.
+    /// With governance, only their author may delete them.
+    /// Machines that were working on the proposal get a new, empty one
+    pub fn delete_proposal(
+        &mut self,
+        proposal_id: ProposalID,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::DeleteProposal {
+                proposal_id,
+                token,
+            },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        if self.proposals.remove(proposal_id).is_none() {
+            println!("Tried to delete proposal {:?}, which isn't open", proposal_id);
+            return;
+        }
+
+        let affected_machines = self
+            .ui_state
+            .pairs()
+            .filter(|(_, state)| state.current_proposal == proposal_id)
+            .map(|(machine, _)| *machine)
+            .collect::<Vec<_>>();
+        let player = self.player_of(token);
+
+        for machine in affected_machines {
+            let new_proposal_id = ProposalID::derived_from((proposal_id, machine.0));
+            let new_proposal = self.new_proposal(player);
+            self.proposals.insert(new_proposal_id, new_proposal);
+            self.switch_to(machine, new_proposal_id, world);
+        }
+    }
+}
+
+impl Simulatable for PlanManager {
+    fn tick(&mut self, _dt: f32, current_instant: Instant, _world: &mut World) {
+        self.current_instant = current_instant;
+    }
+}
+
+impl PlanManager {
+    /// From the first to the most recently implemented proposal,
+    /// leaving out proposals that didn't change any gestures
+    fn implemented_proposals_in_order(&self) -> Vec<ProposalID> {
+        let mut with_positions = self
+            .implemented_proposals
+            .pairs()
+            .filter_map(|(proposal_id, proposal)| {
+                proposal
+                    .current_history()
+                    .iter()
+                    .filter_map(|plan| {
+                        self.master_plan
+                            .steps
+                            .iter()
+                            .position(|step_id| *step_id == plan.step_id)
+                    }).next()
+                    .map(|position| (position, *proposal_id))
+            }).collect::<Vec<_>>();
+
+        with_positions.sort_by_key(|&(position, _)| position);
+        with_positions
+            .into_iter()
+            .map(|(_, proposal_id)| proposal_id)
+            .collect()
+    }
+
+    /// Reverts the given or else the most recently implemented proposal, which becomes
+    /// an open proposal again. Proposals implemented by the simulation itself are skipped.
+    /// Refused if later proposals changed the same gestures or build on them,
+    /// and with governance, if the player with `token` isn't its author
+    pub fn revert(
+        &mut self,
+        maybe_proposal_id: Option<ProposalID>,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::Revert {
+                proposal_id: maybe_proposal_id,
+                token,
+            },
+            world,
+        );
+
+        let in_order = self.implemented_proposals_in_order();
+
+        let (proposal_id, mut proposal) = match maybe_proposal_id
+            .or_else(|| {
+                in_order
+                    .iter()
+                    .rev()
+                    .find(|proposal_id| {
+                        self.implemented_proposals
+                            .get(**proposal_id)
+                            .map(|proposal| !proposal.metadata.artificial)
+                            .unwrap_or(false)
+                    }).cloned()
+            }).and_then(|proposal_id| {
+                self.implemented_proposals
+                    .get(proposal_id)
+                    .map(|proposal| (proposal_id, proposal.clone()))
+            }) {
+            Some((_, ref proposal)) if proposal.metadata.artificial => {
+                println!("Proposals implemented by the simulation can't be reverted");
+                return;
+            }
+            Some(found) => found,
+            None => {
+                println!("No implemented proposal to revert");
+                return;
+            }
+        };
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        let position = in_order
+            .iter()
+            .position(|other_id| *other_id == proposal_id)
+            .unwrap_or(in_order.len());
+
+        let reverted_gesture_ids = proposal
+            .current_history()
+            .iter()
+            .flat_map(|plan| plan.gestures.keys().cloned())
+            .collect::<HashSet<_>>();
+
+        let reverted_ground = RevertedGround::of(
+            proposal
+                .current_history()
+                .iter()
+                .flat_map(|plan| plan.gestures.values()),
+        );
+
+        let dependent_proposals = in_order
+            .iter()
+            .skip(position + 1)
+            .filter(|later_id| {
+                self.implemented_proposals
+                    .get(**later_id)
+                    .expect("should be implemented")
+                    .current_history()
+                    .iter()
+                    .any(|plan| {
+                        plan.gestures.pairs().any(|(gesture_id, gesture)| {
+                            reverted_gesture_ids.contains(gesture_id)
+                                || reverted_ground.is_built_on_by(gesture)
+                        })
+                    })
+            }).cloned()
+            .collect::<CVec<_>>();
+
+        if !dependent_proposals.is_empty() {
+            println!(
+                "Can't revert proposal {:?}, {:?} depend on it",
+                proposal_id, dependent_proposals
+            );
+            BrowserUI::global_broadcast(world).on_revert_refused(
+                proposal_id,
+                dependent_proposals,
+                COption(None),
+                world,
+            );
+            return;
+        }
+
+        let reverted_steps = proposal
+            .current_history()
+            .iter()
+            .map(|plan| plan.step_id)
+            .collect::<HashSet<_>>();
+
+        // restored gestures become part of a new step, so UIs that already
+        // know the steps they were originally part of still pick them up
+        let revert_step_id = StepID(Uuid::from_random_bytes(
+            seed((proposal_id, self.master_plan.steps.len())).gen(),
+        ));
+
+        let mut new_master_plan = self.master_plan.clone();
+        new_master_plan
+            .steps
+            .retain(|step_id| !reverted_steps.contains(step_id));
+
+        for gesture_id in reverted_gesture_ids {
+            let version_before = in_order[..position]
+                .iter()
+                .rev()
+                .filter_map(|earlier_id| {
+                    self.implemented_proposals
+                        .get(*earlier_id)
+                        .expect("should be implemented")
+                        .current_history()
+                        .iter()
+                        .rev()
+                        .filter_map(|plan| plan.gestures.get(gesture_id))
+                        .next()
+                }).next();
+
+            if let Some(gesture) = version_before {
+                new_master_plan
+                    .gestures
+                    .insert(gesture_id, VersionedGesture(gesture.clone(), revert_step_id));
+            } else {
+                new_master_plan.gestures.remove(gesture_id);
+            }
+        }
+
+        new_master_plan.steps.push(revert_step_id);
+
+        match new_master_plan.calculate_result_incrementally(&mut self.result_cache.borrow_mut()) {
+            Ok(result) => {
+                let (actions, new_prototypes) = self.master_result.actions_to(&result);
+                Construction::global_first(world).implement(actions, new_prototypes, world);
+                self.master_plan = new_master_plan;
+                self.master_result = result;
+
+                self.implemented_proposals.remove(proposal_id);
+                proposal.change_metadata(|metadata| metadata.status = ProposalStatus::Draft);
+                self.proposals.insert(proposal_id, proposal);
+
+                let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
+                for other_proposal_id in all_proposal_ids {
+                    self.clear_previews(other_proposal_id);
+                }
+            }
+            Err(err) => {
+                println!("Can't revert proposal {:?}: {}", proposal_id, err);
+                BrowserUI::global_broadcast(world).on_revert_refused(
+                    proposal_id,
+                    CVec::new(),
+                    COption(Some(err)),
+                    world,
+                );
+            }
+        }
+    }
+}
+
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct PlanningState {
+    pub master_plan: PlanHistory,
+    pub proposals: CHashMap<ProposalID, Proposal>,
+    pub implemented_proposals: CHashMap<ProposalID, Proposal>,
+    #[serde(default)]
+    pub governance: GovernanceSettings,
+    /// Kept so players stay who they are when the city is loaded again
+    #[serde(default, with = "::util::serde_pairs")]
+    pub players: CHashMap<PlayerToken, PlayerID>,
+}
+
+impl PlanManager {
+    pub fn save_state(&mut self, saver: SavegameManagerID, world: &mut World) {
+        saver.on_planning_state(
+            PlanningState {
+                master_plan: self.master_plan.clone(),
+                proposals: self.proposals.clone(),
+                implemented_proposals: self.implemented_proposals.clone(),
+                governance: self.governance,
+                players: self.players.clone(),
+            },
+            world,
+        );
+    }
+
+    /// Savegames from before actors were stored don't contain the constructed city,
+    /// so it has to be rebuilt from the restored master plan
+    pub fn restore(
+        &mut self,
+        state: &PlanningState,
+        rebuild_construction: bool,
+        world: &mut World,
+    ) {
+        self.master_plan = state.master_plan.clone();
+        self.proposals = state.proposals.clone();
+        self.implemented_proposals = state.implemented_proposals.clone();
+        self.governance = state.governance;
+        self.players = state.players.clone();
+        BrowserUI::global_broadcast(world).on_governance_update(self.governance, world);
+
+        match self
+            .master_plan
+            .calculate_result_incrementally(&mut self.result_cache.borrow_mut())
+        {
+            Ok(result) => {
+                if rebuild_construction {
+                    let (actions, new_prototypes) = self.master_result.actions_to(&result);
+                    Construction::global_first(world).implement(actions, new_prototypes, world);
+                }
+                self.master_result = result;
+            }
+            Err(err) => {
+                println!("Restore Plan Error: {}", err);
+            }
+        }
+
+        if self.proposals.is_empty() {
+            let new_proposal = self.new_proposal(None);
+            self.proposals.insert(ProposalID::new(), new_proposal);
+        }
+
+        let fallback_proposal_id = *self
+            .proposals
+            .keys()
+            .next()
+            .expect("just ensured there is a proposal");
+
+        let machines_with_stale_proposal = self
+            .ui_state
+            .pairs()
+            .filter(|(_, state)| !self.proposals.contains_key(state.current_proposal))
+            .map(|(machine, _)| *machine)
+            .collect::<Vec<_>>();
+
+        for machine in machines_with_stale_proposal {
+            self.switch_to(machine, fallback_proposal_id, world);
+        }
+
+        let all_proposal_ids = self.proposals.keys().cloned().collect::<Vec<_>>();
+        for proposal_id in all_proposal_ids {
+            self.clear_previews(proposal_id);
+        }
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<PlanManager>();
     auto_setup(system);
     rendering::auto_setup(system);
     interaction::setup(system);
+    exchange::setup(system);
+    review::setup(system);
+    branching::setup(system);
 }
 
 pub fn spawn(world: &mut World) -> PlanManagerID {
-    let initial_proposal_id = ProposalID::new();
+    let initial_proposal_id = ProposalID::derived_from("initial proposal");
     let plan_manager = PlanManagerID::spawn(initial_proposal_id, world);
-    plan_manager.switch_to(MachineID(0), initial_proposal_id, world);
+    plan_manager.switch_to(world.local_machine_id(), initial_proposal_id, world);
     plan_manager
 }
 
@@ -0,0 +1,245 @@
+//! In multiplayer cities, proposals can be required to be reviewed before they are
+//! implemented: the author submits a proposal, the other players vote on it and it
+//! is implemented as soon as enough of them approve.
+//! Only the author can then still submit, rename, delete or revert a proposal.
+//! Players are told apart by secret tokens that the server hands out when they join.
+
+use kay::{World, ActorSystem};
+use compact::COption;
+use browser_ui::{BrowserUI, BrowserUIID};
+use journal::{InputJournal, JournaledInput};
+use util::random::unpredictable_uuid;
+use super::{PlanManager, PlanManagerID, Proposal, ProposalID, ProposalStatus, PlayerID, PlayerToken};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct GovernanceSettings {
+    /// If not enabled, anybody can implement or change any proposal right away
+    pub enabled: bool,
+    /// How many players other than the author need to approve a proposal, at least one.
+    /// The same number of rejections rejects it
+    pub quorum: u8,
+}
+
+impl Default for GovernanceSettings {
+    fn default() -> Self {
+        GovernanceSettings {
+            enabled: false,
+            quorum: 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Vote {
+    pub voter: PlayerID,
+    pub approve: bool,
+}
+
+impl Proposal {
+    /// Votes only count for the version of the proposal that was submitted
+    fn changed_since_submitted(&self) -> bool {
+        self.metadata.reviewed_step != self.current_history().last().map(|plan| plan.step_id)
+    }
+
+    fn n_votes(&self, approve: bool) -> usize {
+        self.metadata
+            .votes
+            .iter()
+            .filter(|vote| vote.approve == approve)
+            .count()
+    }
+}
+
+impl PlanManager {
+    /// Browsers join with the token they got the last time, if any, to stay the same player.
+    /// Unknown tokens aren't taken over, the browser becomes a new player instead
+    pub fn join_as_player(
+        &mut self,
+        known_token: &COption<PlayerToken>,
+        ui: BrowserUIID,
+        world: &mut World,
+    ) {
+        let known_player =
+            known_token.and_then(|token| self.players.get(token).map(|player| (token, *player)));
+
+        let (token, player) = known_player.unwrap_or_else(|| {
+            let token = PlayerToken(unpredictable_uuid());
+            let player = PlayerID(unpredictable_uuid());
+            self.add_player(token, player, world);
+            (token, player)
+        });
+
+        ui.on_player_joined(token, player, world);
+    }
+
+    /// Only used directly when replaying a journal, players that already
+    /// have a token can't get another one, so nobody can pose as them
+    pub fn add_player(&mut self, token: PlayerToken, player: PlayerID, world: &mut World) {
+        if self.players.contains_key(token) || self.players.values().any(|p| *p == player) {
+            println!("Player {:?} already joined", player);
+            return;
+        }
+
+        InputJournal::global_first(world)
+            .record(JournaledInput::AddPlayer { token, player }, world);
+        self.players.insert(token, player);
+    }
+
+    pub fn player_of(&self, token: PlayerToken) -> Option<PlayerID> {
+        self.players.get(token).cloned()
+    }
+
+    pub fn may_implement(&self, proposal_id: ProposalID) -> bool {
+        !self.governance.enabled || self.proposals.get(proposal_id).map_or(false, |proposal| {
+            proposal.metadata.status == ProposalStatus::UnderReview
+                && !proposal.changed_since_submitted()
+                && proposal.n_votes(true) >= self.governance.quorum as usize
+        })
+    }
+
+    /// With governance, only the author of a proposal may edit, submit, rename, delete
+    /// or revert it. Proposals without an author can only be forked
+    pub fn may_change(&self, proposal_id: ProposalID, token: PlayerToken) -> bool {
+        !self.governance.enabled || self
+            .proposals
+            .get(proposal_id)
+            .or_else(|| self.implemented_proposals.get(proposal_id))
+            .and_then(|proposal| proposal.metadata.author)
+            .map_or(false, |author| Some(author) == self.player_of(token))
+    }
+
+    /// Tells the player with `token` that only the author may do what they tried
+    pub fn refuse_change(&self, proposal_id: ProposalID, token: PlayerToken, world: &mut World) {
+        println!("Only the author may change proposal {:?}", proposal_id);
+        if let Some(player) = self.player_of(token) {
+            BrowserUI::global_broadcast(world).on_change_refused(proposal_id, player, world);
+        }
+    }
+
+    pub fn set_governance(&mut self, settings: GovernanceSettings, world: &mut World) {
+        // otherwise, every proposal would be approved and rejected as soon as it is submitted
+        if settings.enabled && settings.quorum == 0 {
+            println!("Proposals need to be approved by at least one other player");
+            return;
+        }
+
+        InputJournal::global_first(world).record(JournaledInput::SetGovernance { settings }, world);
+
+        self.governance = settings;
+        BrowserUI::global_broadcast(world).on_governance_update(settings, world);
+    }
+
+    pub fn get_governance(&mut self, requester: BrowserUIID, world: &mut World) {
+        requester.on_governance_update(self.governance, world);
+    }
+
+    /// Also used to resubmit rejected proposals or proposals that changed since submitting
+    pub fn submit_for_review(
+        &mut self,
+        proposal_id: ProposalID,
+        token: PlayerToken,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::SubmitForReview { proposal_id, token },
+            world,
+        );
+
+        if !self.may_change(proposal_id, token) {
+            self.refuse_change(proposal_id, token, world);
+            return;
+        }
+
+        if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+            let reviewed_step = proposal.current_history().last().map(|plan| plan.step_id);
+            proposal.change_metadata(|metadata| {
+                metadata.status = ProposalStatus::UnderReview;
+                metadata.reviewed_step = reviewed_step;
+                metadata.votes.clear();
+            });
+        }
+    }
+
+    /// Authors can't vote on their own proposals, a later vote of the same player replaces
+    /// their earlier one. Reaching the quorum implements or rejects the proposal
+    pub fn vote(
+        &mut self,
+        proposal_id: ProposalID,
+        token: PlayerToken,
+        approve: bool,
+        world: &mut World,
+    ) {
+        InputJournal::global_first(world).record(
+            JournaledInput::Vote {
+                proposal_id,
+                token,
+                approve,
+            },
+            world,
+        );
+
+        let player = match self.player_of(token) {
+            Some(player) => player,
+            None => {
+                println!("Only players that joined can vote");
+                return;
+            }
+        };
+
+        let quorum = self.governance.quorum as usize;
+
+        let (approved, rejected) = {
+            let proposal = match self.proposals.get_mut(proposal_id) {
+                Some(proposal) => proposal,
+                None => {
+                    println!("There is no proposal {:?} to vote on", proposal_id);
+                    return;
+                }
+            };
+
+            if proposal.metadata.status != ProposalStatus::UnderReview
+                || proposal.metadata.author == Some(player)
+            {
+                println!("Can't vote on proposal {:?}", proposal_id);
+                return;
+            }
+
+            if proposal.changed_since_submitted() {
+                println!("Proposal {:?} changed since it was submitted", proposal_id);
+                proposal.change_metadata(|metadata| {
+                    metadata.status = ProposalStatus::Draft;
+                    metadata.votes.clear();
+                });
+                return;
+            }
+
+            proposal.change_metadata(|metadata| {
+                metadata.votes.retain(|vote| vote.voter != player);
+                metadata.votes.push(Vote {
+                    voter: player,
+                    approve,
+                });
+            });
+
+            (
+                proposal.n_votes(true) >= quorum,
+                proposal.n_votes(false) >= quorum,
+            )
+        };
+
+        if approved {
+            self.implement_without_review(proposal_id, world);
+        } else if rejected {
+            if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+                proposal.change_metadata(|metadata| metadata.status = ProposalStatus::Rejected);
+            }
+        }
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    auto_setup(system);
+}
+
+pub mod kay_auto;
+pub use self::kay_auto::*;
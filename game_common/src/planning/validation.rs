@@ -0,0 +1,109 @@
+//! Checks a calculated plan result for things that are not outright errors,
+//! but probably not what the planner wanted, like roads that lead nowhere.
+
+use compact::CVec;
+use descartes::{P2, Area, PointContainer};
+use style::dimensions::MIN_SWITCHING_LANE_LENGTH;
+use transport::transport_planning::{RoadPrototype, LanePrototype};
+use land_use::zone_planning::{LotPrototype, LotOccupancy};
+use util::grid_accelerator::{bounding_box_of_area, bounding_boxes_overlap};
+use super::{PlanResult, PrototypeKind, PrototypeID, ActionGroups};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PlanWarningKind {
+    ShortLane,
+    DeadEnd,
+    LotWithoutRoad,
+    BuildingOnPavedArea,
+    UnconnectedIncomingLane,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct PlanWarning {
+    pub kind: PlanWarningKind,
+    pub prototype_id: PrototypeID,
+    pub location: P2,
+}
+
+fn center_of(area: &Area) -> P2 {
+    let bounding_box = bounding_box_of_area(area);
+    P2::from_coordinates((bounding_box.min.coords + bounding_box.max.coords) / 2.0)
+}
+
+/// Only prototypes that `actions` construct or morph are checked,
+/// so planners aren't warned about problems that already exist in the master plan
+pub fn validate(result: &PlanResult, actions: &ActionGroups) -> CVec<PlanWarning> {
+    let paved_areas = result
+        .prototypes
+        .values()
+        .filter_map(|prototype| match prototype.kind {
+            PrototypeKind::Road(RoadPrototype::PavedArea(ref area)) => Some(area),
+            PrototypeKind::Road(RoadPrototype::Intersection(ref intersection)) => {
+                Some(&intersection.area)
+            }
+            _ => None,
+        }).map(|area| (area, bounding_box_of_area(area)))
+        .collect::<Vec<_>>();
+
+    let mut warnings = CVec::new();
+
+    for prototype in result.prototypes.values() {
+        if actions.corresponding_action(prototype.id).is_none() {
+            continue;
+        }
+
+        let warn = |kind, location| PlanWarning {
+            kind,
+            prototype_id: prototype.id,
+            location,
+        };
+
+        match prototype.kind {
+            PrototypeKind::Road(RoadPrototype::Lane(LanePrototype(ref path, _))) => {
+                if path.length() < MIN_SWITCHING_LANE_LENGTH {
+                    warnings.push(warn(
+                        PlanWarningKind::ShortLane,
+                        path.along(path.length() / 2.0),
+                    ));
+                }
+            }
+            PrototypeKind::Road(RoadPrototype::Intersection(ref intersection)) => {
+                if intersection.is_dead_end() {
+                    warnings.push(warn(PlanWarningKind::DeadEnd, center_of(&intersection.area)));
+                }
+                for position in intersection.unconnected_incoming_positions() {
+                    warnings.push(warn(PlanWarningKind::UnconnectedIncomingLane, position));
+                }
+            }
+            PrototypeKind::Lot(LotPrototype {
+                ref lot,
+                ref occupancy,
+            }) => {
+                if lot.road_boundaries.is_empty() {
+                    warnings.push(warn(PlanWarningKind::LotWithoutRoad, lot.center_point()));
+                }
+
+                if let LotOccupancy::Occupied(_) = *occupancy {
+                    // the building itself is only generated during construction,
+                    // but it will stand around the center of its lot
+                    let building_location = lot.center_point();
+                    let lot_bounding_box = bounding_box_of_area(&lot.area);
+                    let on_paved_area = paved_areas.iter().any(|&(area, ref bounding_box)| {
+                        bounding_boxes_overlap(&lot_bounding_box, bounding_box)
+                            && area.contains(building_location)
+                    });
+
+                    if on_paved_area {
+                        warnings.push(warn(
+                            PlanWarningKind::BuildingOnPavedArea,
+                            building_location,
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,145 @@
+//! The actor types that are stored in savegames, and how their saved IDs
+//! are replaced with the IDs that the same actors have after restoring.
+
+use kay::{World, Actor, RawID, TypedID};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use std::collections::{HashMap, HashSet};
+
+use construction::Construction;
+use transport::lane::{Lane, LaneID, SwitchLane, SwitchLaneID};
+use transport::pathfinding::trip::{Trip, TripID, TripCreator};
+use economy::market::{Market, TripCostEstimator, TripCostEstimatorID};
+use economy::immigration_and_development::{ImmigrationManager, DevelopmentManager};
+use economy::households::tasks::TaskEndScheduler;
+use economy::households::family::{Family, FamilyID};
+use economy::households::grocery_shop::{GroceryShop, GroceryShopID};
+use economy::households::grain_farm::{GrainFarm, GrainFarmID};
+use economy::households::cow_farm::{CowFarm, CowFarmID};
+use economy::households::vegetable_farm::{VegetableFarm, VegetableFarmID};
+use economy::households::mill::{Mill, MillID};
+use economy::households::bakery::{Bakery, BakeryID};
+use economy::households::neighboring_town_trade::{NeighboringTownTrade, NeighboringTownTradeID};
+use land_use::vacant_lots::{VacantLot, VacantLotID};
+use land_use::buildings::{Building, BuildingID};
+use super::PersistentID;
+
+/// Lists every persistent actor type once. In savegames, actors are stored under the name
+/// of their type. Actors that exist exactly once are spawned at startup, the others
+/// are spawned from their saved state with the `restore` constructor of their ID type.
+macro_rules! persistent_actors {
+    (singletons: $($singleton:ident),*; spawned: $($actor:ident => $actor_id:ident),*) => {
+        /// All persistent actors of this machine, as one broadcast per actor type
+        pub fn all_persistent(world: &mut World) -> Vec<(&'static str, PersistentID)> {
+            vec![
+                $((stringify!($singleton), $singleton::local_broadcast(world).into()),)*
+                $((stringify!($actor), $actor::local_broadcast(world).into()),)*
+            ]
+        }
+
+        /// Checks that a saved state fits the actor type of its kind
+        pub fn check(kind: &str, state: &Value) -> Result<(), String> {
+            $(if kind == stringify!($singleton) {
+                return saved_state::<$singleton>(state).map(|_| ());
+            })*
+            $(if kind == stringify!($actor) {
+                return saved_state::<$actor>(state).map(|_| ());
+            })*
+            Err(format!("there is no kind of actor called {}", kind))
+        }
+
+        /// Spawns an actor of the given kind from its saved state and returns its new ID
+        pub fn restore(kind: &str, state: &Value, world: &mut World) -> Result<RawID, String> {
+            $(if kind == stringify!($singleton) {
+                return Ok($singleton::local_first(world).as_raw());
+            })*
+            $(if kind == stringify!($actor) {
+                return saved_state::<$actor>(state)
+                    .map(|saved| $actor_id::restore(saved, world).as_raw());
+            })*
+            Err(format!("there is no kind of actor called {}", kind))
+        }
+    };
+}
+
+persistent_actors! {
+    singletons: Construction, TripCreator, Market, ImmigrationManager, DevelopmentManager,
+    TaskEndScheduler;
+    spawned: Lane => LaneID, SwitchLane => SwitchLaneID, Trip => TripID,
+    TripCostEstimator => TripCostEstimatorID, Family => FamilyID,
+    GroceryShop => GroceryShopID, GrainFarm => GrainFarmID, CowFarm => CowFarmID,
+    VegetableFarm => VegetableFarmID, Mill => MillID, Bakery => BakeryID,
+    NeighboringTownTrade => NeighboringTownTradeID, VacantLot => VacantLotID,
+    Building => BuildingID
+}
+
+fn saved_state<A: DeserializeOwned>(state: &Value) -> Result<A, String> {
+    serde_json::from_value(state.clone()).map_err(|err| err.to_string())
+}
+
+/// Remembers which ID every restored actor had when saving and which one it has now
+pub struct RestoredIDs {
+    new_ids: HashMap<RawID, RawID>,
+    restored: HashSet<RawID>,
+}
+
+impl RestoredIDs {
+    pub fn new() -> RestoredIDs {
+        RestoredIDs {
+            new_ids: HashMap::new(),
+            restored: HashSet::new(),
+        }
+    }
+
+    pub fn insert(&mut self, saved_id: RawID, new_id: RawID) {
+        self.new_ids.insert(saved_id, new_id);
+        self.restored.insert(new_id);
+    }
+
+    pub fn is_restored(&self, new_id: RawID) -> bool {
+        self.restored.contains(&new_id)
+    }
+
+    /// Replaces all saved IDs anywhere in `value`. Only values that read as a `RawID`
+    /// of a restored actor are replaced, IDs of actors that aren't stored in savegames,
+    /// like connected browser UIs, are left as they are.
+    pub fn replace_ids(&self, value: &mut Value) {
+        let new_id = if let Value::Object(_) = *value {
+            serde_json::from_value::<RawID>(value.clone())
+                .ok()
+                .and_then(|saved_id| self.new_ids.get(&saved_id))
+                .and_then(|new_id| serde_json::to_value(new_id).ok())
+        } else {
+            None
+        };
+
+        if let Some(new_id) = new_id {
+            *value = new_id;
+            return;
+        }
+
+        match *value {
+            Value::Object(ref mut fields) => {
+                for field in fields.values_mut() {
+                    self.replace_ids(field);
+                }
+            }
+            Value::Array(ref mut items) => {
+                for item in items.iter_mut() {
+                    self.replace_ids(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn replaced_in<T: Serialize + DeserializeOwned>(
+        &self,
+        saved: &T,
+    ) -> Result<T, serde_json::Error> {
+        let mut value = serde_json::to_value(saved)?;
+        self.replace_ids(&mut value);
+        serde_json::from_value(value)
+    }
+}
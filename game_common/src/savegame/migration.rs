@@ -0,0 +1,129 @@
+//! Upgrades savegames written by older builds step by step.
+//!
+//! When a change to any saved structure alters its serialized form, bump
+//! `SAVEGAME_VERSION` and append a migration from the previous version to
+//! `MIGRATIONS`. Dropping the first migration raises the oldest supported version.
+
+use serde_json::Value;
+use super::SavegameError;
+#[cfg(test)]
+use super::Savegame;
+
+pub const SAVEGAME_VERSION: u32 = 2;
+
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// `MIGRATIONS[i]` upgrades a savegame from `oldest_supported_version() + i` to the next version
+const MIGRATIONS: &[Migration] = &[from_unversioned, from_planning_only];
+
+pub fn oldest_supported_version() -> u32 {
+    SAVEGAME_VERSION - MIGRATIONS.len() as u32
+}
+
+fn version_of(savegame: &Value) -> Result<u32, SavegameError> {
+    match savegame.get("version") {
+        // saves from before versioning was introduced
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .map(|version| version as u32)
+            .ok_or_else(|| SavegameError::InvalidVersion(version.to_string())),
+    }
+}
+
+pub fn migrate(savegame: Value) -> Result<Value, SavegameError> {
+    let mut savegame = savegame;
+    let version = version_of(&savegame)?;
+
+    if version > SAVEGAME_VERSION {
+        return Err(SavegameError::TooNew {
+            version,
+            newest_supported: SAVEGAME_VERSION,
+        });
+    }
+
+    let oldest_supported = oldest_supported_version();
+
+    if version < oldest_supported {
+        return Err(SavegameError::TooOld {
+            version,
+            oldest_supported,
+        });
+    }
+
+    for from_version in version..SAVEGAME_VERSION {
+        let migration = MIGRATIONS[(from_version - oldest_supported) as usize];
+        savegame = migration(savegame).map_err(|reason| SavegameError::MigrationFailed {
+            from_version,
+            reason,
+        })?;
+
+        if let Value::Object(ref mut fields) = savegame {
+            fields.insert("version".to_owned(), Value::from(from_version + 1));
+        } else {
+            return Err(SavegameError::MigrationFailed {
+                from_version,
+                reason: "Savegame is not an object".to_owned(),
+            });
+        }
+    }
+
+    Ok(savegame)
+}
+
+// version 0 saves only lacked the version field itself
+fn from_unversioned(savegame: Value) -> Result<Value, String> {
+    Ok(savegame)
+}
+
+// version 1 saves didn't store any actors, loading them rebuilds the city from the master plan
+fn from_planning_only(savegame: Value) -> Result<Value, String> {
+    Ok(savegame)
+}
+
+#[cfg(test)]
+fn savegame_with_version(version: Option<u32>) -> Value {
+    let mut fields = ::serde_json::Map::new();
+    if let Some(version) = version {
+        fields.insert("version".to_owned(), Value::from(version));
+    }
+    Value::Object(fields)
+}
+
+#[test]
+fn test_migration_version_checks() {
+    let migrated =
+        migrate(savegame_with_version(None)).expect("Unversioned saves should be migratable");
+    assert!(version_of(&migrated).unwrap() == SAVEGAME_VERSION);
+
+    assert!(migrate(savegame_with_version(Some(SAVEGAME_VERSION))).is_ok());
+
+    match migrate(savegame_with_version(Some(SAVEGAME_VERSION + 1))) {
+        Err(SavegameError::TooNew { version, .. }) => assert!(version == SAVEGAME_VERSION + 1),
+        _ => panic!("Saves from newer builds should be rejected"),
+    }
+}
+
+#[test]
+fn test_loading_old_savegames() {
+    let old_savegames = [
+        (0, include_str!("fixtures/unversioned.json")),
+        (1, include_str!("fixtures/planning_only.json")),
+    ];
+
+    for &(version, json) in &old_savegames {
+        let raw_savegame = ::serde_json::from_str(json).expect("Fixture should be valid JSON");
+        let savegame = match Savegame::from_json(raw_savegame) {
+            Ok(savegame) => savegame,
+            Err(err) => panic!("Savegame version {} should load: {}", version, err),
+        };
+
+        assert!(savegame.version == SAVEGAME_VERSION);
+        assert!(savegame.actors.is_empty() && savegame.simulation_id.is_none());
+        assert!(savegame.simulation.sleepers.is_empty());
+        assert!(savegame.planning.master_plan.live_gestures().count() == 2);
+        assert!(savegame.planning.proposals.len() == 1);
+        assert!(savegame.planning.implemented_proposals.len() == 1);
+        assert!(!savegame.planning.governance.enabled);
+    }
+}
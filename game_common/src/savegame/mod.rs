@@ -0,0 +1,423 @@
+//! Savegames contain the complete state of the city: the simulation clock with all scheduled
+//! wake-ups, the planning state and the state of every persistent actor.
+//! Actor IDs are not stable between runs, so restored actors get new IDs and all IDs
+//! in the saved states are replaced with the new IDs of the same actors.
+//! Messages that are still on their way between actors while saving are not stored.
+//! The world seed and the number of IDs generated so far are stored as well, so a loaded
+//! world continues to be reproducible and doesn't hand out IDs that are already in use.
+
+use kay::{World, ActorSystem, RawID, TypedID};
+use compact::{CString, COption, CVec};
+use simulation::{SimulationID, SimulationState, Sleeper, SleeperID, Instant, Duration};
+use planning::{PlanManagerID, PlanningState};
+use util::random::{RandomnessState, randomness_state, restore_randomness_state};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use std::fs::File;
+
+pub mod migration;
+use self::migration::SAVEGAME_VERSION;
+mod actors;
+use self::actors::RestoredIDs;
+
+#[derive(Serialize, Deserialize)]
+pub struct Savegame {
+    pub version: u32,
+    pub simulation: SimulationState,
+    pub planning: PlanningState,
+    #[serde(default)]
+    pub randomness: Option<RandomnessState>,
+    /// The IDs that the simulation and the plan manager had when saving
+    #[serde(default)]
+    pub simulation_id: Option<SimulationID>,
+    #[serde(default)]
+    pub plan_manager_id: Option<PlanManagerID>,
+    #[serde(default)]
+    pub actors: Vec<ActorState>,
+}
+
+/// The saved state of one persistent actor, as it appears in the savegame file
+#[derive(Serialize, Deserialize)]
+pub struct ActorState {
+    pub kind: String,
+    pub id: RawID,
+    pub state: Value,
+}
+
+/// Actors whose state is stored in savegames. Each actor type that implements this
+/// also needs a constructor `restore(id, saved: &Self)` and an entry in `actors.rs`.
+/// kay only generates messages for handlers that are written out in the source,
+/// so each actor type spells them out, but leaves the work to `save_actor` and `restore_actor`
+pub trait Persistent {
+    /// Replies to `saver` with `SavegameManagerID::on_actor_state`,
+    /// `kind` is the name of the actor type in savegames
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World);
+    /// Replaces the state of the actor with its saved state,
+    /// in which all IDs already refer to restored actors
+    fn restore_state(&mut self, state: &CString, world: &mut World);
+}
+
+/// The state of one persistent actor as JSON, so saving doesn't need to know its type
+#[derive(Compact, Clone)]
+pub struct SavedActor {
+    pub kind: CString,
+    pub id: RawID,
+    pub state: CString,
+}
+
+pub fn save_actor<A: Serialize>(
+    actor: &A,
+    id: RawID,
+    kind: &CString,
+    saver: SavegameManagerID,
+    world: &mut World,
+) {
+    match serde_json::to_string(actor) {
+        Ok(state) => saver.on_actor_state(
+            SavedActor {
+                kind: kind.clone(),
+                id,
+                state: compact_string(&state),
+            },
+            world,
+        ),
+        Err(err) => println!("Can't save {} {:?}: {}", &**kind, id, err),
+    }
+}
+
+/// Saved states were already checked when loading the savegame,
+/// an actor whose state still doesn't fit keeps its current state
+pub fn restore_actor<A: DeserializeOwned>(actor: &mut A, state: &CString) {
+    match serde_json::from_str(state) {
+        Ok(restored) => *actor = restored,
+        Err(err) => println!("Can't restore actor from its saved state: {}", err),
+    }
+}
+
+fn compact_string(string: &str) -> CString {
+    let mut compact = CString::new();
+    compact.push_str(string);
+    compact
+}
+
+#[derive(Debug)]
+pub enum SavegameError {
+    Io(::std::io::Error),
+    Format(serde_json::Error),
+    InvalidVersion(String),
+    TooOld { version: u32, oldest_supported: u32 },
+    TooNew { version: u32, newest_supported: u32 },
+    MigrationFailed { from_version: u32, reason: String },
+    InvalidActorState { kind: String, reason: String },
+}
+
+impl ::std::fmt::Display for SavegameError {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            SavegameError::Io(ref err) => write!(f, "Couldn't access savegame file: {}", err),
+            SavegameError::Format(ref err) => write!(f, "Savegame is malformed: {}", err),
+            SavegameError::InvalidVersion(ref version) => {
+                write!(f, "Savegame has an invalid version: {}", version)
+            }
+            SavegameError::TooOld {
+                version,
+                oldest_supported,
+            } => write!(
+                f,
+                "Savegame version {} is too old, this build can only load versions {} to {}",
+                version, oldest_supported, SAVEGAME_VERSION
+            ),
+            SavegameError::TooNew {
+                version,
+                newest_supported,
+            } => write!(
+                f,
+                "Savegame version {} is from a newer build, this build supports up to version {}",
+                version, newest_supported
+            ),
+            SavegameError::MigrationFailed {
+                from_version,
+                ref reason,
+            } => write!(
+                f,
+                "Couldn't upgrade savegame from version {}: {}",
+                from_version, reason
+            ),
+            SavegameError::InvalidActorState {
+                ref kind,
+                ref reason,
+            } => write!(f, "Savegame contains an invalid {}: {}", kind, reason),
+        }
+    }
+}
+
+impl Savegame {
+    pub fn read_from(path: &str) -> Result<Savegame, SavegameError> {
+        let file = File::open(path).map_err(SavegameError::Io)?;
+        let raw_savegame = serde_json::from_reader(file).map_err(SavegameError::Format)?;
+        Savegame::from_json(raw_savegame)
+    }
+
+    /// Migrates a savegame of any supported version before reading it.
+    /// The states of all actors are checked right away, so restoring can't fail halfway
+    pub fn from_json(raw_savegame: Value) -> Result<Savegame, SavegameError> {
+        let migrated = migration::migrate(raw_savegame)?;
+        let savegame: Savegame = serde_json::from_value(migrated).map_err(SavegameError::Format)?;
+
+        for actor in &savegame.actors {
+            actors::check(&actor.kind, &actor.state).map_err(|reason| {
+                SavegameError::InvalidActorState {
+                    kind: actor.kind.clone(),
+                    reason,
+                }
+            })?;
+        }
+
+        Ok(savegame)
+    }
+
+    pub fn write_to(&self, path: &str) -> Result<(), SavegameError> {
+        // write to a temporary file first so a crash never leaves a half-written save behind
+        let temp_path = format!("{}.tmp", path);
+        {
+            let file = File::create(&temp_path).map_err(SavegameError::Io)?;
+            serde_json::to_writer(file, self).map_err(SavegameError::Format)?;
+        }
+        ::std::fs::rename(&temp_path, path).map_err(SavegameError::Io)
+    }
+
+    /// Spawns all saved actors first, to know which IDs they have now, and then restores
+    /// their states with the saved IDs replaced. Call this once everything that was spawned
+    /// at startup handled its messages, so the restored wake-ups replace theirs.
+    pub fn restore(
+        &self,
+        simulation: SimulationID,
+        plan_manager: PlanManagerID,
+        world: &mut World,
+    ) {
+        if let Some(randomness) = self.randomness {
+            restore_randomness_state(randomness);
+        }
+
+        let mut restored_ids = RestoredIDs::new();
+
+        if let Some(saved_simulation) = self.simulation_id {
+            restored_ids.insert(saved_simulation.as_raw(), simulation.as_raw());
+        }
+
+        if let Some(saved_plan_manager) = self.plan_manager_id {
+            restored_ids.insert(saved_plan_manager.as_raw(), plan_manager.as_raw());
+        }
+
+        let mut restored_actors = Vec::new();
+
+        for actor in &self.actors {
+            match actors::restore(&actor.kind, &actor.state, world) {
+                Ok(new_id) => {
+                    restored_ids.insert(actor.id, new_id);
+                    restored_actors.push((new_id, actor));
+                }
+                Err(reason) => println!("Can't restore {}: {}", actor.kind, reason),
+            }
+        }
+
+        for (new_id, actor) in restored_actors {
+            let mut state = actor.state.clone();
+            restored_ids.replace_ids(&mut state);
+            PersistentID::from_raw(new_id).restore_state(compact_string(&state.to_string()), world);
+        }
+
+        // savegames from before actors were stored are rebuilt from the master plan
+        let has_actors = !self.actors.is_empty();
+        let mut simulation_state = match restored_ids.replaced_in(&self.simulation) {
+            Ok(simulation_state) => simulation_state,
+            Err(err) => {
+                println!("Can't restore the IDs of scheduled wake-ups: {}", err);
+                self.simulation.clone()
+            }
+        };
+        simulation_state
+            .sleepers
+            .retain(|sleeper| restored_ids.is_restored(sleeper.as_raw()));
+
+        simulation.restore(simulation_state, has_actors, world);
+        plan_manager.restore(self.planning.clone(), !has_actors, world);
+    }
+}
+
+#[derive(Compact, Clone)]
+pub struct SavegameManager {
+    id: SavegameManagerID,
+    simulation: SimulationID,
+    plan_manager: PlanManagerID,
+    path: CString,
+    autosave_every: COption<Duration>,
+    ongoing_save: COption<CString>,
+    /// The simulation and the plan manager reply exactly once to each save
+    missing_replies: u8,
+    collected_simulation: COption<SimulationState>,
+    collected_planning: COption<PlanningState>,
+    collected_actors: CVec<SavedActor>,
+}
+
+impl SavegameManager {
+    pub fn spawn(
+        id: SavegameManagerID,
+        simulation: SimulationID,
+        plan_manager: PlanManagerID,
+        path: &CString,
+        autosave_every: &COption<Duration>,
+        world: &mut World,
+    ) -> SavegameManager {
+        if let Some(interval) = **autosave_every {
+            simulation.wake_up_in(interval.into(), id.into(), world);
+        }
+
+        SavegameManager {
+            id,
+            simulation,
+            plan_manager,
+            path: path.clone(),
+            autosave_every: autosave_every.clone(),
+            ongoing_save: COption(None),
+            missing_replies: 0,
+            collected_simulation: COption(None),
+            collected_planning: COption(None),
+            collected_actors: CVec::new(),
+        }
+    }
+
+    pub fn save(&mut self, world: &mut World) {
+        let path = self.path.clone();
+        self.save_as(&path, world);
+    }
+
+    pub fn save_as(&mut self, path: &CString, world: &mut World) {
+        if self.ongoing_save.is_some() {
+            println!("Save already in progress, ignoring save to {}", &**path);
+            return;
+        }
+
+        self.ongoing_save = COption(Some(path.clone()));
+        self.missing_replies = 2;
+        self.simulation.save_state(self.id, world);
+        self.plan_manager.save_state(self.id, world);
+
+        for (kind, persistent_actors) in actors::all_persistent(world) {
+            persistent_actors.save_state(self.id, compact_string(kind), world);
+        }
+    }
+
+    pub fn on_simulation_state(&mut self, state: &SimulationState, _: &mut World) {
+        if self.ongoing_save.is_some() && self.collected_simulation.is_none() {
+            self.collected_simulation = COption(Some(state.clone()));
+            self.missing_replies -= 1;
+        }
+    }
+
+    pub fn on_planning_state(&mut self, state: &PlanningState, _: &mut World) {
+        if self.ongoing_save.is_some() && self.collected_planning.is_none() {
+            self.collected_planning = COption(Some(state.clone()));
+            self.missing_replies -= 1;
+        }
+    }
+
+    pub fn on_actor_state(&mut self, actor: &SavedActor, _: &mut World) {
+        if self.ongoing_save.is_some() {
+            self.collected_actors.push(actor.clone());
+        }
+    }
+
+    /// Broadcasts don't tell how many actors they reached, so this has to be called every
+    /// time after all messages were processed, with whether any messages were still queued then.
+    /// A save is complete once the simulation and the plan manager replied and nothing was
+    /// queued anymore after that, since all persistent actors were asked before.
+    pub fn all_messages_processed(&mut self, nothing_queued: bool, _: &mut World) {
+        if self.ongoing_save.is_none() || self.missing_replies > 0 || !nothing_queued {
+            return;
+        }
+
+        let path = self
+            .ongoing_save
+            .take()
+            .expect("Should only finish while saving");
+        let collected_actors = ::std::mem::replace(&mut self.collected_actors, CVec::new());
+
+        let (simulation, planning) = match (
+            self.collected_simulation.take(),
+            self.collected_planning.take(),
+        ) {
+            (Some(simulation), Some(planning)) => (simulation, planning),
+            _ => {
+                println!(
+                    "Saving game to {} failed: simulation or planning didn't reply",
+                    &*path
+                );
+                return;
+            }
+        };
+
+        let savegame = Savegame {
+            version: SAVEGAME_VERSION,
+            simulation,
+            planning,
+            randomness: Some(randomness_state()),
+            simulation_id: Some(self.simulation),
+            plan_manager_id: Some(self.plan_manager),
+            actors: collected_actors
+                .iter()
+                .filter_map(|actor| match serde_json::from_str(&actor.state) {
+                    Ok(state) => Some(ActorState {
+                        kind: actor.kind.to_string(),
+                        id: actor.id,
+                        state,
+                    }),
+                    Err(err) => {
+                        println!("Can't save {} {:?}: {}", &*actor.kind, actor.id, err);
+                        None
+                    }
+                })
+                .collect(),
+        };
+
+        match savegame.write_to(&path) {
+            Ok(()) => println!("Saved game to {}", &*path),
+            Err(err) => println!("Saving game to {} failed: {}", &*path, err),
+        }
+    }
+}
+
+impl Sleeper for SavegameManager {
+    fn wake(&mut self, _: Instant, world: &mut World) {
+        if let Some(interval) = *self.autosave_every {
+            self.save(world);
+            self.simulation
+                .wake_up_in(interval.into(), self.id.into(), world);
+        }
+    }
+}
+
+pub fn setup(system: &mut ActorSystem) {
+    system.register::<SavegameManager>();
+    auto_setup(system);
+}
+
+pub fn spawn(
+    world: &mut World,
+    simulation: SimulationID,
+    plan_manager: PlanManagerID,
+    path: &str,
+    autosave_every: Option<Duration>,
+) -> SavegameManagerID {
+    SavegameManagerID::spawn(
+        simulation,
+        plan_manager,
+        compact_string(path),
+        COption(autosave_every),
+        world,
+    )
+}
+
+mod kay_auto;
+pub use self::kay_auto::*;
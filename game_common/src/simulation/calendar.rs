@@ -0,0 +1,318 @@
+use super::time::{Instant, TimeOfDay, TimeOfDayRange, Duration, BEGINNING_TIME_OF_DAY,
+MINUTES_PER_DAY};
+
+// The simulation starts on Monday, 1st of March in year 1 (the beginning of spring)
+const BEGINNING_DAY_OF_YEAR: usize = 31 + 28;
+const DAYS_PER_WEEK: usize = 7;
+const DAYS_PER_YEAR: usize = 365;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+pub const WEEKDAYS: [Weekday; DAYS_PER_WEEK] = [
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+    Weekday::Sunday,
+];
+
+impl Weekday {
+    pub fn is_weekend(self) -> bool {
+        self == Weekday::Saturday || self == Weekday::Sunday
+    }
+
+    fn idx(self) -> usize {
+        WEEKDAYS
+            .iter()
+            .position(|weekday| *weekday == self)
+            .expect("All weekdays should be in WEEKDAYS")
+    }
+
+    fn previous(self) -> Weekday {
+        WEEKDAYS[(self.idx() + DAYS_PER_WEEK - 1) % DAYS_PER_WEEK]
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Month {
+    January,
+    February,
+    March,
+    April,
+    May,
+    June,
+    July,
+    August,
+    September,
+    October,
+    November,
+    December,
+}
+
+const MONTHS: [(Month, usize); 12] = [
+    (Month::January, 31),
+    (Month::February, 28),
+    (Month::March, 31),
+    (Month::April, 30),
+    (Month::May, 31),
+    (Month::June, 30),
+    (Month::July, 31),
+    (Month::August, 31),
+    (Month::September, 30),
+    (Month::October, 31),
+    (Month::November, 30),
+    (Month::December, 31),
+];
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Month {
+    pub fn season(self) -> Season {
+        match self {
+            Month::March | Month::April | Month::May => Season::Spring,
+            Month::June | Month::July | Month::August => Season::Summer,
+            Month::September | Month::October | Month::November => Season::Autumn,
+            Month::December | Month::January | Month::February => Season::Winter,
+        }
+    }
+}
+
+/// A calendar day, counted from the first day of the simulation
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct Date {
+    day_number: u32,
+}
+
+impl Date {
+    pub fn day_number(&self) -> usize {
+        self.day_number as usize
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        WEEKDAYS[self.day_number() % DAYS_PER_WEEK]
+    }
+
+    fn days_since_start_of_first_year(&self) -> usize {
+        BEGINNING_DAY_OF_YEAR + self.day_number()
+    }
+
+    /// Starting from year 1
+    pub fn year(&self) -> usize {
+        self.days_since_start_of_first_year() / DAYS_PER_YEAR + 1
+    }
+
+    /// Starting from 0 for the 1st of January
+    pub fn day_of_year(&self) -> usize {
+        self.days_since_start_of_first_year() % DAYS_PER_YEAR
+    }
+
+    /// Returns the month and the day of the month, starting from 1
+    pub fn month_and_day(&self) -> (Month, usize) {
+        let mut days_left = self.day_of_year();
+
+        for &(month, days_in_month) in &MONTHS {
+            if days_left < days_in_month {
+                return (month, days_left + 1);
+            }
+            days_left -= days_in_month;
+        }
+
+        unreachable!("Day of year should always be within a month")
+    }
+
+    pub fn season(&self) -> Season {
+        self.month_and_day().0.season()
+    }
+}
+
+impl From<Instant> for Date {
+    fn from(instant: Instant) -> Date {
+        Date {
+            day_number: ((BEGINNING_TIME_OF_DAY as u64 * 60 + instant.whole_minutes())
+                / MINUTES_PER_DAY as u64) as u32,
+        }
+    }
+}
+
+const MINUTES_PER_WEEK: usize = DAYS_PER_WEEK * MINUTES_PER_DAY;
+
+/// Like `TimeOfDayRange`, but can differ from weekday to weekday
+/// (`None` meaning not at all on that day). A range that ends before it starts
+/// runs past midnight, into the next weekday
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct WeeklyTimeOfDayRange {
+    per_weekday: [Option<TimeOfDayRange>; DAYS_PER_WEEK],
+    /// Shifting all ranges earlier can move them (or their part after midnight)
+    /// onto other weekdays, so instead of the ranges, instants are shifted later when checking
+    #[serde(default)]
+    minutes_earlier: u16,
+}
+
+impl WeeklyTimeOfDayRange {
+    pub fn every_day(range: TimeOfDayRange) -> Self {
+        WeeklyTimeOfDayRange {
+            per_weekday: [Some(range); DAYS_PER_WEEK],
+            minutes_earlier: 0,
+        }
+    }
+
+    pub fn weekdays_and_weekends(
+        on_weekdays: TimeOfDayRange,
+        on_weekends: Option<TimeOfDayRange>,
+    ) -> Self {
+        let mut per_weekday = [Some(on_weekdays); DAYS_PER_WEEK];
+
+        for weekday in WEEKDAYS.iter().filter(|weekday| weekday.is_weekend()) {
+            per_weekday[weekday.idx()] = on_weekends;
+        }
+
+        WeeklyTimeOfDayRange {
+            per_weekday,
+            minutes_earlier: 0,
+        }
+    }
+
+    /// The range starting on `weekday` before shifting it, shifted by as much as all ranges
+    /// were shifted. So it might actually start on the weekday before
+    pub fn on(&self, weekday: Weekday) -> Option<TimeOfDayRange> {
+        self.per_weekday[weekday.idx()]
+            .map(|range| range.earlier_by(Duration::from_minutes(self.minutes_earlier as usize)))
+    }
+
+    /// Where `instant` would be if the ranges weren't shifted,
+    /// with today's and yesterday's (unshifted) range
+    fn unshifted(
+        &self,
+        instant: Instant,
+    ) -> (TimeOfDay, Option<TimeOfDayRange>, Option<TimeOfDayRange>) {
+        let instant = instant + Duration::from_minutes(self.minutes_earlier as usize);
+        let weekday = Date::from(instant).weekday();
+        (
+            TimeOfDay::from(instant),
+            self.per_weekday[weekday.idx()],
+            self.per_weekday[weekday.previous().idx()],
+        )
+    }
+
+    pub fn contains(&self, instant: Instant) -> bool {
+        let (time, today, yesterday) = self.unshifted(instant);
+
+        let in_today = today.map_or(false, |range| {
+            range.start <= time && (range.end < range.start || time <= range.end)
+        });
+        let in_yesterday_after_midnight =
+            yesterday.map_or(false, |range| range.end < range.start && time <= range.end);
+
+        in_today || in_yesterday_after_midnight
+    }
+
+    /// Whether it's still open now or will open again before the day is over,
+    /// counting ranges that started yesterday and last past midnight.
+    /// For shifted ranges, days are those of the ranges before shifting
+    pub fn end_after_on_same_day(&self, instant: Instant) -> bool {
+        let (time, today, yesterday) = self.unshifted(instant);
+
+        let today_ends_after =
+            today.map_or(false, |range| range.end < range.start || time < range.end);
+        let yesterday_ends_after =
+            yesterday.map_or(false, |range| range.end < range.start && time < range.end);
+
+        today_ends_after || yesterday_ends_after
+    }
+
+    pub fn earlier_by(&self, delta: Duration) -> Self {
+        let delta_minutes = (delta.0 / 60) as usize % MINUTES_PER_WEEK;
+
+        WeeklyTimeOfDayRange {
+            per_weekday: self.per_weekday,
+            minutes_earlier: ((self.minutes_earlier as usize + delta_minutes) % MINUTES_PER_WEEK)
+                as u16,
+        }
+    }
+}
+
+impl From<TimeOfDayRange> for WeeklyTimeOfDayRange {
+    fn from(range: TimeOfDayRange) -> WeeklyTimeOfDayRange {
+        WeeklyTimeOfDayRange::every_day(range)
+    }
+}
+
+#[test]
+fn test_calendar_rollover() {
+    use super::time::{Ticks, TICKS_PER_SIM_SECOND};
+
+    let ticks_per_day = Ticks::from(Duration::from_hours(24)).0;
+    let start = Date::from(Instant::new(0));
+    assert!(start.weekday() == Weekday::Monday);
+    assert!(start.month_and_day() == (Month::March, 1));
+    assert!(start.season() == Season::Spring);
+
+    // the first day ends at midnight, 17 hours after the 7:00 start
+    let just_before_midnight = Instant::new(17 * 60 * 60 * u64::from(TICKS_PER_SIM_SECOND) - 1);
+    assert!(Date::from(just_before_midnight).day_number() == 0);
+    assert!(Date::from(just_before_midnight + Ticks(1)).day_number() == 1);
+
+    let one_year_later = Date::from(Instant::new(365 * ticks_per_day));
+    assert!(one_year_later.year() == 2);
+    assert!(one_year_later.month_and_day() == (Month::March, 1));
+    assert!(one_year_later.weekday() == Weekday::Tuesday);
+}
+
+#[test]
+fn test_weekly_ranges_past_midnight() {
+    use super::time::TICKS_PER_SIM_MINUTE;
+
+    // days counted from the Monday of the second week, so early hours are after the start
+    let at = |days_after_monday: usize, h: usize, m: usize| {
+        let minutes =
+            (7 + days_after_monday) * MINUTES_PER_DAY + h * 60 + m - BEGINNING_TIME_OF_DAY * 60;
+        Instant::new(minutes as u64 * u64::from(TICKS_PER_SIM_MINUTE))
+    };
+
+    let mut per_weekday = [None; DAYS_PER_WEEK];
+    per_weekday[Weekday::Monday.idx()] = Some(TimeOfDayRange::new(22, 0, 2, 0));
+    let monday_night = WeeklyTimeOfDayRange {
+        per_weekday,
+        minutes_earlier: 0,
+    };
+
+    assert!(monday_night.contains(at(0, 23, 0)));
+    assert!(monday_night.contains(at(1, 1, 0)));
+    assert!(!monday_night.contains(at(0, 1, 0)));
+    assert!(!monday_night.contains(at(1, 23, 0)));
+    assert!(monday_night.end_after_on_same_day(at(0, 12, 0)));
+    assert!(monday_night.end_after_on_same_day(at(1, 1, 0)));
+    assert!(!monday_night.end_after_on_same_day(at(1, 3, 0)));
+
+    let mut per_weekday = [None; DAYS_PER_WEEK];
+    per_weekday[Weekday::Tuesday.idx()] = Some(TimeOfDayRange::new(1, 0, 5, 0));
+    // now from Monday 23:00 to Tuesday 3:00
+    let shifted_across_midnight = WeeklyTimeOfDayRange {
+        per_weekday,
+        minutes_earlier: 0,
+    }.earlier_by(Duration::from_hours(2));
+
+    assert!(shifted_across_midnight.contains(at(0, 23, 30)));
+    assert!(shifted_across_midnight.contains(at(1, 2, 0)));
+    assert!(!shifted_across_midnight.contains(at(1, 4, 0)));
+    assert!(!shifted_across_midnight.contains(at(1, 23, 30)));
+    assert!(shifted_across_midnight.end_after_on_same_day(at(0, 23, 0)));
+    assert!(!shifted_across_midnight.end_after_on_same_day(at(1, 3, 30)));
+}
@@ -1,10 +1,17 @@
 use kay::{ActorSystem, World};
-use compact::CVec;
+use compact::{CVec, COption};
+use savegame::SavegameManagerID;
+use journal::{InputJournal, JournaledInput};
+use util::profiling;
 
 mod time;
+mod calendar;
+mod wake_ups;
 
 pub use self::time::{Instant, Ticks, Duration, TICKS_PER_SIM_MINUTE, TICKS_PER_SIM_SECOND,
 TimeOfDay, TimeOfDayRange};
+pub use self::calendar::{Date, Weekday, WEEKDAYS, Month, Season, WeeklyTimeOfDayRange};
+pub use self::wake_ups::{WakeUpHandle, WakeUpQueue};
 
 pub trait Simulatable {
     fn tick(&mut self, dt: f32, current_instant: Instant, world: &mut World);
@@ -14,13 +21,29 @@ pub trait Sleeper {
     fn wake(&mut self, current_instant: Instant, world: &mut World);
 }
 
+#[derive(Compact, Clone, Serialize, Deserialize)]
+pub struct SimulationState {
+    pub current_instant: Instant,
+    pub speed: u16,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub sleepers: WakeUpQueue<WakeUpHandle, SleeperID>,
+}
+
 #[derive(Compact, Clone)]
 pub struct Simulation {
     id: SimulationID,
     simulatables: CVec<SimulatableID>,
     current_instant: Instant,
-    sleepers: CVec<(Instant, SleeperID)>,
+    sleepers: WakeUpQueue<WakeUpHandle, SleeperID>,
     speed: u16,
+    paused: bool,
+    pending_step_ticks: u32,
+    run_until: COption<Instant>,
+    /// While replaying a journal, ticks stop before this instant
+    /// until the journal applied its inputs for it
+    replay_hold: COption<Instant>,
 }
 
 impl Simulation {
@@ -33,54 +56,173 @@ impl Simulation {
             id,
             simulatables: simulatables.clone(),
             current_instant: Instant::new(0),
-            sleepers: CVec::new(),
+            sleepers: WakeUpQueue::new(),
             speed: 1,
+            paused: false,
+            pending_step_ticks: 0,
+            run_until: COption(None),
+            replay_hold: COption(None),
         }
     }
 
     pub fn progress(&mut self, world: &mut World) {
-        for _ in 0..self.speed {
-            for simulatable in &self.simulatables {
-                simulatable.tick(
-                    1.0 / (TICKS_PER_SIM_SECOND as f32),
-                    self.current_instant,
-                    world,
-                );
+        let _measurement = profiling::start("Simulation progress");
+        let n_ticks = if self.paused {
+            // single steps still happen at most at the current speed per frame
+            self.pending_step_ticks.min(u32::from(self.speed.max(1)))
+        } else {
+            u32::from(self.speed)
+        };
+
+        for _ in 0..n_ticks {
+            if let Some(hold) = *self.replay_hold {
+                if self.current_instant >= hold {
+                    // the journal sets the next hold after applying its inputs
+                    self.replay_hold = COption(None);
+                    InputJournal::global_first(world).apply_due(self.current_instant, world);
+                    break;
+                }
+            }
+
+            if let Some(target) = *self.run_until {
+                if self.current_instant >= target {
+                    self.run_until = COption(None);
+                    self.paused = true;
+                    break;
+                }
             }
-            while self
-                .sleepers
-                .last()
-                .map(|&(end, _)| end < self.current_instant)
-                .unwrap_or(false)
-            {
-                let (_, sleeper) = self
-                    .sleepers
-                    .pop()
-                    .expect("just checked that there are sleepers");
-                sleeper.wake(self.current_instant, world);
+
+            self.advance_one_tick(world);
+
+            if self.paused {
+                self.pending_step_ticks -= 1;
             }
-            self.current_instant += Ticks(1);
         }
     }
 
-    pub fn wake_up_in(&mut self, remaining_ticks: Ticks, sleeper_id: SleeperID, _: &mut World) {
+    fn advance_one_tick(&mut self, world: &mut World) {
+        for simulatable in &self.simulatables {
+            simulatable.tick(
+                1.0 / (TICKS_PER_SIM_SECOND as f32),
+                self.current_instant,
+                world,
+            );
+        }
+        while let Some((_, sleeper)) = self.sleepers.pop_due_before(self.current_instant) {
+            sleeper.wake(self.current_instant, world);
+        }
+        self.current_instant += Ticks(1);
+    }
+
+    /// Use `SimulationID::wake_up_in` to get a handle for the wake-up
+    pub fn schedule_wake_up(
+        &mut self,
+        handle: WakeUpHandle,
+        remaining_ticks: Ticks,
+        sleeper_id: SleeperID,
+        _: &mut World,
+    ) {
         let wake_up_at = self.current_instant + remaining_ticks;
-        let maybe_idx = self
-            .sleepers
-            .binary_search_by_key(&wake_up_at.iticks(), |&(t, _)| -(t.iticks()));
-        let insert_idx = match maybe_idx {
-            Ok(idx) | Err(idx) => idx,
-        };
-        self.sleepers.insert(insert_idx, (wake_up_at, sleeper_id));
+        self.sleepers.schedule(handle, wake_up_at, sleeper_id);
+    }
+
+    /// Does nothing if the wake-up already happened
+    pub fn cancel_wake_up(&mut self, handle: WakeUpHandle, _: &mut World) {
+        self.sleepers.cancel(handle);
+    }
+
+    /// Does nothing if the wake-up already happened or was cancelled
+    pub fn move_wake_up(&mut self, handle: WakeUpHandle, remaining_ticks: Ticks, _: &mut World) {
+        let wake_up_at = self.current_instant + remaining_ticks;
+        self.sleepers.reschedule(handle, wake_up_at);
+    }
+
+    pub fn hold_for_replay_at(&mut self, instant: Instant, _: &mut World) {
+        self.replay_hold = COption(Some(instant));
     }
 
     pub fn get_info(&mut self, requester: ::browser_ui::BrowserUIID, world: &mut World) {
-        requester.on_simulation_info(self.current_instant, self.speed, world);
+        requester.on_simulation_info(
+            self.current_instant,
+            self.speed,
+            self.paused,
+            self.run_until.clone(),
+            world,
+        );
     }
 
-    pub fn set_speed(&mut self, speed: u16, _world: &mut World) {
+    pub fn set_speed(&mut self, speed: u16, world: &mut World) {
+        InputJournal::global_first(world).record(JournaledInput::SetSpeed { speed }, world);
         self.speed = speed as u16;
     }
+
+    pub fn pause(&mut self, _: &mut World) {
+        self.paused = true;
+        self.pending_step_ticks = 0;
+        self.run_until = COption(None);
+    }
+
+    pub fn resume(&mut self, _: &mut World) {
+        self.paused = false;
+        self.pending_step_ticks = 0;
+    }
+
+    /// Pauses and then advances exactly `n_ticks`, spread over frames at the current speed
+    pub fn step(&mut self, n_ticks: u32, _: &mut World) {
+        self.paused = true;
+        self.pending_step_ticks = self.pending_step_ticks.saturating_add(n_ticks);
+        self.run_until = COption(None);
+    }
+
+    /// Runs at the current speed and pauses once `instant` is reached
+    pub fn run_until(&mut self, instant: Instant, _: &mut World) {
+        self.paused = false;
+        self.pending_step_ticks = 0;
+        self.run_until = COption(Some(instant));
+    }
+
+    pub fn run_until_time_of_day(&mut self, time: TimeOfDay, world: &mut World) {
+        let instant = time.next_after(self.current_instant);
+        self.run_until(instant, world);
+    }
+
+    pub fn save_state(&mut self, saver: SavegameManagerID, world: &mut World) {
+        saver.on_simulation_state(
+            SimulationState {
+                current_instant: self.current_instant,
+                speed: self.speed,
+                paused: self.paused,
+                sleepers: self.sleepers.clone(),
+            },
+            world,
+        );
+    }
+
+    /// Savegames from before actors were stored don't contain the wake-ups of their actors,
+    /// so the wake-ups that were scheduled since startup are kept for them
+    pub fn restore(&mut self, state: &SimulationState, restore_sleepers: bool, _: &mut World) {
+        self.current_instant = state.current_instant;
+        self.speed = state.speed;
+        self.paused = state.paused;
+        if restore_sleepers {
+            self.sleepers = state.sleepers.clone();
+        }
+        self.pending_step_ticks = 0;
+        self.run_until = COption(None);
+    }
+}
+
+impl SimulationID {
+    pub fn wake_up_in(
+        self,
+        remaining_ticks: Ticks,
+        sleeper_id: SleeperID,
+        world: &mut World,
+    ) -> WakeUpHandle {
+        let handle = WakeUpHandle::new();
+        self.schedule_wake_up(handle, remaining_ticks, sleeper_id, world);
+        handle
+    }
 }
 
 pub fn setup(system: &mut ActorSystem) {
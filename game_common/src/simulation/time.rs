@@ -2,11 +2,11 @@ pub const TICKS_PER_SIM_SECOND: u32 = 3;
 pub const TICKS_PER_SIM_MINUTE: u32 = 60 * TICKS_PER_SIM_SECOND;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Ticks(pub u32);
+pub struct Ticks(pub u64);
 
 impl From<Duration> for Ticks {
     fn from(d_secs: Duration) -> Ticks {
-        Ticks(d_secs.0 * TICKS_PER_SIM_SECOND)
+        Ticks(u64::from(d_secs.0) * u64::from(TICKS_PER_SIM_SECOND))
     }
 }
 
@@ -41,50 +41,99 @@ impl Duration {
     pub fn as_days(&self) -> f32 {
         self.as_hours() / 24.0
     }
+
+    pub fn checked_add(self, rhs: Duration) -> Option<Duration> {
+        self.0.checked_add(rhs.0).map(Duration)
+    }
+
+    pub fn checked_sub(self, rhs: Duration) -> Option<Duration> {
+        self.0.checked_sub(rhs.0).map(Duration)
+    }
 }
 
+// Adding and subtracting saturates, use `checked_add`/`checked_sub` to detect overflows
+
 impl ::std::ops::Add for Duration {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self {
-        Duration(self.0 + rhs.0)
+        Duration(self.0.saturating_add(rhs.0))
     }
 }
 
 impl ::std::ops::AddAssign for Duration {
     fn add_assign(&mut self, rhs: Self) {
-        self.0 += rhs.0
+        *self = *self + rhs
+    }
+}
+
+impl ::std::ops::Sub for Duration {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Duration(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl ::std::ops::SubAssign for Duration {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs
     }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
-pub struct Instant(u32);
+pub struct Instant(u64);
 
 impl Instant {
-    pub fn new(ticks: usize) -> Self {
-        Instant(ticks as u32)
+    pub fn new(ticks: u64) -> Self {
+        Instant(ticks)
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.0
     }
 
-    pub fn ticks(&self) -> usize {
-        self.0 as usize
+    pub fn iticks(&self) -> i64 {
+        self.0 as i64
     }
 
-    pub fn iticks(&self) -> isize {
-        self.0 as isize
+    pub fn whole_minutes(&self) -> u64 {
+        self.0 / u64::from(TICKS_PER_SIM_MINUTE)
+    }
+
+    pub fn checked_add<D: Into<Ticks>>(self, rhs: D) -> Option<Instant> {
+        self.0.checked_add(rhs.into().0).map(Instant)
+    }
+
+    pub fn checked_sub<D: Into<Ticks>>(self, rhs: D) -> Option<Instant> {
+        self.0.checked_sub(rhs.into().0).map(Instant)
+    }
+
+    /// The time that passed since `earlier`, or zero if `earlier` is actually later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        let seconds = self.0.saturating_sub(earlier.0) / u64::from(TICKS_PER_SIM_SECOND);
+        Duration(if seconds > u64::from(::std::u32::MAX) {
+            ::std::u32::MAX
+        } else {
+            seconds as u32
+        })
     }
 }
 
+// Adding and subtracting saturates (at the beginning of the simulation for subtraction),
+// use `checked_add`/`checked_sub` to detect overflows
+
 impl<D: Into<Ticks>> ::std::ops::Add<D> for Instant {
     type Output = Self;
 
     fn add(self, rhs: D) -> Self {
-        Instant(self.0 + rhs.into().0)
+        Instant(self.0.saturating_add(rhs.into().0))
     }
 }
 
 impl<D: Into<Ticks>> ::std::ops::AddAssign<D> for Instant {
     fn add_assign(&mut self, rhs: D) {
-        self.0 += rhs.into().0
+        *self = *self + rhs
     }
 }
 
@@ -92,13 +141,13 @@ impl<D: Into<Ticks>> ::std::ops::Sub<D> for Instant {
     type Output = Self;
 
     fn sub(self, rhs: D) -> Self {
-        Instant(self.0 - rhs.into().0)
+        Instant(self.0.saturating_sub(rhs.into().0))
     }
 }
 
 impl<D: Into<Ticks>> ::std::ops::SubAssign<D> for Instant {
     fn sub_assign(&mut self, rhs: D) {
-        self.0 -= rhs.into().0
+        *self = *self - rhs
     }
 }
 
@@ -107,8 +156,8 @@ pub struct TimeOfDay {
     minutes_of_day: u16,
 }
 
-const BEGINNING_TIME_OF_DAY: usize = 7;
-const MINUTES_PER_DAY: usize = 60 * 24;
+pub const BEGINNING_TIME_OF_DAY: usize = 7;
+pub const MINUTES_PER_DAY: usize = 60 * 24;
 
 impl TimeOfDay {
     pub fn new(h: usize, m: usize) -> Self {
@@ -125,46 +174,60 @@ impl TimeOfDay {
     }
 
     pub fn earlier_by(&self, delta: Duration) -> Self {
+        let delta_minutes_of_day = (delta.0 / 60) as usize % MINUTES_PER_DAY;
         TimeOfDay {
-            minutes_of_day: ((((self.minutes_of_day as isize - delta.as_minutes() as isize)
-                % MINUTES_PER_DAY as isize)
-                + MINUTES_PER_DAY as isize) as usize
+            minutes_of_day: ((self.minutes_of_day as usize + MINUTES_PER_DAY
+                - delta_minutes_of_day)
                 % MINUTES_PER_DAY) as u16,
         }
     }
 
     pub fn later_by(&self, delta: Duration) -> Self {
+        let delta_minutes_of_day = (delta.0 / 60) as usize % MINUTES_PER_DAY;
         TimeOfDay {
-            minutes_of_day: ((self.minutes_of_day as usize + delta.as_minutes() as usize)
+            minutes_of_day: ((self.minutes_of_day as usize + delta_minutes_of_day)
                 % MINUTES_PER_DAY) as u16,
         }
     }
+
+    /// The first instant after `instant` (at least a minute later) at which it is this time of day
+    pub fn next_after(&self, instant: Instant) -> Instant {
+        let current = TimeOfDay::from(instant);
+        let minutes_ahead = match (self.minutes_of_day as usize + MINUTES_PER_DAY
+            - current.minutes_of_day as usize)
+            % MINUTES_PER_DAY
+        {
+            0 => MINUTES_PER_DAY,
+            minutes_ahead => minutes_ahead,
+        };
+        let start_of_current_minute = instant.whole_minutes() * u64::from(TICKS_PER_SIM_MINUTE);
+
+        Instant(start_of_current_minute + minutes_ahead as u64 * u64::from(TICKS_PER_SIM_MINUTE))
+    }
 }
 
 impl From<Instant> for TimeOfDay {
     fn from(instant: Instant) -> TimeOfDay {
         TimeOfDay {
-            minutes_of_day: ((BEGINNING_TIME_OF_DAY * 60
-                + (instant.ticks() / TICKS_PER_SIM_MINUTE as usize))
-                % MINUTES_PER_DAY) as u16,
+            minutes_of_day: ((BEGINNING_TIME_OF_DAY as u64 * 60 + instant.whole_minutes())
+                % MINUTES_PER_DAY as u64) as u16,
         }
     }
 }
 
+// Adding and subtracting wraps around midnight
+
 impl<D: Into<Duration>> ::std::ops::Add<D> for TimeOfDay {
     type Output = Self;
 
     fn add(self, rhs: D) -> Self {
-        TimeOfDay {
-            minutes_of_day: self.minutes_of_day + (rhs.into().0 / 60) as u16,
-        }
+        self.later_by(rhs.into())
     }
 }
 
 impl<D: Into<Duration>> ::std::ops::AddAssign<D> for TimeOfDay {
-    #[cfg_attr(feature = "cargo-clippy", allow(suspicious_op_assign_impl))]
     fn add_assign(&mut self, rhs: D) {
-        self.minutes_of_day += (rhs.into().0 / 60) as u16
+        *self = self.later_by(rhs.into())
     }
 }
 
@@ -172,16 +235,13 @@ impl<D: Into<Duration>> ::std::ops::Sub<D> for TimeOfDay {
     type Output = Self;
 
     fn sub(self, rhs: D) -> Self {
-        TimeOfDay {
-            minutes_of_day: self.minutes_of_day - (rhs.into().0 / 60) as u16,
-        }
+        self.earlier_by(rhs.into())
     }
 }
 
 impl<D: Into<Duration>> ::std::ops::SubAssign<D> for TimeOfDay {
-    #[cfg_attr(feature = "cargo-clippy", allow(suspicious_op_assign_impl))]
     fn sub_assign(&mut self, rhs: D) {
-        self.minutes_of_day -= (rhs.into().0 / 60) as u16
+        *self = self.earlier_by(rhs.into())
     }
 }
 
@@ -230,3 +290,33 @@ impl TimeOfDayRange {
         }
     }
 }
+
+#[test]
+fn test_instant_arithmetic_saturates_or_is_checked() {
+    let start = Instant::new(0);
+    let end_of_time = Instant(::std::u64::MAX);
+
+    assert!(start - Duration(1) == start);
+    assert!(start.checked_sub(Duration(1)).is_none());
+    assert!(end_of_time + Ticks(1) == end_of_time);
+    assert!(end_of_time.checked_add(Ticks(1)).is_none());
+    assert!(start.checked_add(Duration(2)) == Some(Instant(2 * u64::from(TICKS_PER_SIM_SECOND))));
+
+    assert!(start.duration_since(start + Duration(5)) == Duration(0));
+    assert!(end_of_time.duration_since(start) == Duration(::std::u32::MAX));
+
+    // well beyond what fits into 32 bits
+    let far_future = Instant(u64::from(::std::u32::MAX) * 4);
+    assert!(far_future.ticks() == u64::from(::std::u32::MAX) * 4);
+}
+
+#[test]
+fn test_duration_arithmetic_saturates_or_is_checked() {
+    let max = Duration(::std::u32::MAX);
+
+    assert!(Duration(1) - Duration(2) == Duration(0));
+    assert!(Duration(1).checked_sub(Duration(2)).is_none());
+    assert!(max + Duration(1) == max);
+    assert!(max.checked_add(Duration(1)).is_none());
+    assert!(Duration(1).checked_add(Duration(2)) == Some(Duration(3)));
+}
@@ -0,0 +1,162 @@
+use compact::{Compact, CVec, CHashMap};
+use std::hash::Hash;
+use util::random::{uuid, Uuid};
+use super::time::Instant;
+
+/// Identifies a scheduled wake-up, so it can be cancelled or moved later
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct WakeUpHandle(pub Uuid);
+
+impl WakeUpHandle {
+    pub fn new() -> WakeUpHandle {
+        WakeUpHandle(uuid())
+    }
+}
+
+impl Default for WakeUpHandle {
+    fn default() -> WakeUpHandle {
+        WakeUpHandle::new()
+    }
+}
+
+/// A priority queue of items that are due at some instant, identified by a key.
+///
+/// Cancelled or moved entries are removed lazily: their old position stays in the heap
+/// until it reaches the top and is then skipped, because it doesn't match `scheduled` anymore.
+#[derive(Compact, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "K: ::serde::Serialize, T: ::serde::Serialize",
+    deserialize = "K: ::serde::Deserialize<'de>, T: ::serde::Deserialize<'de>"
+))]
+pub struct WakeUpQueue<K: Copy + Eq + Hash, T: Compact> {
+    heap: CVec<(Instant, K)>,
+    #[serde(with = "::util::serde_pairs")]
+    scheduled: CHashMap<K, (Instant, T)>,
+}
+
+impl<K: Copy + Eq + Hash, T: Compact> WakeUpQueue<K, T> {
+    pub fn new() -> Self {
+        WakeUpQueue {
+            heap: CVec::new(),
+            scheduled: CHashMap::new(),
+        }
+    }
+
+    /// Schedules `item` to be due at `due`, replacing anything scheduled under `key` before
+    pub fn schedule(&mut self, key: K, due: Instant, item: T) {
+        self.scheduled.insert(key, (due, item));
+        self.heap.push((due, key));
+        let new_idx = self.heap.len() - 1;
+        self.sift_up(new_idx);
+    }
+
+    pub fn cancel(&mut self, key: K) -> Option<T> {
+        self.scheduled.remove(key).map(|(_, item)| item)
+    }
+
+    /// Returns false if there is nothing (anymore) scheduled under `key`
+    pub fn reschedule(&mut self, key: K, new_due: Instant) -> bool {
+        match self.cancel(key) {
+            Some(item) => {
+                self.schedule(key, new_due, item);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels everything scheduled for items that `keep` returns false for
+    pub fn retain<F: Fn(&T) -> bool>(&mut self, keep: F) {
+        let removed_keys = self
+            .scheduled
+            .pairs()
+            .filter(|(_, (_, item))| !keep(item))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for key in removed_keys {
+            self.cancel(key);
+        }
+    }
+
+    pub fn is_scheduled(&self, key: K) -> bool {
+        self.scheduled.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.scheduled.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns the earliest item that is due strictly before `now`
+    pub fn pop_due_before(&mut self, now: Instant) -> Option<(K, T)> {
+        while let Some(&(due, key)) = self.heap.first() {
+            if due >= now {
+                return None;
+            }
+
+            self.pop_heap_top();
+
+            let is_current = self
+                .scheduled
+                .get(key)
+                .map(|&(current_due, _)| current_due == due)
+                .unwrap_or(false);
+
+            if is_current {
+                let (_, item) = self
+                    .scheduled
+                    .remove(key)
+                    .expect("just checked that it is scheduled");
+                return Some((key, item));
+            }
+        }
+
+        None
+    }
+
+    fn pop_heap_top(&mut self) {
+        let last_idx = self.heap.len() - 1;
+        self.heap.swap(0, last_idx);
+        self.heap.pop();
+        self.sift_down(0);
+    }
+
+    fn sift_up(&mut self, mut idx: usize) {
+        while idx > 0 {
+            let parent_idx = (idx - 1) / 2;
+            if self.heap[idx].0 >= self.heap[parent_idx].0 {
+                break;
+            }
+            self.heap.swap(idx, parent_idx);
+            idx = parent_idx;
+        }
+    }
+
+    fn sift_down(&mut self, mut idx: usize) {
+        loop {
+            let mut smallest_idx = idx;
+            for child_idx in &[2 * idx + 1, 2 * idx + 2] {
+                if *child_idx < self.heap.len()
+                    && self.heap[*child_idx].0 < self.heap[smallest_idx].0
+                {
+                    smallest_idx = *child_idx;
+                }
+            }
+            if smallest_idx == idx {
+                break;
+            }
+            self.heap.swap(idx, smallest_idx);
+            idx = smallest_idx;
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash, T: Compact> Default for WakeUpQueue<K, T> {
+    fn default() -> Self {
+        WakeUpQueue::new()
+    }
+}
@@ -80,7 +80,7 @@ impl Constructable for SwitchLane {
     }
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct ConstructionInfo {
     pub length: f32,
     pub path: LinePath,
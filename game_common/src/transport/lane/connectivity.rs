@@ -2,7 +2,7 @@ use compact::CVec;
 use descartes::N;
 use super::LaneID;
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct ConnectivityInfo {
     pub interactions: CVec<Interaction>,
     pub on_intersection: bool,
@@ -19,7 +19,7 @@ impl ConnectivityInfo {
 
 use super::super::microtraffic::LaneLikeID;
 
-#[derive(Compact, Clone, Default)]
+#[derive(Compact, Clone, Default, Serialize, Deserialize)]
 pub struct TransferConnectivityInfo {
     pub left: Option<(LaneID, f32)>,
     pub right: Option<(LaneID, f32)>,
@@ -27,7 +27,7 @@ pub struct TransferConnectivityInfo {
     pub right_distance_map: CVec<(N, N)>,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Interaction {
     pub partner_lane: LaneLikeID,
     pub start: f32,
@@ -35,7 +35,7 @@ pub struct Interaction {
     pub kind: InteractionKind,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum InteractionKind {
     Overlap {
         end: f32,
@@ -48,7 +48,7 @@ pub enum InteractionKind {
     Previous,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum OverlapKind {
     Parallel,
     Transfer,
@@ -1,5 +1,6 @@
-use compact::{CVec};
-use kay::{ActorSystem, World};
+use compact::{CVec, CString};
+use kay::{ActorSystem, World, TypedID};
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use descartes::{N, LinePath};
 
 use super::construction::ConstructionInfo;
@@ -8,7 +9,7 @@ use self::connectivity::{ConnectivityInfo, TransferConnectivityInfo};
 use super::microtraffic::{Microtraffic, TransferringMicrotraffic};
 use super::pathfinding::PathfindingInfo;
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Lane {
     pub id: LaneID,
     pub construction: ConstructionInfo,
@@ -40,7 +41,7 @@ impl Lane {
     }
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct SwitchLane {
     pub id: SwitchLaneID,
     pub construction: ConstructionInfo,
@@ -124,6 +125,44 @@ impl SwitchLane {
     }
 }
 
+impl Lane {
+    pub fn restore(id: LaneID, saved: &Lane, _: &mut World) -> Lane {
+        Lane {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for Lane {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
+impl SwitchLane {
+    pub fn restore(id: SwitchLaneID, saved: &SwitchLane, _: &mut World) -> SwitchLane {
+        SwitchLane {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for SwitchLane {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<Lane>();
     system.register::<SwitchLane>();
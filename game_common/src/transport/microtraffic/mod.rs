@@ -3,6 +3,7 @@ use compact::CVec;
 use ordered_float::OrderedFloat;
 use std::f32::INFINITY;
 use std::ops::{Deref, DerefMut};
+use util::profiling;
 
 use super::lane::{Lane, LaneID, SwitchLane, SwitchLaneID};
 use super::lane::connectivity::{Interaction, InteractionKind, OverlapKind};
@@ -13,7 +14,7 @@ use self::intelligent_acceleration::intelligent_acceleration;
 
 // TODO: move all iteration, updates, etc into one huge retain loop (see identical TODO below)
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Microtraffic {
     pub obstacles: CVec<(Obstacle, LaneLikeID)>,
     pub cars: CVec<LaneCar>,
@@ -40,20 +41,40 @@ impl Microtraffic {
 // unit values while traffic happening at a slower pace to be visible
 const MICROTRAFFIC_UNREALISTIC_SLOWDOWN: f32 = 1.0;
 
-#[derive(Compact, Clone, Default)]
+#[derive(Compact, Clone, Default, Serialize, Deserialize)]
 pub struct TransferringMicrotraffic {
     pub left_obstacles: CVec<Obstacle>,
     pub right_obstacles: CVec<Obstacle>,
     pub cars: CVec<TransferringLaneCar>,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Obstacle {
+    #[serde(with = "ordered_position")]
     pub position: OrderedFloat<f32>,
     pub velocity: f32,
     pub max_velocity: f32,
 }
 
+/// The version of `ordered_float` we use doesn't implement serde's traits
+mod ordered_position {
+    use ordered_float::OrderedFloat;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+    pub fn serialize<S: Serializer>(
+        position: &OrderedFloat<f32>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        position.0.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<OrderedFloat<f32>, D::Error> {
+        f32::deserialize(deserializer).map(OrderedFloat)
+    }
+}
+
 impl Obstacle {
     fn far_ahead() -> Obstacle {
         Obstacle {
@@ -80,7 +101,7 @@ impl Obstacle {
 use super::pathfinding::trip::{TripID, TripResult, TripFate};
 use super::pathfinding::Node;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct LaneCar {
     pub trip: TripID,
     pub as_obstacle: Obstacle,
@@ -112,7 +133,7 @@ impl DerefMut for LaneCar {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct TransferringLaneCar {
     as_lane_car: LaneCar,
     pub switch_position: f32,
@@ -152,8 +173,8 @@ use self::pathfinding::RoutingInfo;
 
 use simulation::{Simulatable, SimulatableID};
 
-const TRAFFIC_LOGIC_THROTTLING: usize = 10;
-const PATHFINDING_THROTTLING: usize = 10;
+const TRAFFIC_LOGIC_THROTTLING: u64 = 10;
+const PATHFINDING_THROTTLING: u64 = 10;
 
 impl LaneLike for Lane {
     fn add_car(
@@ -257,31 +278,34 @@ impl Lane {
 
 impl Simulatable for Lane {
     fn tick(&mut self, dt: f32, current_instant: Instant, world: &mut World) {
+        let _measurement = profiling::start("Lane microtraffic");
         let dt = dt / MICROTRAFFIC_UNREALISTIC_SLOWDOWN;
 
         self.construction.progress += dt * 400.0;
 
         let do_traffic = current_instant.ticks() % TRAFFIC_LOGIC_THROTTLING
-            == self.id.as_raw().instance_id as usize % TRAFFIC_LOGIC_THROTTLING;
+            == self.id.as_raw().instance_id as u64 % TRAFFIC_LOGIC_THROTTLING;
 
         let old_green = self.microtraffic.green;
+        let ticks = current_instant.ticks();
+        let n_timings = self.microtraffic.timings.len() as u64;
         self.microtraffic.yellow_to_red = if self.microtraffic.timings.is_empty() {
             true
         } else {
             !self.microtraffic.timings
-                [((current_instant.ticks() + 100) / 30) % self.microtraffic.timings.len()]
+                [((ticks + 100) / 30 % n_timings) as usize]
         };
         self.microtraffic.yellow_to_green = if self.microtraffic.timings.is_empty() {
             true
         } else {
             self.microtraffic.timings
-                [((current_instant.ticks() + 100) / 30) % self.microtraffic.timings.len()]
+                [((ticks + 100) / 30 % n_timings) as usize]
         };
         self.microtraffic.green = if self.microtraffic.timings.is_empty() {
             true
         } else {
             self.microtraffic.timings
-                [(current_instant.ticks() / 30) % self.microtraffic.timings.len()]
+                [(ticks / 30 % n_timings) as usize]
         };
 
         // TODO: this is just a hacky way to update new lanes about existing lane's green
@@ -303,9 +327,9 @@ impl Simulatable for Lane {
         }
 
         if current_instant.ticks() % PATHFINDING_THROTTLING
-            == self.id.as_raw().instance_id as usize % PATHFINDING_THROTTLING
+            == self.id.as_raw().instance_id as u64 % PATHFINDING_THROTTLING
         {
-            self.update_routes(world);
+            profiling::measure("Lane pathfinding", || self.update_routes(world));
         }
 
         if do_traffic {
@@ -479,7 +503,7 @@ impl Simulatable for Lane {
             let cars = self.microtraffic.cars.iter();
 
             if (current_instant.ticks() + 1) % TRAFFIC_LOGIC_THROTTLING
-                == interaction.partner_lane.as_raw().instance_id as usize % TRAFFIC_LOGIC_THROTTLING
+                == interaction.partner_lane.as_raw().instance_id as u64 % TRAFFIC_LOGIC_THROTTLING
             {
                 let maybe_obstacles = obstacles_for_interaction(
                     interaction,
@@ -554,12 +578,13 @@ impl LaneLike for SwitchLane {
 
 impl Simulatable for SwitchLane {
     fn tick(&mut self, dt: f32, current_instant: Instant, world: &mut World) {
+        let _measurement = profiling::start("SwitchLane microtraffic");
         let dt = dt / MICROTRAFFIC_UNREALISTIC_SLOWDOWN;
 
         self.construction.progress += dt * 400.0;
 
         let do_traffic = current_instant.ticks() % TRAFFIC_LOGIC_THROTTLING
-            == self.id.as_raw().instance_id as usize % TRAFFIC_LOGIC_THROTTLING;
+            == self.id.as_raw().instance_id as u64 % TRAFFIC_LOGIC_THROTTLING;
 
         if do_traffic {
             // TODO: optimize using BinaryHeap?
@@ -715,7 +740,7 @@ impl Simulatable for SwitchLane {
             }
 
             if (current_instant.ticks() + 1) % TRAFFIC_LOGIC_THROTTLING
-                == left.as_raw().instance_id as usize % TRAFFIC_LOGIC_THROTTLING
+                == left.as_raw().instance_id as u64 % TRAFFIC_LOGIC_THROTTLING
             {
                 let obstacles = self
                     .microtraffic
@@ -735,7 +760,7 @@ impl Simulatable for SwitchLane {
             }
 
             if (current_instant.ticks() + 1) % TRAFFIC_LOGIC_THROTTLING
-                == right.as_raw().instance_id as usize % TRAFFIC_LOGIC_THROTTLING
+                == right.as_raw().instance_id as u64 % TRAFFIC_LOGIC_THROTTLING
             {
                 let obstacles = self
                     .microtraffic
@@ -37,27 +37,29 @@ pub trait Node {
     fn remove_attachee(&mut self, attachee: AttacheeID, world: &mut World);
 }
 
-#[derive(Compact, Clone, Default)]
+#[derive(Compact, Clone, Default, Serialize, Deserialize)]
 pub struct PathfindingInfo {
     pub location: Option<Location>,
     pub hops_from_landmark: u8,
     pub learned_landmark_from: Option<NodeID>,
+    #[serde(with = "::util::serde_pairs")]
     pub routes: CHashMap<Location, RoutingInfo>,
     pub routes_changed: bool,
     pub tell_to_forget_next_tick: CVec<Location>,
     pub query_routes_next_tick: bool,
     pub routing_timeout: u16,
     attachees: CVec<AttacheeID>,
+    #[serde(with = "::util::serde_pairs")]
     pub debug_highlight_for: CHashMap<LaneID, ()>,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct Location {
     pub landmark: NodeID,
     pub node: NodeID,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct PreciseLocation {
     pub location: Location,
     pub offset: f32,
@@ -101,7 +103,7 @@ pub trait Attachee {
     );
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct RoutingInfo {
     pub outgoing_idx: u8,
     pub distance: f32,
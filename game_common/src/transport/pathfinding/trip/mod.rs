@@ -1,5 +1,6 @@
 use kay::{World, ActorSystem, Fate, TypedID, Actor};
-use compact::CVec;
+use compact::{CVec, CString};
+use savegame::{Persistent, PersistentID, SavegameManagerID, save_actor, restore_actor};
 use ordered_float::OrderedFloat;
 use simulation::Instant;
 
@@ -9,7 +10,7 @@ use super::{PreciseLocation, RoughLocationID, LocationRequester, LocationRequest
 use itertools::Itertools;
 use super::super::lane::Lane;
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct Trip {
     id: TripID,
     rough_source: RoughLocationID,
@@ -177,7 +178,7 @@ pub trait TripListener {
     );
 }
 
-#[derive(Compact, Clone)]
+#[derive(Compact, Clone, Serialize, Deserialize)]
 pub struct TripCreator {
     id: TripCreatorID,
     simulation: SimulationID,
@@ -202,11 +203,11 @@ impl TripCreator {
     }
 }
 
-use rand::Rng;
+use util::random::{seed, Rng};
 
 impl Sleeper for TripCreator {
     fn wake(&mut self, current_instant: Instant, world: &mut World) {
-        ::rand::thread_rng().shuffle(&mut self.lanes);
+        seed((self.id, current_instant)).shuffle(&mut self.lanes);
 
         for mut pair in &self.lanes.iter().chunks(2) {
             if let (Some(source), Some(dest)) = (pair.next(), pair.next()) {
@@ -292,6 +293,35 @@ impl PositionRequester for FailedTripDebugger {
     }
 }
 
+impl Trip {
+    pub fn restore(id: TripID, saved: &Trip, _: &mut World) -> Trip {
+        Trip {
+            id,
+            ..saved.clone()
+        }
+    }
+}
+
+impl Persistent for Trip {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
+impl Persistent for TripCreator {
+    fn save_state(&mut self, saver: SavegameManagerID, kind: &CString, world: &mut World) {
+        save_actor(self, self.id.as_raw(), kind, saver, world);
+    }
+
+    fn restore_state(&mut self, state: &CString, _: &mut World) {
+        restore_actor(self, state);
+    }
+}
+
 pub fn setup(system: &mut ActorSystem) {
     system.register::<Trip>();
     system.register::<TripCreator>();
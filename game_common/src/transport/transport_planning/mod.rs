@@ -1,18 +1,20 @@
 use compact::{CHashMap, CVec};
 use descartes::{N, P2, V2, Band, LinePath, ClosedLinePath, Area, Intersect, WithUniqueOrthogonal,
-RoughEq, PointContainer, AreaError};
+RoughEq, PointContainer};
 use itertools::Itertools;
 use ordered_float::OrderedFloat;
 
 use planning::{VersionedGesture, StepID, PrototypeID, PlanHistory, PlanResult,
-GestureIntent, Prototype, PrototypeKind, GestureID};
+GestureIntent, Prototype, PrototypeKind, GestureID, PlanError};
+use util::generational_cache::GenerationalCache;
+use util::grid_accelerator::{GridAccelerator, bounding_box_of_area, bounding_boxes_overlap};
 
 mod intersection_connections;
 mod smooth_path;
 use style::dimensions::{LANE_DISTANCE, CENTER_LANE_DISTANCE, MIN_SWITCHING_LANE_LENGTH,
 SWITCHING_LANE_OVERLAP_TOLERANCE};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct RoadIntent {
     pub n_lanes_forward: u8,
     pub n_lanes_backward: u8,
@@ -25,6 +27,11 @@ impl RoadIntent {
             n_lanes_backward,
         }
     }
+
+    /// The same road, seen from its other end
+    pub fn reversed(self) -> Self {
+        RoadIntent::new(self.n_lanes_backward, self.n_lanes_forward)
+    }
 }
 
 #[derive(Compact, Clone, Serialize, Deserialize, Debug)]
@@ -110,7 +117,7 @@ impl IntersectionConnector {
     }
 }
 
-#[derive(Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Debug)]
 pub struct GestureSideID(i16);
 
 impl GestureSideID {
@@ -125,7 +132,7 @@ impl GestureSideID {
 
 #[derive(Compact, Clone, Serialize, Deserialize, Debug)]
 pub struct IntersectionPrototype {
-    area: Area,
+    pub area: Area,
     incoming: CHashMap<GestureSideID, CVec<IntersectionConnector>>,
     outgoing: CHashMap<GestureSideID, CVec<IntersectionConnector>>,
     pub connecting_lanes: CHashMap<(GestureSideID, GestureSideID), CVec<LanePrototype>>,
@@ -136,65 +143,138 @@ impl IntersectionPrototype {
         // TODO: make this better!!
         (&self.area).rough_eq_by(&other.area, 0.1)
     }
+
+    /// Only lanes of a single end of one gesture meet here, which happens at the end
+    /// of a road that isn't connected to any other road. A road crossing itself isn't one
+    pub fn is_dead_end(&self) -> bool {
+        // at the end of a gesture, its forward lanes only come in and its backward lanes
+        // only go out, at its start it's the other way around
+        let mut gesture_ends = self
+            .incoming
+            .keys()
+            .map(|side_id| (side_id, true))
+            .chain(self.outgoing.keys().map(|side_id| (side_id, false)))
+            .map(|(&GestureSideID(side), is_incoming)| (side.abs(), (side > 0) == is_incoming));
+
+        match gesture_ends.next() {
+            Some(first_end) => gesture_ends.all(|end| end == first_end),
+            None => false,
+        }
+    }
+
+    /// Where lanes enter this intersection without any connecting lane leading on from them
+    pub fn unconnected_incoming_positions(&self) -> Vec<P2> {
+        self.incoming
+            .pairs()
+            .filter(|&(incoming_side_id, _)| {
+                !self
+                    .connecting_lanes
+                    .pairs()
+                    .any(|(&(from_side_id, _), lanes)| {
+                        from_side_id == *incoming_side_id && !lanes.is_empty()
+                    })
+            }).flat_map(|(_, connectors)| connectors.iter().map(|connector| connector.position))
+            .collect()
+    }
+}
+
+type VersionedGestureKey = (GestureID, StepID);
+
+/// Intermediate results that only depend on a few (versioned) gestures or lanes,
+/// so they can be reused while other gestures of a plan are changed
+#[derive(Default)]
+pub struct TransportPlanningCache {
+    smooth_paths: GenerationalCache<VersionedGestureKey, Option<LinePath>>,
+    gesture_pair_intersections:
+        GenerationalCache<(VersionedGestureKey, VersionedGestureKey), Vec<(Area, PrototypeID)>>,
+    switch_lanes: GenerationalCache<(PrototypeID, PrototypeID), Vec<(LinePath, PrototypeID)>>,
+}
+
+impl TransportPlanningCache {
+    pub fn finish_generation(&mut self) {
+        self.smooth_paths.finish_generation();
+        self.gesture_pair_intersections.finish_generation();
+        self.switch_lanes.finish_generation();
+    }
 }
 
 fn gesture_intent_smooth_paths(
     history: &PlanHistory,
+    cache: &mut TransportPlanningCache,
 ) -> Vec<(GestureID, StepID, RoadIntent, LinePath)> {
     history
-        .gestures
-        .pairs()
+        .live_gestures()
         .filter_map(
             |(gesture_id, VersionedGesture(gesture, step_id))| match gesture.intent {
-                GestureIntent::Road(ref road_intent) if gesture.points.len() >= 2 => {
-                    smooth_path::smooth_path_from(&gesture.points).map(|path| {
-                        (
-                            *gesture_id,
-                            *step_id,
-                            *road_intent,
-                            path.to_line_path_with_max_angle(0.06),
-                        )
-                    })
-                }
+                GestureIntent::Road(ref road_intent) if gesture.points.len() >= 2 => cache
+                    .smooth_paths
+                    .get_or_insert_with((*gesture_id, *step_id), || {
+                        gesture_smooth_path(&gesture.points)
+                    }).map(|path| (*gesture_id, *step_id, *road_intent, path)),
                 _ => None,
             },
         ).collect::<Vec<_>>()
 }
 
-#[cfg_attr(feature = "cargo-clippy", allow(cyclomatic_complexity))]
-pub fn calculate_prototypes(
-    history: &PlanHistory,
-    _current_result: &PlanResult,
-) -> Result<Vec<Prototype>, AreaError> {
-    let gesture_intent_smooth_paths = gesture_intent_smooth_paths(history);
+/// The path that a road gesture through `points` follows
+pub fn gesture_smooth_path(points: &[P2]) -> Option<LinePath> {
+    smooth_path::smooth_path_from(points).map(|path| path.to_line_path_with_max_angle(0.06))
+}
 
-    let gesture_areas_for_intersection = gesture_intent_smooth_paths
-        .iter()
-        .map(|&(gesture_id, step_id, road_intent, ref path)| {
-            (
-                Band::new_asymmetric(
-                    path.clone(),
-                    f32::from(road_intent.n_lanes_backward) * LANE_DISTANCE + 0.4 * LANE_DISTANCE,
-                    f32::from(road_intent.n_lanes_forward) * LANE_DISTANCE + 0.4 * LANE_DISTANCE,
-                ).as_area(),
-                gesture_id,
-                step_id,
-            )
-        }).collect::<Vec<_>>();
+pub fn gesture_area_for_intersection(road_intent: RoadIntent, path: &LinePath) -> Area {
+    Band::new_asymmetric(
+        path.clone(),
+        f32::from(road_intent.n_lanes_backward) * LANE_DISTANCE + 0.4 * LANE_DISTANCE,
+        f32::from(road_intent.n_lanes_forward) * LANE_DISTANCE + 0.4 * LANE_DISTANCE,
+    ).as_area()
+}
+
+/// Only gestures whose bounding boxes overlap are split against each other,
+/// found using a grid over the whole plan
+pub fn gesture_intersection_areas(
+    gesture_areas: &[(Area, GestureID, StepID)],
+    cache: &mut TransportPlanningCache,
+) -> Vec<(Area, PrototypeID, Vec<GestureID>)> {
+    const GESTURE_GRID_CELL_SIZE: N = 50.0;
 
-    let mut intersection_areas = gesture_areas_for_intersection
+    let gesture_bounding_boxes = gesture_areas
         .iter()
-        .enumerate()
-        .cartesian_product(gesture_areas_for_intersection.iter().enumerate())
-        .flat_map(
-            |(
-                (i_a, (shape_a, gesture_id_a, step_id_a)),
-                (i_b, (shape_b, gesture_id_b, step_id_b)),
-            )| {
-                if i_a == i_b {
-                    // TODO: add self-intersections
-                    vec![]
+        .map(|&(ref shape, _, _)| bounding_box_of_area(shape))
+        .collect::<Vec<_>>();
+
+    let mut gesture_grid = GridAccelerator::new(GESTURE_GRID_CELL_SIZE);
+
+    for (gesture_i, bounding_box) in gesture_bounding_boxes.iter().enumerate() {
+        gesture_grid.add(gesture_i, ::std::iter::once(bounding_box.clone()));
+    }
+
+    // TODO: add self-intersections
+    gesture_grid
+        .unique_colocated_pairs()
+        .filter(|&(gesture_i_a, gesture_i_b)| {
+            bounding_boxes_overlap(
+                &gesture_bounding_boxes[gesture_i_a],
+                &gesture_bounding_boxes[gesture_i_b],
+            )
+        }).flat_map(|(gesture_i_a, gesture_i_b)| {
+            // the order of gestures in the history isn't stable,
+            // so order each pair by gesture ID to keep cache keys and prototype IDs stable
+            let (
+                &(ref shape_a, gesture_id_a, step_id_a),
+                &(ref shape_b, gesture_id_b, step_id_b),
+            ) = {
+                let gesture_a = &gesture_areas[gesture_i_a];
+                let gesture_b = &gesture_areas[gesture_i_b];
+                if (gesture_a.1).0 <= (gesture_b.1).0 {
+                    (gesture_a, gesture_b)
                 } else {
+                    (gesture_b, gesture_a)
+                }
+            };
+
+            let intersections = cache.gesture_pair_intersections.get_or_insert_with(
+                ((gesture_id_a, step_id_a), (gesture_id_b, step_id_b)),
+                || {
                     let split = shape_a.split(shape_b);
                     if let Ok(intersections) = split.intersection() {
                         intersections
@@ -216,9 +296,34 @@ pub fn calculate_prototypes(
                     } else {
                         vec![]
                     }
-                }
-            },
-        ).collect::<Vec<_>>();
+                },
+            );
+
+            intersections
+                .into_iter()
+                .map(move |(area, id)| (area, id, vec![gesture_id_a, gesture_id_b]))
+        }).collect()
+}
+
+#[cfg_attr(feature = "cargo-clippy", allow(cyclomatic_complexity))]
+pub fn calculate_prototypes(
+    history: &PlanHistory,
+    _current_result: &PlanResult,
+    cache: &mut TransportPlanningCache,
+) -> Result<Vec<Prototype>, PlanError> {
+    let gesture_intent_smooth_paths = gesture_intent_smooth_paths(history, cache);
+
+    let gesture_areas_for_intersection = gesture_intent_smooth_paths
+        .iter()
+        .map(|&(gesture_id, step_id, road_intent, ref path)| {
+            (
+                gesture_area_for_intersection(road_intent, path),
+                gesture_id,
+                step_id,
+            )
+        }).collect::<Vec<_>>();
+
+    let mut intersection_areas = gesture_intersection_areas(&gesture_areas_for_intersection, cache);
 
     // add intersections at the starts and ends of gestures
     const END_INTERSECTION_DEPTH: N = 15.0;
@@ -255,6 +360,7 @@ pub fn calculate_prototypes(
                             ).expect("End intersection path should be closed"),
                         ),
                         PrototypeID::from_influences((gesture_id, step_id, i)),
+                        vec![gesture_id],
                     )
                 }).collect::<Vec<_>>()
         },
@@ -264,21 +370,40 @@ pub fn calculate_prototypes(
 
     let mut unioned_intersection_areas = Vec::new();
 
-    for (intersection_area, initial_influences) in intersection_areas {
+    for (intersection_area, initial_influences, initial_gesture_ids) in intersection_areas {
         let mut area_being_added = intersection_area;
         let mut area_being_added_influences = initial_influences;
+        let mut area_being_added_gesture_ids = initial_gesture_ids;
         let mut current_idx = 0;
 
         while current_idx < unioned_intersection_areas.len() {
             let remove = {
-                let &(ref other, other_influences) = &unioned_intersection_areas[current_idx];
+                let &(ref other, other_influences, ref other_gesture_ids) =
+                    &unioned_intersection_areas[current_idx];
 
                 if let Some(hopefully_union) = area_being_added
                     .split_if_intersects(other)
                     .map(|split| split.union())
                 {
-                    area_being_added = hopefully_union?.disjoint().remove(0);
-                    area_being_added_influences.add_influences(other_influences);
+                    area_being_added_gesture_ids.extend(other_gesture_ids.iter().cloned());
+
+                    area_being_added = match hopefully_union {
+                        Ok(union) => union.disjoint().remove(0),
+                        Err(err) => {
+                            let bounding_box = bounding_box_of_area(other);
+                            return Err(PlanError::from_area_error(
+                                &err,
+                                area_being_added_gesture_ids,
+                                P2::from_coordinates(
+                                    (bounding_box.min.coords + bounding_box.max.coords) / 2.0,
+                                ),
+                            ));
+                        }
+                    };
+                    // the ID has to change with every contributing area, so cached
+                    // lanes cut by this intersection are recalculated when it changes
+                    area_being_added_influences =
+                        area_being_added_influences.add_influences(other_influences);
                     true
                 } else {
                     false
@@ -292,12 +417,19 @@ pub fn calculate_prototypes(
             }
         }
 
-        unioned_intersection_areas.push((area_being_added, area_being_added_influences));
+        area_being_added_gesture_ids.sort_by_key(|gesture_id| gesture_id.0);
+        area_being_added_gesture_ids.dedup();
+
+        unioned_intersection_areas.push((
+            area_being_added,
+            area_being_added_influences,
+            area_being_added_gesture_ids,
+        ));
     }
 
     let mut intersection_prototypes: Vec<_> = unioned_intersection_areas
         .into_iter()
-        .map(|(intersection_area, id)| Prototype {
+        .map(|(intersection_area, id, _)| Prototype {
             kind: PrototypeKind::Road(RoadPrototype::Intersection(IntersectionPrototype {
                 area: intersection_area,
                 incoming: CHashMap::new(),
@@ -473,64 +605,70 @@ pub fn calculate_prototypes(
                     (right_path, right_outline, right_band, right_id),
                     (left_path, left_outline, left_band, left_id),
                 )| {
-                    let mut intersections = (right_outline, left_outline).intersect();
-                    let switch_id = right_id.add_influences(left_id);
-
-                    if intersections.len() < 2 {
-                        vec![]
-                    } else {
-                        intersections.sort_by_key(|intersection| {
-                            OrderedFloat(
-                                right_band.outline_distance_to_path_distance(intersection.along_a),
-                            )
-                        });
-
-                        intersections
-                            .windows(2)
-                            .filter_map(|intersection_pair| {
-                                let first_along_right = right_band
-                                    .outline_distance_to_path_distance(
-                                        intersection_pair[0].along_a,
-                                    );
-                                let second_along_right = right_band
-                                    .outline_distance_to_path_distance(
-                                        intersection_pair[1].along_a,
-                                    );
-                                let first_along_left = left_band.outline_distance_to_path_distance(
-                                    intersection_pair[0].along_b,
-                                );
-                                let second_along_left = left_band
-                                    .outline_distance_to_path_distance(
-                                        intersection_pair[1].along_b,
-                                    );
-                                // intersecting subsections go in the same direction on both
-                                // lanes?
-                                if first_along_left < second_along_left {
-                                    // are the midpoints of subsections on each side still in
-                                    // range?
-                                    if right_path
-                                        .along((first_along_right + second_along_right) / 2.0)
-                                        .rough_eq_by(
-                                            left_path.along(
-                                                (first_along_left + second_along_left) / 2.0,
-                                            ),
-                                            SWITCHING_LANE_OVERLAP_TOLERANCE,
-                                        ) {
-                                        right_path.subsection(first_along_right, second_along_right)
+                    cache.switch_lanes.get_or_insert_with((**right_id, **left_id), || {
+                        let mut intersections = (right_outline, left_outline).intersect();
+                        let switch_id = right_id.add_influences(left_id);
+
+                        if intersections.len() < 2 {
+                            vec![]
+                        } else {
+                            intersections.sort_by_key(|intersection| {
+                                OrderedFloat(
+                                    right_band
+                                        .outline_distance_to_path_distance(intersection.along_a),
+                                )
+                            });
+
+                            intersections
+                                .windows(2)
+                                .filter_map(|intersection_pair| {
+                                    let first_along_right = right_band
+                                        .outline_distance_to_path_distance(
+                                            intersection_pair[0].along_a,
+                                        );
+                                    let second_along_right = right_band
+                                        .outline_distance_to_path_distance(
+                                            intersection_pair[1].along_a,
+                                        );
+                                    let first_along_left = left_band
+                                        .outline_distance_to_path_distance(
+                                            intersection_pair[0].along_b,
+                                        );
+                                    let second_along_left = left_band
+                                        .outline_distance_to_path_distance(
+                                            intersection_pair[1].along_b,
+                                        );
+                                    // intersecting subsections go in the same direction on both
+                                    // lanes?
+                                    if first_along_left < second_along_left {
+                                        // are the midpoints of subsections on each side still in
+                                        // range?
+                                        if right_path
+                                            .along((first_along_right + second_along_right) / 2.0)
+                                            .rough_eq_by(
+                                                left_path.along(
+                                                    (first_along_left + second_along_left) / 2.0,
+                                                ),
+                                                SWITCHING_LANE_OVERLAP_TOLERANCE,
+                                            ) {
+                                            right_path
+                                                .subsection(first_along_right, second_along_right)
+                                        } else {
+                                            None
+                                        }
                                     } else {
                                         None
                                     }
-                                } else {
-                                    None
-                                }
-                            }).coalesce(|prev_subsection, next_subsection| {
-                                prev_subsection
-                                    .concat(&next_subsection)
-                                    .map_err(|_| (prev_subsection, next_subsection))
-                            }).filter(|subsection| subsection.length() > MIN_SWITCHING_LANE_LENGTH)
-                            .map(|subsection| (subsection, switch_id))
-                            .collect()
-                    }
+                                }).coalesce(|prev_subsection, next_subsection| {
+                                    prev_subsection
+                                        .concat(&next_subsection)
+                                        .map_err(|_| (prev_subsection, next_subsection))
+                                }).filter(|subsection| {
+                                    subsection.length() > MIN_SWITCHING_LANE_LENGTH
+                                }).map(|subsection| (subsection, switch_id))
+                                .collect()
+                        }
+                    })
                 },
             ).collect::<Vec<_>>()
     };
@@ -0,0 +1,63 @@
+use fnv::FnvHashMap;
+use std::hash::Hash;
+
+/// Memoizes results of expensive calculations that are repeated with mostly the same inputs.
+///
+/// Entries survive as long as they were used in the current or the previous generation,
+/// so calling `finish_generation` after every full calculation drops what has become stale,
+/// while still allowing two alternating calculations (like a proposal preview and the
+/// master plan) to share their entries.
+pub struct GenerationalCache<K: Hash + Eq, V: Clone> {
+    current: FnvHashMap<K, V>,
+    previous: FnvHashMap<K, V>,
+}
+
+impl<K: Hash + Eq, V: Clone> GenerationalCache<K, V> {
+    pub fn new() -> Self {
+        GenerationalCache {
+            current: FnvHashMap::default(),
+            previous: FnvHashMap::default(),
+        }
+    }
+
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, compute: F) -> V {
+        let result: Result<V, ()> = self.try_get_or_insert_with(key, || Ok(compute()));
+        result.unwrap_or_else(|_| unreachable!())
+    }
+
+    /// Failed calculations are not cached
+    pub fn try_get_or_insert_with<E, F: FnOnce() -> Result<V, E>>(
+        &mut self,
+        key: K,
+        compute: F,
+    ) -> Result<V, E> {
+        if let Some(value) = self.current.get(&key) {
+            return Ok(value.clone());
+        }
+
+        let value = match self.previous.remove(&key) {
+            Some(value) => value,
+            None => compute()?,
+        };
+        self.current.insert(key, value.clone());
+        Ok(value)
+    }
+
+    pub fn finish_generation(&mut self) {
+        self.previous = ::std::mem::replace(&mut self.current, FnvHashMap::default());
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.len() + self.previous.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K: Hash + Eq, V: Clone> Default for GenerationalCache<K, V> {
+    fn default() -> Self {
+        GenerationalCache::new()
+    }
+}
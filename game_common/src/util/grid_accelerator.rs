@@ -1,4 +1,4 @@
-use descartes::{N, BoundingBox};
+use descartes::{N, P2, Area, BoundingBox};
 use fnv::{FnvHashMap, FnvHashSet};
 use roaring::RoaringBitmap;
 
@@ -68,4 +68,42 @@ impl GridAccelerator {
     pub fn colocated_pairs(&self) -> &Vec<(usize, RoaringBitmap)> {
         &self.colocated_pairs
     }
+
+    /// Every pair of refs that share at least one cell, only once
+    /// and with the ref that was added earlier first
+    pub fn unique_colocated_pairs<'a>(&'a self) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.colocated_pairs
+            .iter()
+            .flat_map(|&(later_ref, ref earlier_refs)| {
+                earlier_refs
+                    .iter()
+                    .map(|earlier_ref| earlier_ref as usize)
+                    // a ref can see itself if several of its bounding boxes share a cell
+                    .filter(move |&earlier_ref| earlier_ref != later_ref)
+                    .map(move |earlier_ref| (earlier_ref, later_ref))
+            })
+    }
+}
+
+pub fn bounding_box_of_points<'a, I: IntoIterator<Item = &'a P2>>(points: I) -> BoundingBox {
+    let mut min = P2::new(::std::f32::INFINITY, ::std::f32::INFINITY);
+    let mut max = P2::new(::std::f32::NEG_INFINITY, ::std::f32::NEG_INFINITY);
+    for point in points {
+        min = P2::new(min.x.min(point.x), min.y.min(point.y));
+        max = P2::new(max.x.max(point.x), max.y.max(point.y));
+    }
+    BoundingBox { min, max }
+}
+
+pub fn bounding_box_of_area(area: &Area) -> BoundingBox {
+    bounding_box_of_points(
+        area.primitives
+            .iter()
+            .flat_map(|primitive| primitive.boundary.path().points.iter()),
+    )
+}
+
+/// Also true if the boxes only touch
+pub fn bounding_boxes_overlap(a: &BoundingBox, b: &BoundingBox) -> bool {
+    a.min.x <= b.max.x && b.min.x <= a.max.x && a.min.y <= b.max.y && b.min.y <= a.max.y
 }
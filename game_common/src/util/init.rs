@@ -34,7 +34,8 @@ use backtrace::Backtrace;
 use std::fs::File;
 use std::io::Write;
 
-pub fn set_error_hook() {
+/// `journal_path` is mentioned in the report, so the crash can be reproduced with `--replay`
+pub fn set_error_hook(journal_path: Option<String>) {
     let callback: Box<FnMut(&PanicInfo)> = Box::new(move |panic_info| {
         let title = "SIMULATION BROKE :(";
 
@@ -53,9 +54,17 @@ pub fn set_error_hook() {
             panic_info.location().map(|l| l.line()).unwrap_or(0)
         );
 
+        let journal_note = match journal_path {
+            Some(ref path) => format!(
+                "\n\nINPUT JOURNAL:\nPlease attach {:?} to your report, it can reproduce this",
+                path
+            ),
+            None => String::new(),
+        };
+
         let body = format!(
-            "WHAT HAPPENED:\n{}\n\nWHERE IT HAPPENED:\n{}\n\nWHERE EXACTLY:\n{:?}",
-            message, location, backtrace
+            "WHAT HAPPENED:\n{}\n\nWHERE IT HAPPENED:\n{}{}\n\nWHERE EXACTLY:\n{:?}",
+            message, location, journal_note, backtrace
         );
 
         let report_guide = "HOW TO REPORT \
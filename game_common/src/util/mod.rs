@@ -1,5 +1,8 @@
 pub mod init;
 pub mod disjoint_sets;
 pub mod grid_accelerator;
+pub mod generational_cache;
 pub mod async_counter;
 pub mod random;
+pub mod profiling;
+pub mod serde_pairs;
@@ -0,0 +1,197 @@
+//! Measures where the time of a machine's main loop goes, grouped into named categories
+//! and aggregated over windows of frames. Nested measurements are subtracted from their
+//! parent, so every category only counts its own time and the categories add up.
+//!
+//! Nothing is measured unless profiling was enabled, which keeps the cost for normal runs
+//! at a single atomic load per measurement.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[derive(Default)]
+struct CategoryTotals {
+    self_time: Duration,
+    calls: usize,
+}
+
+struct OpenMeasurement {
+    started: Instant,
+    time_in_children: Duration,
+}
+
+#[derive(Default)]
+struct Profile {
+    categories: HashMap<&'static str, CategoryTotals>,
+    open_measurements: Vec<OpenMeasurement>,
+    window_started: Option<Instant>,
+    frames_in_window: usize,
+}
+
+thread_local! {
+    static PROFILE: RefCell<Profile> = RefCell::new(Profile::default());
+}
+
+/// Measures until the returned guard is dropped, use like
+/// `let _measurement = profiling::start("Lane ticks");`
+pub fn start(category: &'static str) -> Measurement {
+    if is_enabled() {
+        PROFILE.with(|profile| {
+            profile.borrow_mut().open_measurements.push(OpenMeasurement {
+                started: Instant::now(),
+                time_in_children: Duration::new(0, 0),
+            })
+        });
+        Measurement(Some(category))
+    } else {
+        Measurement(None)
+    }
+}
+
+pub fn measure<T, F: FnOnce() -> T>(category: &'static str, f: F) -> T {
+    let _measurement = start(category);
+    f()
+}
+
+pub struct Measurement(Option<&'static str>);
+
+impl Drop for Measurement {
+    fn drop(&mut self) {
+        if let Some(category) = self.0 {
+            PROFILE.with(|profile| {
+                let mut profile = profile.borrow_mut();
+                let measurement = profile
+                    .open_measurements
+                    .pop()
+                    .expect("Should have an open measurement");
+                let elapsed = measurement.started.elapsed();
+
+                if let Some(parent) = profile.open_measurements.last_mut() {
+                    parent.time_in_children += elapsed;
+                }
+
+                let totals = profile.categories.entry(category).or_default();
+                totals.self_time += elapsed
+                    .checked_sub(measurement.time_in_children)
+                    .unwrap_or_else(|| Duration::new(0, 0));
+                totals.calls += 1;
+            });
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CategoryTiming {
+    pub category: String,
+    pub total_ms: f32,
+    pub ms_per_frame: f32,
+    pub calls: usize,
+    pub share: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProfileReport {
+    pub frames: usize,
+    pub window_ms: f32,
+    /// Sorted by total time, most expensive first
+    pub categories: Vec<CategoryTiming>,
+}
+
+fn as_ms(duration: Duration) -> f32 {
+    duration.as_secs() as f32 * 1000.0 + duration.subsec_nanos() as f32 / 1.0E6
+}
+
+/// Call once per main loop iteration. Returns a report (and starts a new window)
+/// once at least `window` has passed since the current window started.
+pub fn finish_frame(window: Duration) -> Option<ProfileReport> {
+    if !is_enabled() {
+        return None;
+    }
+
+    PROFILE.with(|profile| {
+        let mut profile = profile.borrow_mut();
+        profile.frames_in_window += 1;
+
+        let window_started = *profile.window_started.get_or_insert_with(Instant::now);
+        let window_elapsed = window_started.elapsed();
+
+        if window_elapsed < window {
+            return None;
+        }
+
+        let frames = profile.frames_in_window;
+        let measured_ms: f32 = profile
+            .categories
+            .values()
+            .map(|totals| as_ms(totals.self_time))
+            .sum();
+
+        let mut categories = profile
+            .categories
+            .drain()
+            .map(|(category, totals)| {
+                let total_ms = as_ms(totals.self_time);
+                CategoryTiming {
+                    category: category.to_owned(),
+                    total_ms,
+                    ms_per_frame: total_ms / frames as f32,
+                    calls: totals.calls,
+                    share: total_ms / measured_ms.max(::std::f32::EPSILON),
+                }
+            }).collect::<Vec<_>>();
+        categories.sort_by(|a, b| {
+            b.total_ms
+                .partial_cmp(&a.total_ms)
+                .unwrap_or(::std::cmp::Ordering::Equal)
+        });
+
+        profile.frames_in_window = 0;
+        profile.window_started = Some(Instant::now());
+
+        Some(ProfileReport {
+            frames,
+            window_ms: as_ms(window_elapsed),
+            categories,
+        })
+    })
+}
+
+impl ProfileReport {
+    pub fn as_table(&self) -> String {
+        let mut table = format!(
+            "PROFILE ({} frames in {:.0}ms, {:.1} FPS)\n{:<36} {:>10} {:>10} {:>10} {:>7}\n",
+            self.frames,
+            self.window_ms,
+            self.frames as f32 * 1000.0 / self.window_ms.max(::std::f32::EPSILON),
+            "category",
+            "total ms",
+            "ms/frame",
+            "calls",
+            "share"
+        );
+
+        for timing in &self.categories {
+            table.push_str(&format!(
+                "{:<36} {:>10.1} {:>10.2} {:>10} {:>6.1}%\n",
+                timing.category,
+                timing.total_ms,
+                timing.ms_per_frame,
+                timing.calls,
+                timing.share * 100.0
+            ));
+        }
+
+        table
+    }
+}
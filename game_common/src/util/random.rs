@@ -2,6 +2,48 @@ pub use rand::{Rng, RngCore, thread_rng};
 pub use uuid::Uuid;
 use fnv::FnvHasher;
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+// Stored as two halves, since there are no 64-bit atomics on all targets
+static WORLD_SEED_HIGH: AtomicUsize = ATOMIC_USIZE_INIT;
+static WORLD_SEED_LOW: AtomicUsize = ATOMIC_USIZE_INIT;
+static UUIDS_GENERATED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// All randomness of a world is derived from its seed, so the same seed and the same
+/// inputs (see `journal`) result in the same city
+pub fn set_world_seed(world_seed: u64) {
+    WORLD_SEED_HIGH.store((world_seed >> 32) as usize, Ordering::SeqCst);
+    WORLD_SEED_LOW.store((world_seed & 0xFFFF_FFFF) as usize, Ordering::SeqCst);
+}
+
+pub fn world_seed() -> u64 {
+    ((WORLD_SEED_HIGH.load(Ordering::SeqCst) as u64) << 32)
+        | WORLD_SEED_LOW.load(Ordering::SeqCst) as u64
+}
+
+/// A fresh seed for when none was given, based on real randomness
+pub fn random_world_seed() -> u64 {
+    thread_rng().gen()
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct RandomnessState {
+    pub world_seed: u64,
+    pub uuids_generated: u64,
+}
+
+pub fn randomness_state() -> RandomnessState {
+    RandomnessState {
+        world_seed: world_seed(),
+        uuids_generated: UUIDS_GENERATED.load(Ordering::SeqCst) as u64,
+    }
+}
+
+/// Continues where a saved world left off, so new IDs don't collide with saved ones
+pub fn restore_randomness_state(state: RandomnessState) {
+    set_world_seed(state.world_seed);
+    UUIDS_GENERATED.store(state.uuids_generated as usize, Ordering::SeqCst);
+}
 
 // A hashing function with hopefully low correlation between seeds
 // but not necessarily good randomness of sequential probes on the same seed
@@ -22,23 +64,47 @@ impl RngCore for FnvRng {
         self.next_u64() as u32
     }
 
-    fn fill_bytes(&mut self, _bytes: &mut [u8]) {
-        unimplemented!()
+    fn fill_bytes(&mut self, bytes: &mut [u8]) {
+        for chunk in bytes.chunks_mut(8) {
+            let random = self.next_u64();
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                *byte = (random >> (8 * i)) as u8;
+            }
+        }
     }
 
-    fn try_fill_bytes(&mut self, _bytes: &mut [u8]) -> Result<(), ::rand::Error> {
-        unimplemented!()
+    fn try_fill_bytes(&mut self, bytes: &mut [u8]) -> Result<(), ::rand::Error> {
+        self.fill_bytes(bytes);
+        Ok(())
     }
 }
 
+/// Deterministic randomness for a given seed (and world seed)
 pub fn seed<S: Hash>(seed: S) -> FnvRng {
     let mut hasher = FnvHasher::default();
+    world_seed().hash(&mut hasher);
     seed.hash(&mut hasher);
     FnvRng {
         seed: hasher.finish(),
     }
 }
 
+/// On the server, IDs are derived from the world seed and the number of IDs generated
+/// so far, which makes them reproducible. Browsers keep using real randomness, because
+/// their IDs have to be unique across all connected clients.
+#[cfg(feature = "server")]
+pub fn uuid() -> Uuid {
+    let n_generated = UUIDS_GENERATED.fetch_add(1, Ordering::SeqCst);
+    Uuid::from_random_bytes(seed(("uuid", n_generated)).gen())
+}
+
+#[cfg(not(feature = "server"))]
 pub fn uuid() -> Uuid {
     Uuid::from_random_bytes(thread_rng().gen())
 }
+
+/// For secrets, which must not be derivable from the world seed,
+/// and which don't change how many IDs were generated so far
+pub fn unpredictable_uuid() -> Uuid {
+    Uuid::from_random_bytes(thread_rng().gen())
+}
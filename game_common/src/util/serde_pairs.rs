@@ -0,0 +1,32 @@
+//! Serializes a `CHashMap` as a list of key-value pairs, for use with `#[serde(with)]`.
+//! JSON only allows strings as keys of maps, so maps keyed by IDs or other
+//! structs can't be saved as JSON maps.
+
+use compact::{Compact, CHashMap};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use std::hash::Hash;
+
+pub fn serialize<K, V, S>(map: &CHashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: Copy + Eq + Hash + Serialize,
+    V: Compact + Serialize,
+    S: Serializer,
+{
+    serializer.collect_seq(map.pairs())
+}
+
+pub fn deserialize<'de, K, V, D>(deserializer: D) -> Result<CHashMap<K, V>, D::Error>
+where
+    K: Copy + Eq + Hash + Deserialize<'de>,
+    V: Compact + Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    let pairs = Vec::<(K, V)>::deserialize(deserializer)?;
+    let mut map = CHashMap::new();
+
+    for (key, value) in pairs {
+        map.insert(key, value);
+    }
+
+    Ok(map)
+}
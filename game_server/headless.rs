@@ -0,0 +1,194 @@
+use citybound_common::*;
+use clap::ArgMatches;
+use std::fs::File;
+use std::hash::Hash;
+
+use descartes::P2;
+use planning::{Plan, Proposal, StepID, GestureID, Gesture, GestureIntent};
+use simulation::{Duration, Ticks};
+use util::random::{seed, Rng, Uuid};
+
+#[derive(Deserialize)]
+pub struct Scenario {
+    #[serde(default = "default_speed")]
+    pub speed: u16,
+    pub duration_days: f32,
+    /// World seed, the same seed and steps always result in the same city
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub steps: Vec<ScenarioStep>,
+}
+
+fn default_speed() -> u16 {
+    1
+}
+
+/// Gestures that are implemented as one proposal once the simulation reaches `at_day`
+#[derive(Deserialize)]
+pub struct ScenarioStep {
+    #[serde(default)]
+    pub at_day: f32,
+    pub gestures: Vec<ScenarioGesture>,
+}
+
+#[derive(Deserialize)]
+pub struct ScenarioGesture {
+    pub points: Vec<P2>,
+    pub intent: GestureIntent,
+}
+
+// IDs normally come from `uuid()`, which would make every run different
+fn scenario_uuid<H: Hash>(influences: H) -> Uuid {
+    Uuid::from_random_bytes(seed(influences).gen())
+}
+
+impl ScenarioStep {
+    fn as_proposal(&self, step_idx: usize) -> Proposal {
+        Proposal::from_plan(Plan {
+            step_id: StepID(scenario_uuid(("step", step_idx))),
+            gestures: self
+                .gestures
+                .iter()
+                .enumerate()
+                .map(|(gesture_idx, scenario_gesture)| {
+                    (
+                        GestureID(scenario_uuid(("gesture", step_idx, gesture_idx))),
+                        Gesture::new(
+                            scenario_gesture.points.clone().into(),
+                            scenario_gesture.intent.clone(),
+                        ),
+                    )
+                }).collect(),
+        })
+    }
+}
+
+pub fn load_scenario(path: &str) -> Result<Scenario, String> {
+    let file = File::open(path).map_err(|err| format!("Couldn't open {}: {}", path, err))?;
+    ::serde_json::from_reader(file).map_err(|err| format!("Couldn't parse {}: {}", path, err))
+}
+
+pub fn run(arg_matches: &ArgMatches) {
+    let scenario_path = arg_matches.value_of("scenario").unwrap();
+    let mut scenario = match load_scenario(scenario_path) {
+        Ok(scenario) => scenario,
+        Err(err) => {
+            println!("{}", err);
+            ::std::process::exit(1);
+        }
+    };
+
+    if let Some(days) = arg_matches.value_of("days") {
+        scenario.duration_days = days.parse().expect("--days should be a number");
+    }
+
+    if let Some(seed) = arg_matches.value_of("seed") {
+        scenario.seed = seed.parse().expect("--seed should be a number");
+    }
+
+    util::profiling::set_enabled(arg_matches.is_present("profile"));
+
+    let speed = scenario.speed.max(1);
+    let ticks_per_day = Ticks::from(Duration::from_hours(24)).0 as f32;
+    let total_ticks = (scenario.duration_days * ticks_per_day) as usize;
+
+    let mut steps_by_tick = scenario
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(step_idx, step)| ((step.at_day * ticks_per_day) as usize, step_idx, step))
+        .collect::<Vec<_>>();
+    steps_by_tick.sort_by_key(|&(at_tick, step_idx, _)| (at_tick, step_idx));
+    let mut pending_steps = steps_by_tick.into_iter().peekable();
+
+    // a single machine that never connects, so no sockets are opened
+    let mut system = Box::new(kay::ActorSystem::new(kay::Networking::new(
+        0,
+        vec!["localhost:9999".to_owned()],
+        5000,
+        2,
+        5,
+    )));
+
+    setup_all(&mut system);
+
+    util::random::set_world_seed(scenario.seed);
+
+    let world = &mut system.world();
+
+    let (simulation, plan_manager) = super::spawn_world(world);
+    simulation.set_speed(speed, world);
+    system.process_all_messages();
+
+    println!(
+        "HEADLESS: running {} for {} ticks at speed {} with seed {}",
+        scenario_path, total_ticks, speed, scenario.seed
+    );
+
+    let started = ::std::time::Instant::now();
+    let mut current_tick = 0;
+    let mut implemented_steps = 0;
+
+    while current_tick < total_ticks {
+        while pending_steps
+            .peek()
+            .map(|&(at_tick, ..)| at_tick <= current_tick)
+            .unwrap_or(false)
+        {
+            let (_, step_idx, step) = pending_steps.next().expect("just peeked a step");
+            plan_manager.implement_artificial_proposal(
+                step.as_proposal(step_idx),
+                ::compact::CVec::new(),
+                world,
+            );
+            util::profiling::measure("Other message processing", || {
+                system.process_all_messages()
+            });
+            implemented_steps += 1;
+        }
+
+        simulation.progress(world);
+        util::profiling::measure("Other message processing", || system.process_all_messages());
+
+        if system.shutting_down {
+            break;
+        }
+
+        current_tick += speed as usize;
+    }
+
+    if let Some(geojson_path) = arg_matches.value_of("geojson") {
+        let mut compact_geojson_path = ::compact::CString::new();
+        compact_geojson_path.push_str(geojson_path);
+        plan_manager.export_geojson_to_file(compact_geojson_path, world);
+        system.process_all_messages();
+    }
+
+    // the whole run as a single window
+    let maybe_profile_report = util::profiling::finish_frame(::std::time::Duration::new(0, 0));
+
+    let elapsed = started.elapsed();
+    let elapsed_secs = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1.0E9;
+    let (hours, minutes) =
+        simulation::TimeOfDay::from(simulation::Instant::new(current_tick as u64)).hours_minutes();
+
+    println!("HEADLESS SUMMARY:");
+    println!("  scenario steps implemented: {}", implemented_steps);
+    println!(
+        "  simulated: {} ticks ({:.2} days, ending at {:02}:{:02})",
+        current_tick,
+        current_tick as f32 / ticks_per_day,
+        hours,
+        minutes
+    );
+    println!(
+        "  wall time: {:.2}s ({:.0} ticks/s)",
+        elapsed_secs,
+        current_tick as f32 / elapsed_secs.max(0.001)
+    );
+
+    if let Some(profile_report) = maybe_profile_report {
+        println!("{}", profile_report.as_table());
+    }
+}
@@ -12,9 +12,14 @@ extern crate rust_embed_flag;
 struct Asset;
 
 extern crate clap;
-use clap::{Arg, App};
+use clap::{Arg, App, SubCommand};
 
-use kay::Actor;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use kay::{Actor, World};
+use std::sync::{Arc, Mutex};
 use transport::lane::{Lane, SwitchLane};
 use economy::households::family::Family;
 use economy::households::grocery_shop::GroceryShop;
@@ -25,6 +30,11 @@ use economy::households::bakery::Bakery;
 use economy::households::neighboring_town_trade::NeighboringTownTrade;
 use economy::households::tasks::TaskEndScheduler;
 use construction::Construction;
+use journal::InputJournal;
+use simulation::SimulationID;
+use planning::{PlanManager, PlanManagerID};
+
+mod headless;
 
 const VERSION: &str = include_str!("../.version");
 
@@ -80,11 +90,118 @@ fn main() {
                 .value_name("n-turns")
                 .default_value("5")
                 .help("How many network turns to skip if server/client are ahead"),
+        ).arg(
+            Arg::with_name("load")
+                .long("load")
+                .value_name("savegame.json")
+                .help("Savegame to restore the city from at startup"),
+        ).arg(
+            Arg::with_name("savegame")
+                .long("savegame")
+                .value_name("savegame.json")
+                .default_value("savegame.json")
+                .help("Where to save the city to (autosaves and saves from the browser UI)"),
+        ).arg(
+            Arg::with_name("autosave-every")
+                .long("autosave-every")
+                .value_name("sim-hours")
+                .default_value("6")
+                .help("How often to autosave, in simulation hours (0 disables autosave)"),
+        ).arg(
+            Arg::with_name("journal")
+                .long("journal")
+                .value_name("journal.jsonl")
+                .default_value("journal.jsonl")
+                .help("Where to record all inputs to, for reproducing the session with --replay"),
+        ).arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("journal.jsonl")
+                .help("Replays the inputs of a recorded journal instead of recording a new one"),
+        ).arg(
+            Arg::with_name("import-proposal")
+                .long("import-proposal")
+                .value_name("plan.json")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Imports an exported plan as a new proposal at startup (can be repeated)"),
+        ).arg(
+            Arg::with_name("import-osm")
+                .long("import-osm")
+                .value_name("extract.osm")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Imports highways and landuse of an OSM XML extract as a new proposal"),
+        ).arg(
+            Arg::with_name("review-quorum")
+                .long("review-quorum")
+                .value_name("n-approvals")
+                .help("Only implement proposals once this many other players approved them"),
+        ).arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("u64")
+                .help("World seed that makes IDs and all random choices reproducible"),
+        ).arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .help("Measures where simulation time goes and prints a table every window"),
+        ).arg(
+            Arg::with_name("profile-window")
+                .long("profile-window")
+                .value_name("seconds")
+                .default_value("5")
+                .help("How many seconds of frames to aggregate for each profile"),
+        ).subcommand(
+            SubCommand::with_name("headless")
+                .about("Runs a scenario without browser UI or networking and prints a summary")
+                .arg(
+                    Arg::with_name("scenario")
+                        .value_name("scenario.json")
+                        .required(true)
+                        .help("Scenario file with gestures to implement, speed and duration"),
+                ).arg(
+                    Arg::with_name("days")
+                        .long("days")
+                        .value_name("n-days")
+                        .help("Overrides the duration of the scenario in simulation days"),
+                ).arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .value_name("u64")
+                        .help("Overrides the world seed of the scenario"),
+                ).arg(
+                    Arg::with_name("geojson")
+                        .long("geojson")
+                        .value_name("city.geojson")
+                        .help("Exports the implemented city as GeoJSON at the end of the run"),
+                ).arg(
+                    Arg::with_name("profile")
+                        .long("profile")
+                        .help("Prints where simulation time went for the whole run"),
+                ),
         ).get_matches();
 
+    if let Some(headless_matches) = arg_matches.subcommand_matches("headless") {
+        headless::run(headless_matches);
+        return;
+    }
+
     let serve_host_port = arg_matches.value_of("bind").unwrap().to_owned();
     let arg_matches_2 = arg_matches.clone();
 
+    let profiling_enabled = arg_matches.is_present("profile");
+    util::profiling::set_enabled(profiling_enabled);
+    let profile_window = ::std::time::Duration::from_secs(
+        arg_matches
+            .value_of("profile-window")
+            .unwrap()
+            .parse()
+            .expect("--profile-window should be a number of seconds"),
+    );
+    let last_profile_report = Arc::new(Mutex::new(None::<util::profiling::ProfileReport>));
+    let last_profile_report_2 = last_profile_report.clone();
+
     let my_host = format!(
         "{}:{}",
         match arg_matches.value_of("mode").unwrap() {
@@ -127,6 +244,13 @@ fn main() {
                     );
 
                 Response::html(rendered)
+            } else if request.url() == "/profile.json" {
+                if profiling_enabled {
+                    Response::json(&*last_profile_report_2.lock().unwrap())
+                } else {
+                    Response::text("Profiling is disabled, start the server with --profile")
+                        .with_status_code(404)
+                }
             } else if let Some(asset) = Asset::get(&request.url()[1..]) {
                 Response::from_data(
                     if request.url().ends_with(".wasm") {
@@ -169,26 +293,103 @@ fn main() {
 
         system.networking_connect();
 
-        let simulatables = vec![
-            Lane::local_broadcast(world).into(),
-            SwitchLane::local_broadcast(world).into(),
-            Family::local_broadcast(world).into(),
-            GroceryShop::local_broadcast(world).into(),
-            GrainFarm::local_broadcast(world).into(),
-            CowFarm::local_broadcast(world).into(),
-            Mill::local_broadcast(world).into(),
-            Bakery::local_broadcast(world).into(),
-            NeighboringTownTrade::local_broadcast(world).into(),
-            TaskEndScheduler::local_first(world).into(),
-            Construction::global_first(world).into(),
-        ];
-        let simulation = simulation::spawn(world, simulatables);
-        util::init::set_error_hook();
-
-        let plan_manager = planning::spawn(world);
-        construction::spawn(world);
-        transport::spawn(world, simulation);
-        economy::spawn(world, simulation, plan_manager);
+        let replay_entries = arg_matches.value_of("replay").map(|replay_path| {
+            journal::read_from(replay_path).unwrap_or_else(|err| {
+                println!("Couldn't replay {}: {}", replay_path, err);
+                ::std::process::exit(1);
+            })
+        });
+
+        let world_seed = arg_matches
+            .value_of("seed")
+            .map(|seed| seed.parse().expect("--seed should be a number"))
+            .or_else(|| {
+                replay_entries
+                    .as_ref()
+                    .and_then(|entries| journal::recorded_world_seed(entries))
+            }).unwrap_or_else(util::random::random_world_seed);
+        util::random::set_world_seed(world_seed);
+
+        let (simulation, plan_manager) = spawn_world(world);
+
+        if let Some(load_path) = arg_matches.value_of("load") {
+            match savegame::Savegame::read_from(load_path) {
+                Ok(savegame) => {
+                    println!("Loading city from {}", load_path);
+                    // let the freshly spawned world settle, so the savegame replaces its state
+                    system.process_all_messages();
+                    savegame.restore(simulation, plan_manager, world);
+                }
+                Err(err) => {
+                    println!("Couldn't load {}: {}", load_path, err);
+                    ::std::process::exit(1);
+                }
+            }
+        }
+
+        // a loaded savegame brings its own world seed
+        println!("World seed: {}", util::random::world_seed());
+
+        let journal = InputJournal::global_first(world);
+        let recorded_journal_path = if let Some(entries) = replay_entries {
+            journal.replay(entries.into(), world);
+            None
+        } else {
+            let journal_path = arg_matches.value_of("journal").unwrap();
+            let mut compact_journal_path = ::compact::CString::new();
+            compact_journal_path.push_str(journal_path);
+            journal.record_to(compact_journal_path, world);
+            journal.record(
+                journal::JournaledInput::WorldSeed {
+                    seed: util::random::world_seed(),
+                },
+                world,
+            );
+            Some(journal_path.to_owned())
+        };
+
+        for plan_path in arg_matches.values_of("import-proposal").into_iter().flatten() {
+            let mut compact_plan_path = ::compact::CString::new();
+            compact_plan_path.push_str(plan_path);
+            plan_manager.import_proposal_from_file(compact_plan_path, world);
+        }
+
+        for osm_path in arg_matches.values_of("import-osm").into_iter().flatten() {
+            let mut compact_osm_path = ::compact::CString::new();
+            compact_osm_path.push_str(osm_path);
+            plan_manager.import_osm_from_file(compact_osm_path, world);
+        }
+
+        if let Some(quorum) = arg_matches.value_of("review-quorum") {
+            let quorum: u8 = quorum.parse().expect("--review-quorum should be a number");
+            assert!(quorum >= 1, "--review-quorum should be at least 1");
+            plan_manager.set_governance(
+                planning::review::GovernanceSettings {
+                    enabled: true,
+                    quorum,
+                },
+                world,
+            );
+        }
+
+        let autosave_every_hours: usize = arg_matches
+            .value_of("autosave-every")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let savegame_manager = savegame::spawn(
+            world,
+            simulation,
+            plan_manager,
+            arg_matches.value_of("savegame").unwrap(),
+            if autosave_every_hours == 0 {
+                None
+            } else {
+                Some(simulation::Duration::from_hours(autosave_every_hours))
+            },
+        );
+
+        util::init::set_error_hook(recorded_journal_path);
         system.process_all_messages();
 
         let mut frame_counter = util::init::FrameCounter::new();
@@ -197,7 +398,9 @@ fn main() {
         loop {
             frame_counter.start_frame();
 
-            system.process_all_messages();
+            util::profiling::measure("Other message processing", || {
+                system.process_all_messages()
+            });
 
             if system.shutting_down {
                 break;
@@ -206,12 +409,24 @@ fn main() {
             if skip_turns == 0 {
                 simulation.progress(world);
 
-                system.process_all_messages();
+                util::profiling::measure("Other message processing", || {
+                    system.process_all_messages()
+                });
             }
 
-            system.networking_send_and_receive();
+            util::profiling::measure("Networking", || system.networking_send_and_receive());
 
-            system.process_all_messages();
+            util::profiling::measure("Other message processing", || {
+                system.process_all_messages()
+            });
+
+            let nothing_queued = system.get_queue_lengths().values().all(|length| *length == 0);
+            savegame_manager.all_messages_processed(nothing_queued, world);
+
+            if let Some(report) = util::profiling::finish_frame(profile_window) {
+                println!("{}", report.as_table());
+                *last_profile_report.lock().unwrap() = Some(report);
+            }
 
             if skip_turns > 0 {
                 skip_turns -= 1;
@@ -228,3 +443,28 @@ fn main() {
         }
     });
 }
+
+pub fn spawn_world(world: &mut World) -> (SimulationID, PlanManagerID) {
+    let simulatables = vec![
+        Lane::local_broadcast(world).into(),
+        SwitchLane::local_broadcast(world).into(),
+        Family::local_broadcast(world).into(),
+        GroceryShop::local_broadcast(world).into(),
+        GrainFarm::local_broadcast(world).into(),
+        CowFarm::local_broadcast(world).into(),
+        Mill::local_broadcast(world).into(),
+        Bakery::local_broadcast(world).into(),
+        NeighboringTownTrade::local_broadcast(world).into(),
+        TaskEndScheduler::local_first(world).into(),
+        Construction::global_first(world).into(),
+        InputJournal::global_first(world).into(),
+        PlanManager::global_first(world).into(),
+    ];
+    let simulation = simulation::spawn(world, simulatables);
+    let plan_manager = planning::spawn(world);
+    journal::spawn(world, simulation, plan_manager);
+    construction::spawn(world);
+    transport::spawn(world, simulation);
+    economy::spawn(world, simulation, plan_manager);
+    (simulation, plan_manager)
+}